@@ -0,0 +1,44 @@
+use cgmath::Vector2;
+use context::Context;
+use core::victory::BattleOutcome;
+use std::collections::HashMap;
+use types::ScreenPos;
+use ui::button::{Button, ButtonId, ButtonManager};
+use ui::gui::Gui;
+use ui::screen::{ScreenCommand, ScreenType};
+
+pub fn aftermath(context: &mut Context, outcome: &BattleOutcome) -> Gui {
+    let mut button_manager = ButtonManager::new();
+    let mut callbacks: HashMap<ButtonId, Box<dyn Fn(&mut Context) -> ()>> = HashMap::new();
+    let mut lines = vec![match outcome.winner {
+        Some(team) => format!("[team {} wins]", team.id),
+        None => "[draw]".to_string(),
+    }];
+    let seconds = outcome.duration as u32;
+    lines.push(format!("[lasted {}:{:02}]", seconds / 60, seconds % 60));
+    let mut teams: Vec<_> = outcome.casualties.keys().cloned().collect();
+    teams.sort();
+    for team in teams {
+        lines.push(format!(
+            "[team {} lost {}]",
+            team.id,
+            outcome.casualties_of(team)
+        ));
+    }
+    let mut button_pos = ScreenPos {
+        v: Vector2 { x: 10, y: 10 },
+    };
+    for line in &lines {
+        let id = button_manager.add_button(Button::new(context, line, button_pos));
+        let vstep = button_manager.buttons()[&id].size().h;
+        button_pos.v.y += (vstep as f32 * 1.5) as i32;
+    }
+    let button_menu_id = button_manager.add_button(Button::new(context, "[main menu]", button_pos));
+    let call: Box<dyn Fn(&mut Context) -> ()> = Box::new(back_to_menu);
+    callbacks.insert(button_menu_id, call);
+    Gui::new_from_buttons(button_manager, callbacks)
+}
+
+fn back_to_menu(context: &mut Context) {
+    context.add_command(ScreenCommand::ChangeScreen(ScreenType::Menu));
+}
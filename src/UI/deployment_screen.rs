@@ -0,0 +1,56 @@
+use cgmath::Vector2;
+use context::Context;
+use core::deployment::Deployment;
+use std::collections::HashMap;
+use types::ScreenPos;
+use ui::button::{Button, ButtonId, ButtonManager};
+use ui::gui::Gui;
+use ui::screen::{ScreenCommand, ScreenType};
+
+pub fn deployment_screen(context: &mut Context, deployment: &Deployment) -> Gui {
+    let mut button_manager = ButtonManager::new();
+    let mut callbacks: HashMap<ButtonId, Box<dyn Fn(&mut Context) -> ()>> = HashMap::new();
+    let mut button_pos = ScreenPos {
+        v: Vector2 { x: 10, y: 10 },
+    };
+    let button_auto_id =
+        button_manager.add_button(Button::new_small(context, "[auto deploy]", button_pos));
+    let call: Box<dyn Fn(&mut Context) -> ()> = Box::new(auto_deploy);
+    callbacks.insert(button_auto_id, call);
+    button_pos.v.x += button_manager.buttons()[&button_auto_id].size().w + 10;
+    let button_ready_id =
+        button_manager.add_button(Button::new_small(context, "[ready]", button_pos));
+    let call: Box<dyn Fn(&mut Context) -> ()> = Box::new(ready);
+    callbacks.insert(button_ready_id, call);
+    button_pos.v.x += button_manager.buttons()[&button_ready_id].size().w + 10;
+    let button_menu_id =
+        button_manager.add_button(Button::new_small(context, "[main menu]", button_pos));
+    let call: Box<dyn Fn(&mut Context) -> ()> = Box::new(back_to_menu);
+    callbacks.insert(button_menu_id, call);
+    if let Some(player_id) = deployment.player() {
+        let player = deployment
+            .battlefield()
+            .get_player(&player_id)
+            .expect("Bad player id");
+        button_pos.v.x = 10;
+        button_pos.v.y += (button_manager.buttons()[&button_menu_id].size().h as f32 * 1.5) as i32;
+        let line = format!(
+            "[{} deploys: click a company, then its new place]",
+            player.name
+        );
+        let _ = button_manager.add_button(Button::new_small(context, &line, button_pos));
+    }
+    Gui::new_from_buttons(button_manager, callbacks)
+}
+
+fn auto_deploy(context: &mut Context) {
+    context.add_command(ScreenCommand::AutoDeploy);
+}
+
+fn ready(context: &mut Context) {
+    context.add_command(ScreenCommand::DeploymentReady);
+}
+
+fn back_to_menu(context: &mut Context) {
+    context.add_command(ScreenCommand::ChangeScreen(ScreenType::Menu));
+}
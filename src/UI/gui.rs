@@ -1,10 +1,14 @@
 use cgmath::{ortho, Matrix4};
 use context::Context;
+use core::company::CompId;
 use glutin::{self, MouseButton, VirtualKeyCode, WindowEvent};
 use std::collections::HashMap;
 use types::Size2;
-use ui::button::{ButtonId, ButtonManager};
+use ui::aftermath_screen;
+use ui::button::{Button, ButtonId, ButtonManager};
+use ui::deployment_screen;
 use ui::main_menu_screen;
+use ui::replay_screen;
 use ui::screen::{EventStatus, ScreenCommand, ScreenType};
 use ui::tactical_screen;
 use GameState;
@@ -13,13 +17,22 @@ use GameState;
 pub struct Gui {
     button_manager: ButtonManager,
     callbacks: HashMap<ButtonId, Box<dyn Fn(&mut Context) -> ()>>,
+    /// The button showing the card of each company, on the tactical screen
+    cards: HashMap<CompId, ButtonId>,
 }
 
 impl Gui {
     pub fn new(context: &mut Context, gamestate: &GameState) -> Gui {
         match *gamestate {
             GameState::Menu => main_menu_screen::main_menu(context),
-            GameState::Battle(_) => tactical_screen::tactical_screen(context),
+            GameState::Deployment(ref deployment) => {
+                deployment_screen::deployment_screen(context, deployment)
+            }
+            GameState::Battle(ref battlefield) => {
+                tactical_screen::tactical_screen(context, battlefield)
+            }
+            GameState::Aftermath(ref outcome) => aftermath_screen::aftermath(context, outcome),
+            GameState::Replay(_) => replay_screen::replay_screen(context),
         }
     }
 
@@ -30,6 +43,20 @@ impl Gui {
         Gui {
             button_manager,
             callbacks,
+            cards: HashMap::new(),
+        }
+    }
+
+    pub fn set_card(&mut self, comp_id: CompId, button_id: ButtonId) {
+        self.cards.insert(comp_id, button_id);
+    }
+
+    /// Puts `label` on the card of `comp_id`, if it has one.
+    pub fn update_card(&mut self, context: &mut Context, comp_id: CompId, label: &str) {
+        if let Some(&button_id) = self.cards.get(&comp_id) {
+            let pos = self.button_manager.buttons()[&button_id].pos();
+            let button = Button::new_small(context, label, pos);
+            self.button_manager.buttons_mut().insert(button_id, button);
         }
     }
     pub fn draw(&self, context: &mut Context) {
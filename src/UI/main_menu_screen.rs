@@ -22,11 +22,26 @@ pub fn main_menu(context: &mut Context) -> Gui {
     let vstep = button_manager.buttons()[&button_start_hotseat_id].size().h;
     let vstep = (vstep as f32 * 1.5) as i32;
     button_pos.v.y += vstep;
-    let _button_start_vs_ai_id =
-        button_manager.add_button(Button::new(context, "[start nothing]", button_pos));
+    let button_start_vs_ai_id =
+        button_manager.add_button(Button::new(context, "[start vs ai]", button_pos));
+    let call: Box<dyn Fn(&mut Context) -> ()> = Box::new(start_battle_vs_ai);
+    callbacks.insert(button_start_vs_ai_id, call);
+    button_pos.v.y += vstep;
+    let button_replay_id =
+        button_manager.add_button(Button::new(context, "[watch last battle]", button_pos));
+    let call: Box<dyn Fn(&mut Context) -> ()> = Box::new(watch_replay);
+    callbacks.insert(button_replay_id, call);
     Gui::new_from_buttons(button_manager, callbacks)
 }
 
 fn start_battle(context: &mut Context) {
     context.add_command(ScreenCommand::ChangeScreen(ScreenType::Battle));
 }
+
+fn start_battle_vs_ai(context: &mut Context) {
+    context.add_command(ScreenCommand::ChangeScreen(ScreenType::BattleVsAi));
+}
+
+fn watch_replay(context: &mut Context) {
+    context.add_command(ScreenCommand::WatchReplay);
+}
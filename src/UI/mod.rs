@@ -1,6 +1,9 @@
+pub mod aftermath_screen;
 pub mod button;
+pub mod deployment_screen;
 pub mod gui;
 pub mod main_menu_screen;
+pub mod replay_screen;
 pub mod screen;
 pub mod tactical_screen;
 pub mod text;
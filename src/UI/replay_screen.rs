@@ -0,0 +1,39 @@
+use cgmath::Vector2;
+use context::Context;
+use std::collections::HashMap;
+use types::ScreenPos;
+use ui::button::{Button, ButtonId, ButtonManager};
+use ui::gui::Gui;
+use ui::screen::{PlaybackCommand, ScreenCommand, ScreenType};
+
+pub fn replay_screen(context: &mut Context) -> Gui {
+    let mut button_manager = ButtonManager::new();
+    let mut callbacks: HashMap<ButtonId, Box<dyn Fn(&mut Context) -> ()>> = HashMap::new();
+    let mut button_pos = ScreenPos {
+        v: Vector2 { x: 10, y: 10 },
+    };
+    let buttons = [
+        ("[-10s]", PlaybackCommand::SkipBack),
+        ("[pause]", PlaybackCommand::TogglePause),
+        ("[slower]", PlaybackCommand::SlowDown),
+        ("[faster]", PlaybackCommand::SpeedUp),
+        ("[+10s]", PlaybackCommand::SkipForward),
+    ];
+    for &(label, command) in &buttons {
+        let id = button_manager.add_button(Button::new_small(context, label, button_pos));
+        let call: Box<dyn Fn(&mut Context) -> ()> = Box::new(move |context: &mut Context| {
+            context.add_command(ScreenCommand::ControlPlayback(command));
+        });
+        callbacks.insert(id, call);
+        button_pos.v.x += button_manager.buttons()[&id].size().w + 10;
+    }
+    let button_menu_id =
+        button_manager.add_button(Button::new_small(context, "[main menu]", button_pos));
+    let call: Box<dyn Fn(&mut Context) -> ()> = Box::new(back_to_menu);
+    callbacks.insert(button_menu_id, call);
+    Gui::new_from_buttons(button_manager, callbacks)
+}
+
+fn back_to_menu(context: &mut Context) {
+    context.add_command(ScreenCommand::ChangeScreen(ScreenType::Menu));
+}
@@ -1,4 +1,5 @@
 use context::Context;
+use core::position::Position;
 use glutin::WindowEvent;
 
 #[allow(dead_code)]
@@ -6,6 +7,25 @@ pub enum ScreenCommand {
     ChangeScreen(ScreenType),
     PopPopup,
     PushPopup(Box<Screen>),
+    SaveBattle,
+    LoadBattle,
+    /// Watch the replay of the last battle
+    WatchReplay,
+    ControlPlayback(PlaybackCommand),
+    /// The ground was clicked at this position
+    MapClick(Position),
+    AutoDeploy,
+    /// The player deploying is done
+    DeploymentReady,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlaybackCommand {
+    TogglePause,
+    SlowDown,
+    SpeedUp,
+    SkipBack,
+    SkipForward,
 }
 
 #[allow(dead_code)]
@@ -13,6 +33,8 @@ pub enum ScreenCommand {
 pub enum ScreenType {
     Menu,
     Battle,
+    /// A gate assault against the computer
+    BattleVsAi,
     ShuttingDown,
 }
 
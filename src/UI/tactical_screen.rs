@@ -1,8 +1,77 @@
+use cgmath::Vector2;
 use context::Context;
+use core::battlefield::Battlefield;
+use core::company::CompId;
+use core::player::Controller;
 use std::collections::HashMap;
-use ui::button::ButtonManager;
+use types::ScreenPos;
+use ui::button::{Button, ButtonId, ButtonManager};
 use ui::gui::Gui;
+use ui::screen::ScreenCommand;
 
-pub fn tactical_screen(_context: &mut Context) -> Gui {
-    Gui::new_from_buttons(ButtonManager::new(), HashMap::new())
+pub fn tactical_screen(context: &mut Context, battlefield: &Battlefield) -> Gui {
+    let mut button_manager = ButtonManager::new();
+    let mut callbacks: HashMap<ButtonId, Box<dyn Fn(&mut Context) -> ()>> = HashMap::new();
+    let mut button_pos = ScreenPos {
+        v: Vector2 { x: 10, y: 10 },
+    };
+    let button_save_id =
+        button_manager.add_button(Button::new_small(context, "[save]", button_pos));
+    let call: Box<dyn Fn(&mut Context) -> ()> = Box::new(save_battle);
+    callbacks.insert(button_save_id, call);
+    button_pos.v.x += button_manager.buttons()[&button_save_id].size().w + 10;
+    let button_load_id =
+        button_manager.add_button(Button::new_small(context, "[load]", button_pos));
+    let call: Box<dyn Fn(&mut Context) -> ()> = Box::new(load_battle);
+    callbacks.insert(button_load_id, call);
+    button_pos.v.x = 10;
+    let vstep = button_manager.buttons()[&button_save_id].size().h;
+    let mut cards = Vec::new();
+    for comp_id in carded_companies(battlefield) {
+        button_pos.v.y += (vstep as f32 * 1.5) as i32;
+        let label = company_card(battlefield, comp_id);
+        let button_id = button_manager.add_button(Button::new_small(context, &label, button_pos));
+        cards.push((comp_id, button_id));
+    }
+    let mut gui = Gui::new_from_buttons(button_manager, callbacks);
+    for (comp_id, button_id) in cards {
+        gui.set_card(comp_id, button_id);
+    }
+    gui
+}
+
+/// The companies of the human players, which get a card.
+fn carded_companies(battlefield: &Battlefield) -> Vec<CompId> {
+    let mut comp_ids: Vec<CompId> = battlefield
+        .get_company_iter()
+        .filter(|&(_, company)| {
+            let player = battlefield
+                .get_player(&company.player_id)
+                .expect("Bad player id");
+            player.controller == Controller::Human
+        })
+        .map(|(&comp_id, _)| comp_id)
+        .collect();
+    comp_ids.sort();
+    comp_ids
+}
+
+/// The card of a company: its unit type, strength and rank.
+pub fn company_card(battlefield: &Battlefield, comp_id: CompId) -> String {
+    let company = battlefield.get_company(&comp_id).expect("Bad company id");
+    let indiv = battlefield.get_indiv(&company.members[0]).unwrap();
+    format!(
+        "[{} x{} {}]",
+        battlefield.get_unit_type(indiv.type_id).name,
+        company.members.len(),
+        battlefield.company_rank(comp_id).name()
+    )
+}
+
+fn save_battle(context: &mut Context) {
+    context.add_command(ScreenCommand::SaveBattle);
+}
+
+fn load_battle(context: &mut Context) {
+    context.add_command(ScreenCommand::LoadBattle);
 }
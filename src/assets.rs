@@ -0,0 +1,310 @@
+//! Loading the data of the game from the assets folder, and saving and loading battles and
+//! replays. Nothing in here needs a window.
+
+use core::battlefield::Battlefield;
+use core::fatigue::FatigueRules;
+use core::replay::Replay;
+use core::save::{self, SaveError};
+use core::scenario::{Scenario, ScenarioError};
+use core::terrain::Terrain;
+use core::unit::UnitType;
+use core::visibility::DEFAULT_SIGHT_RANGE;
+use image;
+use serde_json;
+use std::collections::HashMap;
+use std::fs as std_fs;
+use std::io::{BufReader, BufWriter, Cursor};
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use types::Size2;
+
+pub fn load_as_string<P: AsRef<Path>>(path: P) -> String {
+    String::from_utf8(load(path).into_inner()).unwrap()
+}
+
+/// Loads the data of all units, without their meshes. The index of a unit in the result is its
+/// `UnitTypeId`, matching the `MeshId` given out by `load_all_units`.
+pub fn load_all_unit_types() -> Result<Vec<UnitType>, ScenarioError> {
+    let mut units: Vec<UnitType> = Vec::new();
+    for path in unit_dirs()? {
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        // Skip the same units `load_all_units` skips, so the ids keep matching
+        if !(has_file_with_ext(&path, "obj") && has_file_with_ext(&path, "png")) {
+            continue;
+        }
+        units.push(load_unit_data(&path, name)?);
+    }
+    Ok(units)
+}
+
+pub fn has_file_with_ext<P: AsRef<Path>>(path: &P, ext: &str) -> bool {
+    std_fs::read_dir(path)
+        .unwrap()
+        .any(|file| file.unwrap().path().extension().is_some_and(|e| e == ext))
+}
+
+/// Every folder in the units folder is the definition of a unit. Sorted, so the units always get
+/// the same ids. Fails if there is no units folder, e.g. when not run from the game's folder.
+pub fn unit_dirs() -> Result<Vec<PathBuf>, ScenarioError> {
+    let dir = PathBuf::from("./assets/units/");
+    let entries = std_fs::read_dir(&dir).map_err(|err| ScenarioError::Read {
+        path: dir.display().to_string(),
+        reason: err.to_string(),
+    })?;
+    let mut dirs: Vec<PathBuf> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Reads a JSON list of unit types instead of the units folder, from `path` relative to the
+/// working directory. The index of a unit in the list is its `UnitTypeId`.
+pub fn load_unit_types<P: AsRef<Path>>(path: P) -> Result<Vec<UnitType>, ScenarioError> {
+    let path = path.as_ref();
+    let json = std_fs::read_to_string(path).map_err(|err| ScenarioError::Read {
+        path: path.display().to_string(),
+        reason: err.to_string(),
+    })?;
+    serde_json::from_str(&json)
+        .map_err(|err| ScenarioError::Parse(format!("{}: {}", path.display(), err)))
+}
+
+/// Loads a greyscale png as terrain. Every pixel is a height sample, `spacing` m from its
+/// neighbours. Black is at 0 m, white at `max_height` m.
+pub fn load_heightmap<P: AsRef<Path>>(path: P, spacing: f64, max_height: f64) -> Terrain {
+    let img = image::load(load(path), image::PNG).unwrap().to_luma();
+    let (w, h) = img.dimensions();
+    let heights = img
+        .pixels()
+        .map(|pixel| pixel.data[0] as f64 / 255.0 * max_height)
+        .collect();
+    let samples = Size2 {
+        w: w as i32,
+        h: h as i32,
+    };
+    Terrain::from_heights(samples, spacing, heights)
+}
+
+/// How soldiers tire, see `core::fatigue`.
+const FATIGUE_RULES: &str = "fatigue.json";
+
+/// Loads a scenario file and sets up its battle. Paths start from the assets folder, see
+/// `Scenario::build` for `seed`.
+pub fn load_scenario<P: AsRef<Path>>(
+    path: P,
+    unit_types: Vec<UnitType>,
+    seed: u64,
+) -> Result<Battlefield, ScenarioError> {
+    let json = read_asset(path.as_ref())?;
+    let scenario = Scenario::from_json(&json)?;
+    let fatigue_rules = FatigueRules::from_json(&read_asset(Path::new(FATIGUE_RULES))?)
+        .map_err(|err| ScenarioError::Parse(format!("{}: {}", FATIGUE_RULES, err)))?;
+    let mut battlefield = scenario.build(unit_types, seed, |file, spacing, max_height| {
+        let path = Path::new("assets").join(file);
+        if !path.is_file() {
+            return Err(ScenarioError::Read {
+                path: path.display().to_string(),
+                reason: "no such file".to_string(),
+            });
+        }
+        Ok(load_heightmap(file, spacing, max_height))
+    })?;
+    battlefield.set_fatigue_rules(fatigue_rules);
+    Ok(battlefield)
+}
+
+/// Saves `battlefield` to `path`, relative to the working directory rather than the assets.
+pub fn save_battle<P: AsRef<Path>>(path: P, battlefield: &Battlefield) -> Result<(), SaveError> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        std_fs::create_dir_all(dir).map_err(|err| SaveError::Io(err.to_string()))?;
+    }
+    let file = std_fs::File::create(path).map_err(|err| SaveError::Io(err.to_string()))?;
+    save::write_battle(&mut BufWriter::new(file), battlefield)
+}
+
+pub fn load_battle<P: AsRef<Path>>(path: P) -> Result<Battlefield, SaveError> {
+    let file = std_fs::File::open(path).map_err(|err| SaveError::Io(err.to_string()))?;
+    save::read_battle(&mut BufReader::new(file))
+}
+
+/// Saves `replay` to `path`, like `save_battle`.
+pub fn save_replay<P: AsRef<Path>>(path: P, replay: &Replay) -> Result<(), SaveError> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        std_fs::create_dir_all(dir).map_err(|err| SaveError::Io(err.to_string()))?;
+    }
+    let file = std_fs::File::create(path).map_err(|err| SaveError::Io(err.to_string()))?;
+    save::write_replay(&mut BufWriter::new(file), replay)
+}
+
+pub fn load_replay<P: AsRef<Path>>(path: P) -> Result<Replay, SaveError> {
+    let file = std_fs::File::open(path).map_err(|err| SaveError::Io(err.to_string()))?;
+    save::read_replay(&mut BufReader::new(file))
+}
+
+/// Reads a text file from the assets folder, without panicking when it isn't there.
+fn read_asset(path: &Path) -> Result<String, ScenarioError> {
+    let path = Path::new("assets").join(path);
+    std_fs::read_to_string(&path).map_err(|err| ScenarioError::Read {
+        path: path.display().to_string(),
+        reason: err.to_string(),
+    })
+}
+
+/// Loads any file. Path starts from the assets folder
+pub fn load<P: AsRef<Path>>(path: P) -> Cursor<Vec<u8>> {
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut buf = Vec::new();
+    let fullpathwithassets = &Path::new("assets").join(&path);
+    let mut fullpath = &Path::new("").join(&path);
+    if !(fullpath.starts_with("assets")
+        || fullpath.starts_with("/assets")
+        || fullpath.starts_with("./assets"))
+    {
+        fullpath = fullpathwithassets;
+    }
+    let mut file = match File::open(fullpath) {
+        Ok(file) => file,
+        Err(err) => {
+            panic!("Can`t open file '{}' ({})", fullpath.display(), err);
+        }
+    };
+    match file.read_to_end(&mut buf) {
+        Ok(_) => Cursor::new(buf),
+        Err(err) => {
+            panic!("Can`t read file '{}' ({})", fullpath.display(), err);
+        }
+    }
+}
+
+/// Load the data (not the meshes) of a single unit from the .txt file in its folder. The unit is
+/// named after the folder, see `CompanyDesc::unit_type`.
+pub fn load_unit_data<P: AsRef<Path>>(path: &P, name: String) -> Result<UnitType, ScenarioError> {
+    let path = path.as_ref();
+    let file = std_fs::read_dir(path)
+        .map_err(|err| err.to_string())
+        .and_then(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .find(|file| file.extension().is_some_and(|ext| ext == "txt"))
+                .ok_or_else(|| "no .txt file".to_string())
+        })
+        .map_err(|reason| ScenarioError::Read {
+            path: path.display().to_string(),
+            reason,
+        })?;
+    let text = std_fs::read_to_string(&file).map_err(|err| ScenarioError::Read {
+        path: file.display().to_string(),
+        reason: err.to_string(),
+    })?;
+    parse_unit_data(&text, name)
+        .map_err(|err| ScenarioError::Parse(format!("{}: {}", file.display(), err)))
+}
+
+/// Reads the fields of a unit file: `key: value` pairs, separated by commas. `sight_range` may
+/// be left out, `name` is only there for whoever reads the file.
+fn parse_unit_data(text: &str, name: String) -> Result<UnitType, String> {
+    let mut fields = HashMap::new();
+    for field in text
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+    {
+        let mut parts = field.splitn(2, ':');
+        let key = parts.next().unwrap_or("").trim();
+        let value = parts
+            .next()
+            .ok_or_else(|| format!("Field '{}' has no value", key))?
+            .trim();
+        if fields.insert(key, value).is_some() {
+            return Err(format!("Field '{}' is given twice", key));
+        }
+    }
+    Ok(UnitType {
+        name,
+        count: unit_field(&fields, "count")?,
+        size: unit_field(&fields, "size")?,
+        hp: unit_field(&fields, "hp")?,
+        defence_skill: unit_field(&fields, "defence_skill")?,
+        armor: unit_field(&fields, "armor")?,
+        shield: unit_field(&fields, "shield")?,
+        attack_skill: unit_field(&fields, "attack_skill")?,
+        weapon_type: unit_field(&fields, "weapon_type")?,
+        morale: unit_field(&fields, "morale")?,
+        speed: unit_field(&fields, "speed")?,
+        cost_recruit: unit_field(&fields, "cost_recruit")?,
+        cost_upkeep: unit_field(&fields, "cost_upkeep")?,
+        sight_range: match fields.get("sight_range") {
+            Some(_) => unit_field(&fields, "sight_range")?,
+            None => DEFAULT_SIGHT_RANGE,
+        },
+    })
+}
+
+fn unit_field<T: FromStr>(fields: &HashMap<&str, &str>, key: &str) -> Result<T, String> {
+    let value = fields
+        .get(key)
+        .ok_or_else(|| format!("Field '{}' is missing", key))?;
+    value
+        .parse()
+        .map_err(|_| format!("Field '{}' has a bad value '{}'", key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_all_unit_types, load_scenario, load_unit_data};
+    use core::player::PlayerId;
+    use core::scenario::ScenarioError;
+    use core::weapon::WeaponType;
+    use std::env;
+    use std::fs as std_fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_unit_file_is_read() {
+        let text = std_fs::read_to_string("assets/units/legionair/legionair.txt").unwrap();
+        let dir = env::temp_dir().join("at_the_gates_test_unit");
+        std_fs::create_dir_all(&dir).unwrap();
+        let edited = text
+            .replace("attack_skill: 6", "attack_skill: 11")
+            .replace("weapon_type: Thrown", "weapon_type: Sling")
+            .replace("morale: 8", "morale: 5");
+        assert_ne!(edited, text);
+        std_fs::write(dir.join("unit.txt"), edited).unwrap();
+        let unit = load_unit_data(&dir, "edited".to_string()).unwrap();
+        assert_eq!(unit.name, "edited");
+        assert_eq!(unit.attack_skill, 11);
+        assert_eq!(unit.weapon_type, WeaponType::Sling);
+        assert_eq!(unit.morale, 5);
+        assert_eq!((unit.count, unit.defence_skill, unit.speed), (80, 6, 10));
+        std_fs::write(dir.join("unit.txt"), text.replace("hp: 3", "hp: many")).unwrap();
+        match load_unit_data(&dir, "broken".to_string()) {
+            Err(ScenarioError::Parse(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        std_fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bundled_scenarios_deploy() {
+        for entry in std_fs::read_dir("assets/scenarios").unwrap() {
+            let file = entry.unwrap().file_name();
+            let path = Path::new("scenarios").join(&file);
+            let mut bf = load_scenario(&path, load_all_unit_types().unwrap(), 0)
+                .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+            let mut player_ids: Vec<PlayerId> = bf.get_player_iter().map(|(&id, _)| id).collect();
+            player_ids.sort();
+            for player_id in player_ids {
+                if let Err(err) = bf.auto_deploy(player_id) {
+                    panic!("{}, player {}: {}", path.display(), player_id.id, err);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Fights every unit type against every other one and prints how they fared, for balancing.
+//!
+//! `matchups [--runs N] [--seed N] [--units FILE] [--format csv|markdown] [--out FILE]`
+//!
+//! The unit types are loaded like in the game, or from a JSON list with `--units`. Each pair
+//! fights `runs` duels, see `core::matchup`.
+
+extern crate at_the_gates;
+
+use at_the_gates::assets;
+use at_the_gates::core::matchup::MatchupMatrix;
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str =
+    "Usage: matchups [--runs N] [--seed N] [--units FILE] [--format csv|markdown] [--out FILE]";
+
+/// Duels per pair of unit types when not given
+const DEFAULT_RUNS: u32 = 20;
+
+enum Format {
+    Csv,
+    Markdown,
+}
+
+struct Args {
+    runs: u32,
+    seed: u64,
+    units: Option<String>,
+    format: Format,
+    out: Option<String>,
+}
+
+fn main() {
+    let args = parse_args(env::args().skip(1).collect()).unwrap_or_else(|err| fail(&err));
+    let unit_types = match args.units {
+        Some(ref path) => assets::load_unit_types(path),
+        None => assets::load_all_unit_types(),
+    }
+    .unwrap_or_else(|err| fail(&format!("Can`t load units: {}", err)));
+    let matrix = MatchupMatrix::run(&unit_types, args.runs, args.seed);
+    let text = match args.format {
+        Format::Csv => matrix.to_csv(),
+        Format::Markdown => matrix.to_markdown(),
+    };
+    match args.out {
+        Some(ref path) => {
+            if let Err(err) = fs::write(path, text) {
+                fail(&format!("Can`t write '{}': {}", path, err));
+            }
+        }
+        None => print!("{}", text),
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Args, String> {
+    let mut args = args.into_iter();
+    let mut parsed = Args {
+        runs: DEFAULT_RUNS,
+        seed: 0,
+        units: None,
+        format: Format::Markdown,
+        out: None,
+    };
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}\n{}", flag, USAGE))?;
+        let bad_value = || format!("Bad value for {}: {}", flag, value);
+        match flag.as_str() {
+            "--runs" => parsed.runs = value.parse().map_err(|_| bad_value())?,
+            "--seed" => parsed.seed = value.parse().map_err(|_| bad_value())?,
+            "--units" => parsed.units = Some(value),
+            "--format" => {
+                parsed.format = match value.as_str() {
+                    "csv" => Format::Csv,
+                    "markdown" | "md" => Format::Markdown,
+                    _ => return Err(bad_value()),
+                }
+            }
+            "--out" => parsed.out = Some(value),
+            _ => return Err(format!("Unknown option {}\n{}", flag, USAGE)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
@@ -0,0 +1,93 @@
+//! Fights out battles without a window and reports how they went, one JSON line per battle.
+//!
+//! `simulate <scenario> [--runs N] [--seed N] [--max-time S] [--units FILE] [--out FILE]`
+//!
+//! The scenario is found in the assets folder, like in the game. Run `i` is seeded with
+//! `seed + i`, unless the scenario sets its own seed. `--units` reads the unit types from a
+//! JSON list instead of the units folder.
+
+extern crate at_the_gates;
+extern crate serde_json;
+
+use at_the_gates::assets;
+use at_the_gates::core::simulation::{self, DEFAULT_MAX_DURATION};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+struct Args {
+    scenario: String,
+    runs: u64,
+    seed: u64,
+    max_duration: f64,
+    units: Option<String>,
+    out: Option<String>,
+}
+
+const USAGE: &str =
+    "Usage: simulate <scenario> [--runs N] [--seed N] [--max-time S] [--units FILE] [--out FILE]";
+
+fn main() {
+    let args = parse_args(env::args().skip(1).collect()).unwrap_or_else(|err| fail(&err));
+    let unit_types = match args.units {
+        Some(ref path) => assets::load_unit_types(path),
+        None => assets::load_all_unit_types(),
+    }
+    .unwrap_or_else(|err| fail(&format!("Can`t load units: {}", err)));
+    let mut out: Box<dyn Write> = match args.out {
+        Some(ref path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(err) => fail(&format!("Can`t create '{}': {}", path, err)),
+        },
+        None => Box::new(io::stdout()),
+    };
+    for run in 0..args.runs {
+        let battlefield =
+            match assets::load_scenario(&args.scenario, unit_types.clone(), args.seed + run) {
+                Ok(battlefield) => battlefield,
+                Err(err) => fail(&format!("Can`t load scenario: {}", err)),
+            };
+        let report = simulation::run(battlefield, args.max_duration);
+        let line = serde_json::to_string(&report).expect("Can`t serialize report");
+        if let Err(err) = writeln!(out, "{}", line) {
+            fail(&format!("Can`t write report: {}", err));
+        }
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Args, String> {
+    let mut args = args.into_iter();
+    let scenario = match args.next() {
+        Some(ref arg) if !arg.starts_with("--") => arg.clone(),
+        _ => return Err(USAGE.to_string()),
+    };
+    let mut parsed = Args {
+        scenario,
+        runs: 1,
+        seed: 0,
+        max_duration: DEFAULT_MAX_DURATION,
+        units: None,
+        out: None,
+    };
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}\n{}", flag, USAGE))?;
+        let bad_value = || format!("Bad value for {}: {}", flag, value);
+        match flag.as_str() {
+            "--runs" => parsed.runs = value.parse().map_err(|_| bad_value())?,
+            "--seed" => parsed.seed = value.parse().map_err(|_| bad_value())?,
+            "--max-time" => parsed.max_duration = value.parse().map_err(|_| bad_value())?,
+            "--units" => parsed.units = Some(value),
+            "--out" => parsed.out = Some(value),
+            _ => return Err(format!("Unknown option {}\n{}", flag, USAGE)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
@@ -1,9 +1,9 @@
 #![allow(dead_code)]
 
-use cgmath::{perspective, Angle, Array, Matrix3, Matrix4, Rad, Vector3};
+use cgmath::{perspective, Angle, Array, Matrix3, Matrix4, Rad, SquareMatrix, Vector3, Vector4};
 use core::misc::clamp;
 use std::f32::consts::PI;
-use types::{Size2, WorldPos};
+use types::{ScreenPos, Size2, WorldPos};
 
 #[derive(Clone, Debug)]
 pub struct Camera {
@@ -51,6 +51,27 @@ impl Camera {
         self.projection_mat * zoom_m * x_angle_m * z_angle_m * tr_m
     }
 
+    /// The point at z = 0 that shows at `pos` on the screen, if the camera looks at the
+    /// ground there. Hills are ignored.
+    pub fn ground_pos(&self, pos: ScreenPos, win_size: Size2) -> Option<WorldPos> {
+        let inverse = self.mat().invert()?;
+        let x = 2.0 * pos.v.x as f32 / win_size.w as f32 - 1.0;
+        let y = 1.0 - 2.0 * pos.v.y as f32 / win_size.h as f32;
+        let unproject = |z| {
+            let v = inverse * Vector4::new(x, y, z, 1.0);
+            v.truncate() / v.w
+        };
+        let near = unproject(-1.0);
+        let far = unproject(1.0);
+        if near.z <= 0.0 || far.z >= near.z {
+            return None;
+        }
+        let v = near + (far - near) * (near.z / (near.z - far.z));
+        Some(WorldPos {
+            v: Vector3::new(v.x as f64, v.y as f64, 0.0),
+        })
+    }
+
     pub fn add_horizontal_angle(&mut self, angle: Rad<f32>) {
         self.z_angle = self.z_angle + angle;
         while self.z_angle < Rad(0.0) {
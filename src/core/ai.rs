@@ -0,0 +1,757 @@
+//! Commanders for the players the computer controls. Every `THINK_INTERVAL` steps a commander
+//! looks at the battle, picks a `Strategy` and gives its companies the same orders a human
+//! player would.
+//!
+//! A commander only reads the battlefield, so it decides the same way every time the battle is
+//! played. That is why its orders aren't recorded: a replay runs the commanders again instead.
+//!
+//! The strategy depends on the role of the player and on how strong its army is compared to
+//! the enemy. In a gate assault, the team that has to hold the gate attacks it and the other
+//! teams defend it; without a gate it is a battle in the open field.
+//!
+//! A gate that is still standing has to be broken down before it can be stormed, see
+//! `structure`.
+//!
+//! Like a human player, a commander only knows of the enemies its team has seen, see
+//! `visibility`. Until it has seen any, it goes by its role alone.
+
+use cgmath::{Rad, Vector2};
+use core::battlefield::{Battlefield, TICKS_PER_SECOND};
+use core::company::{CompId, Company, Formation, FormationShape};
+use core::level::ConnectionId;
+use core::movement::heading_of;
+use core::order::Order;
+use core::player::{PlayerId, TeamId};
+use core::position::Position;
+use core::victory::{VictoryCondition, HOLD_RADIUS};
+use core::visibility::Sighting;
+use std::collections::BTreeMap;
+
+/// Steps between two looks at the battle
+pub const THINK_INTERVAL: u64 = 2 * TICKS_PER_SECOND as u64;
+
+/// Orders to a place closer than this (in m) to the current one aren't worth giving
+const REORDER_DIST: f64 = 2.0;
+
+/// Attackers march in column until they are this close (in m) to the gate
+const DEPLOY_DIST: f64 = 25.0;
+
+/// How far (in m) a defender looks for high ground to hold
+const HIGH_GROUND_DIST: f64 = 20.0;
+
+/// Room between a company and the one behind it, in m
+const RESERVE_DIST: f64 = 8.0;
+
+/// A company this close (in m) to where it was sent has got there, and holds
+const ARRIVED_DIST: f64 = 0.5;
+
+/// Ranged companies keep to this fraction of their range from the enemy
+const FIRING_DIST: f64 = 0.8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Strategy {
+    /// Field battle: march on the nearest enemy
+    Advance,
+    /// Field battle, when weaker: take the high ground nearby and let the enemy come
+    HoldHighGround,
+    /// Gate assault, attacker: march on the gate in column and push through in line
+    Storm,
+    /// Gate assault, attacker, when weaker: keep out of reach and let the ranged companies
+    /// thin out the defenders first
+    Besiege,
+    /// Gate assault, defender: line up on our side of the gate and hold it
+    DefendGate,
+    /// Gate assault, defender, when much stronger: go out and fight
+    Sally,
+}
+
+impl Strategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Advance => "advance",
+            Strategy::HoldHighGround => "hold the high ground",
+            Strategy::Storm => "storm the gate",
+            Strategy::Besiege => "besiege",
+            Strategy::DefendGate => "defend the gate",
+            Strategy::Sally => "sally",
+        }
+    }
+}
+
+/// What a player fights for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Role {
+    Field,
+    Attacker(ConnectionId),
+    Defender(ConnectionId),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Commander {
+    player_id: PlayerId,
+    strategy: Strategy,
+    /// Where its companies stood the first time it thought, to tell the sides of a gate apart
+    home: Option<Position>,
+}
+
+impl Commander {
+    pub fn new(player_id: PlayerId) -> Commander {
+        Commander {
+            player_id,
+            strategy: Strategy::Advance,
+            home: None,
+        }
+    }
+
+    pub fn player_id(&self) -> PlayerId {
+        self.player_id
+    }
+
+    /// The strategy picked the last time it thought.
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Picks a strategy for the battle as it is now, and returns the orders that carry it out.
+    pub fn think(&mut self, battlefield: &Battlefield) -> Vec<Order> {
+        let team = battlefield
+            .get_player(&self.player_id)
+            .expect("Bad player id")
+            .team;
+        let ours = companies_where(battlefield, |company| company.player_id == self.player_id);
+        if ours.is_empty() {
+            return Vec::new();
+        }
+        let theirs = contacts(battlefield, team);
+        let their_strength: f64 = theirs.iter().map(|contact| contact.strength).sum();
+        let ratio = strength(battlefield, &ours) / their_strength.max(1.0);
+        let role = role_of(battlefield, team);
+        self.strategy = match role {
+            Role::Field if ratio >= 0.8 || theirs.is_empty() => Strategy::Advance,
+            Role::Field => Strategy::HoldHighGround,
+            Role::Attacker(_)
+                if ratio >= 1.0 || theirs.is_empty() || running_out_of_time(battlefield, team) =>
+            {
+                Strategy::Storm
+            }
+            Role::Attacker(_) => Strategy::Besiege,
+            Role::Defender(gate)
+                if ratio >= 2.0 && !theirs.is_empty() && is_open(battlefield, gate) =>
+            {
+                Strategy::Sally
+            }
+            Role::Defender(_) => Strategy::DefendGate,
+        };
+        let mut orders = Vec::new();
+        let front = centre_of(battlefield, &ours);
+        let started = *self.home.get_or_insert(front);
+        match (self.strategy, role) {
+            (Strategy::Advance, _) | (Strategy::Sally, _) => {
+                for &comp_id in &ours {
+                    let company = battlefield.get_company(&comp_id).unwrap();
+                    let target = nearest(&theirs, company.pos)
+                        .unwrap_or_else(|| expected_enemy(battlefield, team));
+                    form_up(battlefield, comp_id, FormationShape::Line, &mut orders);
+                    move_to(battlefield, comp_id, target, &mut orders);
+                }
+            }
+            (Strategy::HoldHighGround, _) => {
+                let hill = high_ground(battlefield, front);
+                let enemy = average(theirs.iter().map(|contact| contact.pos));
+                let rot = facing(hill, enemy);
+                line_up(battlefield, &ours, hill, rot, &mut orders);
+            }
+            (Strategy::Storm, Role::Attacker(gate)) => {
+                // By where they came from, so the ends don't swap once half of them are through
+                let home = battlefield
+                    .deployment_zone(self.player_id)
+                    .map_or(started, |zone| zone.centre());
+                let (near, far) = gate_ends(battlefield, gate, home);
+                // A gate that still stands has to be broken first
+                let standing_gate = battlefield
+                    .gate_of(gate)
+                    .filter(|structure| !structure.is_breached())
+                    .map(|structure| structure.id);
+                for &comp_id in &ours {
+                    let company = battlefield.get_company(&comp_id).unwrap();
+                    let deployed = company.pos.dist(&near) <= DEPLOY_DIST;
+                    let shape = if deployed {
+                        FormationShape::Line
+                    } else {
+                        FormationShape::Column
+                    };
+                    form_up(battlefield, comp_id, shape, &mut orders);
+                    // Ranged companies shoot over the heads of the others from outside
+                    if is_ranged(battlefield, company) {
+                        let dest = towards(near, home, firing_dist(battlefield, company) / 2.0);
+                        move_to(battlefield, comp_id, dest, &mut orders);
+                        continue;
+                    }
+                    match standing_gate {
+                        // Only once in line, where it stands to attack depends on its formation
+                        Some(structure_id) if deployed => {
+                            if company.target != Some(structure_id) {
+                                orders.push(Order::AttackStructure {
+                                    comp_id,
+                                    structure_id,
+                                });
+                            }
+                        }
+                        Some(_) => move_to(battlefield, comp_id, near, &mut orders),
+                        // Through the gate, drive off whoever holds its far end
+                        None => {
+                            let dest = nearest(&theirs, far)
+                                .filter(|pos| pos.dist(&far) <= HOLD_RADIUS)
+                                .unwrap_or(far);
+                            move_to(battlefield, comp_id, dest, &mut orders);
+                        }
+                    }
+                }
+            }
+            (Strategy::Besiege, Role::Attacker(gate)) => {
+                let (near, _) = gate_ends(battlefield, gate, front);
+                for &comp_id in &ours {
+                    let company = battlefield.get_company(&comp_id).unwrap();
+                    if is_ranged(battlefield, company) {
+                        let target = nearest(&theirs, company.pos).expect("Besieging nobody");
+                        let dest = towards(target, company.pos, firing_dist(battlefield, company));
+                        move_to(battlefield, comp_id, dest, &mut orders);
+                    } else {
+                        let reach = theirs
+                            .iter()
+                            .map(|contact| contact.reach)
+                            .fold(0.0, f64::max);
+                        let reach = reach + RESERVE_DIST;
+                        let dest = towards(near, front, reach);
+                        form_up(battlefield, comp_id, FormationShape::Line, &mut orders);
+                        move_to(battlefield, comp_id, dest, &mut orders);
+                    }
+                }
+            }
+            (Strategy::DefendGate, Role::Defender(gate)) => {
+                let (near, far) = gate_ends(battlefield, gate, front);
+                let rot = facing(near, far);
+                // In line right behind the gate, the ranged companies behind them
+                let (ranged, melee): (Vec<CompId>, Vec<CompId>) =
+                    ours.iter().cloned().partition(|comp_id| {
+                        is_ranged(battlefield, battlefield.get_company(comp_id).unwrap())
+                    });
+                let behind = |dist: f64| towards(near, far, -dist);
+                line_up(battlefield, &melee, behind(2.0), rot, &mut orders);
+                let depth = melee.len() as f64 * RESERVE_DIST;
+                line_up(battlefield, &ranged, behind(depth + 2.0), rot, &mut orders);
+            }
+            _ => unreachable!("{:?} for {:?}", self.strategy, role),
+        }
+        orders
+    }
+}
+
+/// The ids of the companies for which `pred` holds and that aren't running away, sorted.
+fn companies_where<F: Fn(&Company) -> bool>(battlefield: &Battlefield, pred: F) -> Vec<CompId> {
+    let mut ids: Vec<CompId> = battlefield
+        .get_company_iter()
+        .filter(|&(_, company)| !company.is_routing() && pred(company))
+        .map(|(&id, _)| id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// The hp of all soldiers of `comp_ids`.
+fn strength(battlefield: &Battlefield, comp_ids: &[CompId]) -> f64 {
+    comp_ids
+        .iter()
+        .flat_map(|comp_id| battlefield.get_company(comp_id).unwrap().members.iter())
+        .map(|id| battlefield.get_indiv(id).expect("Bad indiv id").hp.max(0) as f64)
+        .sum()
+}
+
+fn role_of(battlefield: &Battlefield, team: TeamId) -> Role {
+    for condition in battlefield.victory_conditions() {
+        if let VictoryCondition::HoldGate {
+            gate, team: holder, ..
+        } = *condition
+        {
+            return if holder == team {
+                Role::Attacker(gate)
+            } else {
+                Role::Defender(gate)
+            };
+        }
+    }
+    Role::Field
+}
+
+/// Whether a time limit that `team` doesn't win by is two thirds gone.
+fn running_out_of_time(battlefield: &Battlefield, team: TeamId) -> bool {
+    battlefield
+        .victory_conditions()
+        .iter()
+        .any(|condition| match *condition {
+            VictoryCondition::TimeLimit { time, winner } => {
+                winner != Some(team) && battlefield.duration() > time * 2.0 / 3.0
+            }
+            _ => false,
+        })
+}
+
+/// An enemy company as far as a commander knows it, from the sightings of its soldiers.
+struct Contact {
+    pos: Position,
+    /// The hp of its soldiers
+    strength: f64,
+    /// How far it shoots, 0 if it can't
+    reach: f64,
+}
+
+/// The enemy companies `team` has seen, but not running away the last time it looked. Sorted
+/// by company id.
+fn contacts(battlefield: &Battlefield, team: TeamId) -> Vec<Contact> {
+    let sight = match battlefield.sight(team) {
+        Some(sight) => sight,
+        None => return Vec::new(),
+    };
+    let mut by_company: BTreeMap<CompId, Vec<&Sighting>> = BTreeMap::new();
+    for sighting in sight.sightings() {
+        by_company
+            .entry(sighting.comp_id)
+            .or_default()
+            .push(sighting);
+    }
+    by_company
+        .values()
+        .filter(|sightings| sightings.iter().any(|sighting| !sighting.routing))
+        .map(|sightings| Contact {
+            pos: average(sightings.iter().map(|sighting| sighting.pos)),
+            strength: sightings
+                .iter()
+                .map(|sighting| sighting.hp.max(0) as f64)
+                .sum(),
+            reach: sightings
+                .iter()
+                .filter(|sighting| sighting.ammo > 0)
+                .map(|sighting| battlefield.get_unit_type(sighting.type_id).weapon_type)
+                .filter(|weapon_type| weapon_type.is_ranged())
+                .map(|weapon_type| weapon_type.stats().range)
+                .fold(0.0, f64::max),
+        })
+        .collect()
+}
+
+/// Where to look for enemies when none have been seen: the middle of their deployment zones,
+/// or else of the map.
+fn expected_enemy(battlefield: &Battlefield, team: TeamId) -> Position {
+    let mut enemies: Vec<PlayerId> = battlefield
+        .get_player_iter()
+        .filter(|&(_, player)| player.team != team)
+        .map(|(&id, _)| id)
+        .collect();
+    enemies.sort();
+    let zones: Vec<Position> = enemies
+        .into_iter()
+        .filter_map(|id| battlefield.deployment_zone(id).map(|zone| zone.centre()))
+        .collect();
+    if zones.is_empty() {
+        let size = battlefield.map_size;
+        Position::new(size.w as f64 / 2.0, size.h as f64 / 2.0)
+    } else {
+        average(zones.into_iter())
+    }
+}
+
+fn centre_of(battlefield: &Battlefield, comp_ids: &[CompId]) -> Position {
+    average(
+        comp_ids
+            .iter()
+            .map(|comp_id| battlefield.get_company(comp_id).unwrap().pos),
+    )
+}
+
+/// The average of `positions`, on the ground.
+fn average<I: Iterator<Item = Position>>(positions: I) -> Position {
+    let (n, x, y) = positions.fold((0.0, 0.0, 0.0), |(n, x, y), pos| {
+        (n + 1.0, x + pos.x, y + pos.y)
+    });
+    let n = f64::max(n, 1.0);
+    Position::new(x / n, y / n)
+}
+
+/// The position of the contact closest to `pos`, if there are any.
+fn nearest(contacts: &[Contact], pos: Position) -> Option<Position> {
+    let mut best: Option<Position> = None;
+    for contact in contacts {
+        if best.is_none_or(|best| contact.pos.dist(&pos) < best.dist(&pos)) {
+            best = Some(contact.pos);
+        }
+    }
+    best
+}
+
+/// The ends of the gate, the one on the side of `front` first.
+fn gate_ends(
+    battlefield: &Battlefield,
+    gate: ConnectionId,
+    front: Position,
+) -> (Position, Position) {
+    let connection = battlefield
+        .nav()
+        .get_connection(&gate)
+        .expect("Bad connection id");
+    if connection.a.dist(&front) <= connection.b.dist(&front) {
+        (connection.a, connection.b)
+    } else {
+        (connection.b, connection.a)
+    }
+}
+
+/// Whether the gate can be passed: it is open, or broken down.
+fn is_open(battlefield: &Battlefield, gate: ConnectionId) -> bool {
+    battlefield
+        .nav()
+        .get_connection(&gate)
+        .expect("Bad connection id")
+        .open
+}
+
+/// The point `dist` m from `from` in the direction of `to`. Negative distances go the other way.
+fn towards(from: Position, to: Position, dist: f64) -> Position {
+    let len = from.dist(&to);
+    if len == 0.0 {
+        return from;
+    }
+    let t = dist / len;
+    Position::on_level(
+        from.x + (to.x - from.x) * t,
+        from.y + (to.y - from.y) * t,
+        from.level,
+    )
+}
+
+fn facing(from: Position, to: Position) -> Rad<f32> {
+    heading_of(Vector2::new(to.x - from.x, to.y - from.y))
+}
+
+/// The highest walkable spot on a 2 m grid within `HIGH_GROUND_DIST` of `pos`.
+fn high_ground(battlefield: &Battlefield, pos: Position) -> Position {
+    let terrain = battlefield.terrain();
+    let (w, h) = (battlefield.map_size.w as f64, battlefield.map_size.h as f64);
+    let mut best = pos;
+    let steps = (HIGH_GROUND_DIST / 2.0) as i32;
+    for j in -steps..(steps + 1) {
+        for i in -steps..(steps + 1) {
+            let spot = Position::new(pos.x + 2.0 * i as f64, pos.y + 2.0 * j as f64);
+            if spot.x < 0.0 || spot.y < 0.0 || spot.x > w || spot.y > h {
+                continue;
+            }
+            if spot.dist(&pos) > HIGH_GROUND_DIST || !battlefield.nav().is_walkable(spot) {
+                continue;
+            }
+            if terrain.height(spot.x, spot.y) > terrain.height(best.x, best.y) {
+                best = spot;
+            }
+        }
+    }
+    best
+}
+
+fn is_ranged(battlefield: &Battlefield, company: &Company) -> bool {
+    company_weapon_range(battlefield, company).is_some()
+}
+
+/// How far the company shoots, if it has anything left to shoot.
+fn company_weapon_range(battlefield: &Battlefield, company: &Company) -> Option<f64> {
+    let indiv = battlefield.get_indiv(&company.members[0])?;
+    let weapon_type = battlefield.get_unit_type(indiv.type_id).weapon_type;
+    let has_ammo = company.members.iter().any(|id| {
+        battlefield
+            .get_indiv(id)
+            .is_some_and(|indiv| indiv.ammo > 0)
+    });
+    if weapon_type.is_ranged() && has_ammo {
+        Some(weapon_type.stats().range)
+    } else {
+        None
+    }
+}
+
+fn firing_dist(battlefield: &Battlefield, company: &Company) -> f64 {
+    company_weapon_range(battlefield, company).unwrap_or(0.0) * FIRING_DIST
+}
+
+/// Puts the companies of `comp_ids` in a row at `pos`, the first in the middle, facing `rot`.
+/// Once there they hold.
+fn line_up(
+    battlefield: &Battlefield,
+    comp_ids: &[CompId],
+    pos: Position,
+    rot: Rad<f32>,
+    orders: &mut Vec<Order>,
+) {
+    let mut offset = 0.0;
+    for (i, &comp_id) in comp_ids.iter().enumerate() {
+        form_up(battlefield, comp_id, FormationShape::Line, orders);
+        let company = battlefield.get_company(&comp_id).unwrap();
+        let width = Formation::line(company.members.len()).width as f64 + 2.0;
+        // Alternately to the right and the left of the ones placed so far
+        let side = if i % 2 == 0 { 1.0 } else { -1.0 };
+        let dest = pos.offset(Vector2::new(side * offset, 0.0), rot);
+        if i % 2 == 0 {
+            offset += width;
+        }
+        if company.order.is_some() && company.pos.dist(&dest) < ARRIVED_DIST {
+            orders.push(Order::Hold { comp_id });
+        } else {
+            give_move(battlefield, comp_id, dest, rot, orders);
+        }
+    }
+}
+
+/// Sends the company to `dest`, facing the way it goes.
+fn move_to(battlefield: &Battlefield, comp_id: CompId, dest: Position, orders: &mut Vec<Order>) {
+    let pos = battlefield.get_company(&comp_id).unwrap().pos;
+    give_move(battlefield, comp_id, dest, facing(pos, dest), orders);
+}
+
+fn give_move(
+    battlefield: &Battlefield,
+    comp_id: CompId,
+    dest: Position,
+    facing: Rad<f32>,
+    orders: &mut Vec<Order>,
+) {
+    let company = battlefield.get_company(&comp_id).unwrap();
+    let current = company.order.map_or(company.pos, |order| order.dest);
+    if current.dist(&dest) > REORDER_DIST {
+        orders.push(Order::MoveCompany {
+            comp_id,
+            dest,
+            facing,
+        });
+    }
+}
+
+fn form_up(
+    battlefield: &Battlefield,
+    comp_id: CompId,
+    shape: FormationShape,
+    orders: &mut Vec<Order>,
+) {
+    let company = battlefield.get_company(&comp_id).unwrap();
+    if company.formation.shape == shape {
+        return;
+    }
+    let count = company.members.len();
+    let formation = match shape {
+        FormationShape::Line => Formation::line(count),
+        FormationShape::Column => Formation::column(count),
+        FormationShape::Square => Formation::square(count),
+        FormationShape::Wedge => Formation::wedge(count),
+    };
+    orders.push(Order::SetFormation { comp_id, formation });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Strategy, THINK_INTERVAL};
+    use cgmath::Rad;
+    use core::battlefield::Battlefield;
+    use core::company::{CompId, Formation, FormationShape};
+    use core::level::{ConnectionId, ConnectionKind, Level};
+    use core::player::{Controller, PlayerId, TeamId};
+    use core::position::Position;
+    use core::replay::{Playback, Replay};
+    use core::structure::StructureKind;
+    use core::terrain::Terrain;
+    use core::unit::{test_unit_type, UnitType, UnitTypeId};
+    use core::victory::VictoryCondition;
+    use core::weapon::WeaponType;
+    use std::f32::consts::PI;
+    use types::Size2;
+
+    fn melee_unit_type() -> UnitType {
+        UnitType {
+            name: "melee".to_string(),
+            weapon_type: WeaponType::Melee,
+            ..test_unit_type()
+        }
+    }
+
+    /// A battlefield with one player per entry of `controllers`, each on its own team.
+    fn new_battlefield(size: Size2, controllers: &[Controller]) -> Battlefield {
+        let mut bf = Battlefield::new(vec![melee_unit_type()], 5);
+        bf.set_terrain(Terrain::flat(size));
+        for (i, &controller) in controllers.iter().enumerate() {
+            bf.add_player(
+                "Player",
+                "Rome",
+                TeamId { id: i as u8 },
+                [1.0, 1.0, 1.0, 1.0],
+                controller,
+            );
+        }
+        bf
+    }
+
+    fn add_company(bf: &mut Battlefield, player: u8, pos: Position, rot: Rad<f32>) -> CompId {
+        bf.add_company(
+            PlayerId { id: player },
+            UnitTypeId { id: 0 },
+            16,
+            pos,
+            rot,
+            Formation::new(FormationShape::Line, 4, 4),
+        )
+    }
+
+    fn strategy_of(bf: &Battlefield, player: u8) -> Strategy {
+        let commander = bf
+            .commanders()
+            .iter()
+            .find(|commander| commander.player_id() == PlayerId { id: player })
+            .unwrap();
+        commander.strategy()
+    }
+
+    #[test]
+    fn test_ai_marches_on_the_enemy() {
+        let mut bf = new_battlefield(Size2 { w: 20, h: 50 }, &[Controller::Human, Controller::Ai]);
+        let ours = add_company(&mut bf, 0, Position::new(10.0, 5.0), Rad(0.0));
+        let theirs = add_company(&mut bf, 1, Position::new(10.0, 40.0), Rad(PI));
+        let replay = Replay::new(&bf);
+        let start = bf.get_company(&theirs).unwrap().pos;
+        for _ in 0..(20 * 30) {
+            bf.step();
+        }
+        assert_eq!(strategy_of(&bf, 1), Strategy::Advance);
+        let dist = |bf: &Battlefield| {
+            let pos = bf.get_company(&theirs).unwrap().pos;
+            pos.dist(&bf.get_company(&ours).unwrap().pos)
+        };
+        assert!(dist(&bf) < 8.0);
+        assert!(bf.get_company(&theirs).unwrap().pos.dist(&start) > 20.0);
+        // The commander's orders aren't recorded, the replay thinks again
+        assert!(bf.orders().is_empty());
+        let mut recorded = replay;
+        recorded.record(&bf);
+        let mut playback = Playback::new(recorded);
+        playback.seek(u64::MAX);
+        assert_eq!(dist(playback.battlefield()), dist(&bf));
+    }
+
+    /// A wall across the middle of the map with an open gate in it. Team 0 wins by holding the
+    /// gate for 10 s; it has `attackers` companies, team 1 has `defenders`.
+    fn gate_assault(attackers: usize, defenders: usize) -> Battlefield {
+        let mut bf = new_battlefield(Size2 { w: 40, h: 60 }, &[Controller::Ai, Controller::Ai]);
+        bf.add_obstacle(
+            Level::Ground,
+            Position::new(0.0, 29.0),
+            Position::new(40.0, 31.0),
+        );
+        let gate = bf.add_connection(
+            ConnectionKind::GatePassage,
+            Position::new(20.0, 28.0),
+            Position::new(20.0, 32.0),
+        );
+        bf.add_victory_condition(VictoryCondition::HoldGate {
+            gate,
+            team: TeamId { id: 0 },
+            time: 10.0,
+        });
+        for i in 0..attackers {
+            let pos = Position::new(8.0 + 8.0 * i as f64, 6.0);
+            add_company(&mut bf, 0, pos, Rad(0.0));
+        }
+        for i in 0..defenders {
+            let pos = Position::new(8.0 + 8.0 * i as f64, 52.0);
+            add_company(&mut bf, 1, pos, Rad(PI));
+        }
+        bf
+    }
+
+    #[test]
+    fn test_strategies_follow_strength() {
+        // Nobody sees through the wall: the attackers go for the gate, the defenders hold it
+        let mut bf = gate_assault(1, 3);
+        bf.step();
+        assert_eq!(strategy_of(&bf, 0), Strategy::Storm);
+        assert_eq!(strategy_of(&bf, 1), Strategy::DefendGate);
+        // Two defending companies wait outside, in sight of the attackers
+        let mut bf = gate_assault(1, 3);
+        for (i, id) in [1, 2].iter().enumerate() {
+            let pos = Position::new(8.0 + 8.0 * i as f64, 20.0);
+            bf.deploy_company(CompId { id: *id }, pos, Rad(PI)).unwrap();
+        }
+        bf.step();
+        assert_eq!(strategy_of(&bf, 0), Strategy::Besiege);
+        assert_eq!(strategy_of(&bf, 1), Strategy::Sally);
+    }
+
+    #[test]
+    fn test_defenders_hold_the_gate() {
+        let mut bf = gate_assault(2, 3);
+        for _ in 0..(THINK_INTERVAL * 20) {
+            bf.step();
+        }
+        // The defending companies stand in line right behind the gate
+        for comp_id in &[CompId { id: 2 }, CompId { id: 3 }, CompId { id: 4 }] {
+            let company = bf.get_company(comp_id).unwrap();
+            assert!(
+                company.pos.y > 32.0 && company.pos.y < 40.0,
+                "{:?}",
+                company.pos
+            );
+            assert!((company.pos.x - 20.0).abs() < 12.0, "{:?}", company.pos);
+            assert!(company.order.is_none());
+        }
+        assert_eq!(strategy_of(&bf, 0), Strategy::Besiege);
+        assert!(!bf.is_over());
+    }
+
+    #[test]
+    fn test_attackers_take_the_gate() {
+        let mut bf = gate_assault(3, 1);
+        for _ in 0..(20 * 180) {
+            bf.step();
+            if bf.is_over() {
+                break;
+            }
+        }
+        let outcome = bf.outcome().expect("Battle not over");
+        assert_eq!(outcome.winner, Some(TeamId { id: 0 }));
+    }
+
+    #[test]
+    fn test_attackers_break_down_the_gate() {
+        let mut bf = new_battlefield(Size2 { w: 40, h: 60 }, &[Controller::Ai, Controller::Ai]);
+        let wall = |x0: f64, x1: f64| (Position::new(x0, 29.0), Position::new(x1, 31.0));
+        let kinds = [
+            (StructureKind::WallSegment, wall(0.0, 18.0)),
+            (StructureKind::Gate, wall(18.0, 22.0)),
+            (StructureKind::WallSegment, wall(22.0, 40.0)),
+        ];
+        for &(kind, (min, max)) in kinds.iter() {
+            bf.add_structure(kind, min, max);
+        }
+        let gate = bf.gate_of(ConnectionId { id: 0 }).unwrap().id;
+        bf.add_victory_condition(VictoryCondition::HoldGate {
+            gate: ConnectionId { id: 0 },
+            team: TeamId { id: 0 },
+            time: 10.0,
+        });
+        for i in 0..2 {
+            let pos = Position::new(12.0 + 16.0 * i as f64, 6.0);
+            add_company(&mut bf, 0, pos, Rad(0.0));
+        }
+        add_company(&mut bf, 1, Position::new(20.0, 52.0), Rad(PI));
+        for _ in 0..(20 * 300) {
+            bf.step();
+            if bf.is_over() {
+                break;
+            }
+        }
+        assert!(bf.get_structure(&gate).unwrap().is_breached());
+        let outcome = bf.outcome().expect("Battle not over");
+        assert_eq!(outcome.winner, Some(TeamId { id: 0 }));
+    }
+}
@@ -1,59 +1,2522 @@
-use cgmath::Rad;
+use cgmath::{Rad, Vector2};
+use core::ai::{Commander, THINK_INTERVAL};
+use core::combat::{self, AttackResult, ATTACK_INTERVAL, MELEE_DAMAGE, MELEE_RANGE};
+use core::company::{CompId, Company, Formation};
+use core::deployment::{DeploymentError, Zone, DEPLOY_GAP};
+use core::event::BattleEvent;
+use core::fatigue::{Exertion, FatigueRules};
+use core::level::{Connection, ConnectionId, ConnectionKind, Level, CONNECTION_DIST};
+use core::misc::seeded_rng;
+use core::morale::{
+    Morale, MoraleInputs, MoraleState, RALLY_DIST, ROUTING_FRIEND_DIST, WAVERING_ATTACK_DELAY,
+};
+use core::movement::{heading_of, MoveOrder};
+use core::order::{Order, RecordedOrder};
+use core::pathfinding::{FlowField, NavGrid, Path, REPATH_DIST, WAYPOINT_DIST};
+use core::player::{Controller, Player, PlayerId, TeamId, MAX_PLAYERS};
 use core::position::Position;
-use core::unit::{Indiv, IndivId, UnitTypeId};
-use std::collections::hash_map::Iter;
-use std::collections::HashMap;
-use types::Size2;
+use core::projectile::{Projectile, ProjectileId, HIT_RADIUS};
+use core::spatial::{SpatialGrid, CELL_SIZE};
+use core::structure::{
+    DamageType, Structure, StructureId, StructureKind, StructureState, STRIKE_DIST,
+};
+use core::terrain::Terrain;
+use core::unit::{Indiv, IndivId, UnitType, UnitTypeId};
+use core::veterancy::{self, Rank, VeteranCompany, XP_PER_BATTLE, XP_PER_KILL};
+use core::victory::{self, BattleOutcome, VictoryCondition, HOLD_RADIUS};
+use core::visibility::{self, Sight, Sighting, SIGHT_INTERVAL};
+use rand::prng::XorShiftRng;
+use rand::Rng;
+use std::collections::hash_map::{self, Iter};
+use std::collections::{HashMap, HashSet};
+use std::f64;
+use std::f64::consts::PI;
+use std::mem;
+use types::{Size2, Time};
 
-#[derive(Clone, Debug)]
+/// Number of simulation steps per second. Independent of the render frame rate.
+pub const TICKS_PER_SECOND: u32 = 20;
+
+/// Length of a single simulation step in seconds.
+pub const TICK_TIME: f64 = 1.0 / TICKS_PER_SECOND as f64;
+
+/// Upper bound on the steps done in one call to `tick`, so a long frame
+/// (e.g. dragging the window) doesn't stall the game catching up.
+pub const MAX_STEPS_PER_TICK: u32 = 10;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Battlefield {
+    players: HashMap<PlayerId, Player>,
     indivs: HashMap<IndivId, Indiv>,
-    //companies: HashMap<CompId, Vec<indivId>>,
+    /// Where everyone in `indivs` stands, for neighbour queries
+    grid: SpatialGrid,
+    companies: HashMap<CompId, Company>,
+    projectiles: HashMap<ProjectileId, Projectile>,
+    nav: NavGrid,
+    /// The ways to where companies are marching, shared by their soldiers. Not saved, they are
+    /// built again when needed.
+    #[serde(skip)]
+    flow_fields: Vec<FlowField>,
+    /// Walls, gates and towers, breached ones included
+    structures: HashMap<StructureId, Structure>,
+    unit_types: Vec<UnitType>,
+    terrain: Terrain,
     pub map_size: Size2,
+    seed: u64,
+    /// All randomness of the battle comes from here, so the same seed and the same orders
+    /// always give the same battle
+    rng: XorShiftRng,
+    /// What happened so far during the current step
+    events: Vec<BattleEvent>,
+    victory_conditions: Vec<VictoryCondition>,
+    /// Seconds each of `victory_conditions` has been held without a break, for `HoldGate`
+    held_for: Vec<f64>,
+    casualties: HashMap<TeamId, u32>,
+    outcome: Option<BattleOutcome>,
+    /// Every order given so far, for replays. Not saved, a `Replay` keeps the ones it needs.
+    #[serde(skip)]
+    orders: Vec<RecordedOrder>,
+    fatigue_rules: FatigueRules,
+    /// Where each player may deploy. Players without a zone may deploy anywhere.
+    deployment_zones: HashMap<PlayerId, Zone>,
+    /// One for every player the computer controls, sorted by player id
+    commanders: Vec<Commander>,
+    /// What every team knows of its enemies
+    sights: HashMap<TeamId, Sight>,
+    next_player_id: u8,
     next_indiv_id: u32,
+    next_comp_id: u32,
+    next_projectile_id: u32,
+    next_connection_id: u32,
+    next_structure_id: u32,
+    tick_count: u64,
+    time_acc: f64,
 }
 
 impl Battlefield {
-    pub fn new() -> Battlefield {
-        let indivs: HashMap<IndivId, Indiv> = HashMap::new();
-
-        let mut battlefield = Battlefield {
-            indivs,
+    /// An empty battlefield of flat ground, without players or soldiers. See
+    /// `scenario::Scenario` for setting up a battle.
+    pub fn new(unit_types: Vec<UnitType>, seed: u64) -> Battlefield {
+        Battlefield {
+            players: HashMap::new(),
+            indivs: HashMap::new(),
+            grid: SpatialGrid::new(CELL_SIZE),
+            companies: HashMap::new(),
+            projectiles: HashMap::new(),
+            unit_types,
+            terrain: Terrain::flat(Size2 { w: 5, h: 5 }),
+            nav: NavGrid::new(&Terrain::flat(Size2 { w: 5, h: 5 })),
+            flow_fields: Vec::new(),
+            structures: HashMap::new(),
             map_size: Size2 { w: 5, h: 5 },
+            seed,
+            rng: seeded_rng(seed),
+            events: Vec::new(),
+            victory_conditions: Vec::new(),
+            held_for: Vec::new(),
+            casualties: HashMap::new(),
+            outcome: None,
+            orders: Vec::new(),
+            fatigue_rules: FatigueRules::default(),
+            deployment_zones: HashMap::new(),
+            commanders: Vec::new(),
+            sights: HashMap::new(),
+            next_player_id: 0,
             next_indiv_id: 0,
+            next_comp_id: 0,
+            next_projectile_id: 0,
+            next_connection_id: 0,
+            next_structure_id: 0,
+            tick_count: 0,
+            time_acc: 0.0,
+        }
+    }
+
+    /// Panics if the battle already has `MAX_PLAYERS` players.
+    pub fn add_player(
+        &mut self,
+        name: &str,
+        faction: &str,
+        team: TeamId,
+        colour: [f32; 4],
+        controller: Controller,
+    ) -> PlayerId {
+        assert!(self.players.len() < MAX_PLAYERS, "Too many players");
+        let id = PlayerId {
+            id: self.next_player_id,
         };
-        for i in 0..5 {
-            for j in 0..5 {
-                battlefield.add_indiv_at(Position::new(i as f64, j as f64));
-            }
+        self.next_player_id += 1;
+        self.players.insert(
+            id,
+            Player {
+                id,
+                name: name.to_owned(),
+                faction: faction.to_owned(),
+                team,
+                colour,
+                controller,
+            },
+        );
+        if controller == Controller::Ai {
+            self.commanders.push(Commander::new(id));
         }
-        battlefield
+        id
+    }
+
+    pub fn get_player(&self, player_id: &PlayerId) -> Option<&Player> {
+        self.players.get(player_id)
+    }
+
+    pub fn get_player_iter(&self) -> Iter<'_, PlayerId, Player> {
+        self.players.iter()
+    }
+
+    /// Whether `a` and `b` fight on the same side. A player is its own ally.
+    pub fn are_allies(&self, a: PlayerId, b: PlayerId) -> bool {
+        let a = self.players.get(&a).expect("Bad player id");
+        let b = self.players.get(&b).expect("Bad player id");
+        a.is_ally_of(b)
     }
 
-    fn add_indiv_at(&mut self, pos: Position) {
-        let id = self.next_indiv_id;
-        self.add_indiv(&Indiv {
-            id: IndivId { id },
+    pub fn are_enemies(&self, a: PlayerId, b: PlayerId) -> bool {
+        !self.are_allies(a, b)
+    }
+
+    /// Adds a company of `count` soldiers of type `type_id`, already standing in formation.
+    pub fn add_company(
+        &mut self,
+        player_id: PlayerId,
+        type_id: UnitTypeId,
+        count: usize,
+        pos: Position,
+        rot: Rad<f32>,
+        formation: Formation,
+    ) -> CompId {
+        let comp_id = CompId {
+            id: self.next_comp_id,
+        };
+        self.next_comp_id += 1;
+        let first_id = self.next_indiv_id;
+        let hp = self.get_unit_type(type_id).hp as i8;
+        let ammo = self.get_unit_type(type_id).weapon_type.stats().ammo;
+        let unit_morale = self.get_unit_type(type_id).morale;
+        let company = Company {
+            id: comp_id,
+            player_id,
             pos,
-            rot: Rad(0.0),
-            player_id: 0, // Replace by PlayerID?
-            type_id: UnitTypeId { id: 0 },
-            hp: 0,
-            xp: 0,
-        });
-        self.next_indiv_id += 1;
+            rot,
+            vel: Vector2::new(0.0, 0.0),
+            formation,
+            members: (0..count as u32)
+                .map(|i| IndivId { id: first_id + i })
+                .collect(),
+            order: None,
+            path: Vec::new(),
+            morale: Morale::new(unit_morale, count, 0.0),
+            target: None,
+        };
+        for (&id, slot_pos) in company.members.iter().zip(company.slot_positions()) {
+            self.add_indiv(&Indiv {
+                id,
+                pos: slot_pos,
+                rot,
+                vel: Vector2::new(0.0, 0.0),
+                comp_id,
+                order: None,
+                target: Some(MoveOrder {
+                    dest: slot_pos,
+                    facing: rot,
+                }),
+                path: Vec::new(),
+                path_goal: None,
+                player_id,
+                type_id,
+                hp,
+                xp: 0,
+                attack_cooldown: 0.0,
+                ammo,
+                stamina: 1.0,
+            });
+        }
+        self.next_indiv_id += count as u32;
+        self.companies.insert(comp_id, company);
+        comp_id
     }
 
     fn add_indiv(&mut self, indiv: &Indiv) {
-        assert!(self.indivs.get(&indiv.id).is_none());
+        assert!(!self.indivs.contains_key(&indiv.id));
         self.indivs.insert(indiv.id, indiv.clone());
+        self.grid.insert(indiv.id, indiv.pos);
     }
 
     pub fn get_indiv(&self, indiv_id: &IndivId) -> Option<&Indiv> {
         self.indivs.get(indiv_id)
     }
 
-    pub fn get_indiv_iter(&self) -> Iter<IndivId, Indiv> {
+    pub fn get_indiv_iter(&self) -> Iter<'_, IndivId, Indiv> {
         self.indivs.iter()
     }
+
+    pub fn get_unit_type(&self, type_id: UnitTypeId) -> &UnitType {
+        &self.unit_types[type_id.id as usize]
+    }
+
+    /// The id of the unit type called `name`.
+    pub fn get_unit_type_id(&self, name: &str) -> Option<UnitTypeId> {
+        self.unit_types
+            .iter()
+            .position(|unit_type| unit_type.name == name)
+            .map(|i| UnitTypeId { id: i as u16 })
+    }
+
+    pub fn get_company(&self, comp_id: &CompId) -> Option<&Company> {
+        self.companies.get(comp_id)
+    }
+
+    pub fn get_company_iter(&self) -> hash_map::Iter<'_, CompId, Company> {
+        self.companies.iter()
+    }
+
+    /// Average xp of the soldiers of the company.
+    pub fn company_xp(&self, comp_id: CompId) -> i8 {
+        let company = self.companies.get(&comp_id).expect("Bad company id");
+        let total: i32 = company
+            .members
+            .iter()
+            .map(|id| self.indivs[id].xp as i32)
+            .sum();
+        (total / company.members.len() as i32) as i8
+    }
+
+    pub fn company_rank(&self, comp_id: CompId) -> Rank {
+        Rank::of(self.company_xp(comp_id))
+    }
+
+    /// Gives every soldier of the company `xp` and the morale that comes with it. Meant for
+    /// setting up a battle, before the fighting starts.
+    pub fn set_company_xp(&mut self, comp_id: CompId, xp: i8) {
+        let xp = xp.clamp(0, veterancy::MAX_XP);
+        let company = self.companies.get_mut(&comp_id).expect("Bad company id");
+        for id in &company.members {
+            self.indivs.get_mut(id).unwrap().xp = xp;
+        }
+        let type_id = self.indivs[&company.members[0]].type_id;
+        let unit_morale = self.unit_types[type_id.id as usize].morale;
+        company.morale = Morale::new(
+            unit_morale,
+            company.morale.start_strength,
+            Rank::of(xp).morale_bonus(),
+        );
+    }
+
+    /// Gives the companies of this battle the experience of `veterans` from an earlier one.
+    /// Each veteran company goes to the first company, by id, of a player with the same name
+    /// and of the same unit type that didn't get one yet.
+    pub fn enlist_veterans(&mut self, veterans: &[VeteranCompany]) {
+        let mut comp_ids: Vec<CompId> = self.companies.keys().cloned().collect();
+        comp_ids.sort();
+        for veteran in veterans {
+            let found = comp_ids.iter().position(|comp_id| {
+                let company = &self.companies[comp_id];
+                let type_id = self.indivs[&company.members[0]].type_id;
+                self.players[&company.player_id].name == veteran.player
+                    && self.unit_types[type_id.id as usize].name == veteran.unit_type
+            });
+            if let Some(i) = found {
+                let comp_id = comp_ids.remove(i);
+                self.set_company_xp(comp_id, veteran.xp);
+            }
+        }
+    }
+
+    /// Gives `xp` to a soldier. When that changes the rank of its company, the company gets
+    /// the morale bonus of its new rank, and a promotion is reported.
+    fn give_xp(&mut self, indiv_id: IndivId, xp: i8) {
+        let comp_id = self.indivs[&indiv_id].comp_id;
+        let old_rank = self.company_rank(comp_id);
+        let indiv = self.indivs.get_mut(&indiv_id).unwrap();
+        indiv.xp = veterancy::add_xp(indiv.xp, xp);
+        let unit_morale = self.unit_types[indiv.type_id.id as usize].morale;
+        let rank = self.company_rank(comp_id);
+        if rank != old_rank {
+            self.companies.get_mut(&comp_id).unwrap().morale.base =
+                Morale::base_for(unit_morale, rank.morale_bonus());
+        }
+        if rank > old_rank {
+            self.events
+                .push(BattleEvent::CompanyPromoted { comp_id, rank });
+        }
+    }
+
+    pub fn get_projectile_iter(&self) -> hash_map::Iter<'_, ProjectileId, Projectile> {
+        self.projectiles.iter()
+    }
+
+    pub fn terrain(&self) -> &Terrain {
+        &self.terrain
+    }
+
+    /// Replaces the ground. The map takes the size of the new terrain.
+    pub fn set_terrain(&mut self, terrain: Terrain) {
+        self.map_size = terrain.size();
+        self.nav.rebuild(&terrain);
+        self.terrain = terrain;
+    }
+
+    pub fn nav(&self) -> &NavGrid {
+        &self.nav
+    }
+
+    /// Blocks the rectangle from `min` to `max` on `level`, for walls and buildings.
+    pub fn add_obstacle(&mut self, level: Level, min: Position, max: Position) {
+        self.nav.add_obstacle(level, min, max);
+    }
+
+    /// Makes the rectangle from `min` to `max` on `level` walkable, for wall walkways and
+    /// tower tops.
+    pub fn add_walkable_area(&mut self, level: Level, min: Position, max: Position) {
+        self.nav.add_walkable_area(level, min, max);
+    }
+
+    /// The shortest path from `from` to `to`, see `NavGrid::find_path`.
+    pub fn find_path(&self, from: Position, to: Position) -> Option<Path> {
+        self.nav.find_path(from, to)
+    }
+
+    /// Adds stairs, a ladder or a gate passage between `a` and `b`.
+    pub fn add_connection(
+        &mut self,
+        kind: ConnectionKind,
+        a: Position,
+        b: Position,
+    ) -> ConnectionId {
+        let id = ConnectionId {
+            id: self.next_connection_id,
+        };
+        self.next_connection_id += 1;
+        self.nav.add_connection(Connection {
+            id,
+            kind,
+            a,
+            b,
+            open: true,
+        });
+        id
+    }
+
+    pub fn get_connection_iter(&self) -> hash_map::Iter<'_, ConnectionId, Connection> {
+        self.nav.connections().iter()
+    }
+
+    /// Opens or closes a gate. Soldiers whose path goes through a gate that closes plan again
+    /// once they get to it.
+    pub fn set_connection_open(&mut self, connection_id: ConnectionId, open: bool) {
+        self.nav
+            .get_connection_mut(&connection_id)
+            .expect("Bad connection id")
+            .open = open;
+    }
+
+    /// Builds a wall segment, gate or tower on the ground from `min` to `max`. It blocks the
+    /// way until it is breached. A gate gets a closed passage across its short side, which
+    /// takes the next connection id.
+    pub fn add_structure(
+        &mut self,
+        kind: StructureKind,
+        min: Position,
+        max: Position,
+    ) -> StructureId {
+        let id = StructureId {
+            id: self.next_structure_id,
+        };
+        self.next_structure_id += 1;
+        let mut structure = Structure::new(id, kind, min, max);
+        self.nav.add_obstacle(Level::Ground, min, max);
+        if kind == StructureKind::Gate {
+            let centre = structure.centre();
+            let (a, b) = if max.x - min.x >= max.y - min.y {
+                (
+                    Position::new(centre.x, min.y - 1.0),
+                    Position::new(centre.x, max.y + 1.0),
+                )
+            } else {
+                (
+                    Position::new(min.x - 1.0, centre.y),
+                    Position::new(max.x + 1.0, centre.y),
+                )
+            };
+            let passage = self.add_connection(ConnectionKind::GatePassage, a, b);
+            self.set_connection_open(passage, false);
+            structure.passage = Some(passage);
+        }
+        self.structures.insert(id, structure);
+        id
+    }
+
+    pub fn get_structure(&self, structure_id: &StructureId) -> Option<&Structure> {
+        self.structures.get(structure_id)
+    }
+
+    pub fn get_structure_iter(&self) -> hash_map::Iter<'_, StructureId, Structure> {
+        self.structures.iter()
+    }
+
+    /// The gate `passage` goes through, if it goes through one.
+    pub fn gate_of(&self, passage: ConnectionId) -> Option<&Structure> {
+        self.structures
+            .values()
+            .find(|structure| structure.passage == Some(passage))
+    }
+
+    /// The structure that isn't breached yet standing at `pos`, if any. Levels don't matter,
+    /// a structure reaches from the ground to its top.
+    fn structure_at(&self, pos: Position) -> Option<StructureId> {
+        self.structures
+            .values()
+            .filter(|structure| !structure.is_breached() && structure.contains(pos))
+            .map(|structure| structure.id)
+            .min()
+    }
+
+    /// Rolls a hit on a structure. Reports it when it gets to a worse state, and clears the
+    /// way through once it is breached.
+    fn damage_structure(&mut self, structure_id: StructureId, damage: u8, damage_type: DamageType) {
+        let structure = self
+            .structures
+            .get_mut(&structure_id)
+            .expect("Bad structure id");
+        let old_state = structure.state();
+        if !structure.hit(&mut self.rng, damage, damage_type) {
+            return;
+        }
+        let state = structure.state();
+        if state == old_state {
+            return;
+        }
+        self.events.push(BattleEvent::StructureDamaged {
+            structure_id,
+            state,
+        });
+        if state == StructureState::Breached {
+            self.breach(structure_id);
+        }
+    }
+
+    /// Opens the way through a structure with no hp left, for walking and for sight. The
+    /// companies sent to break it are done.
+    fn breach(&mut self, structure_id: StructureId) {
+        let structure = self.structures[&structure_id].clone();
+        self.nav
+            .remove_obstacle(&self.terrain, Level::Ground, structure.min, structure.max);
+        if let Some(passage) = structure.passage {
+            self.set_connection_open(passage, true);
+        }
+        for company in self.companies.values_mut() {
+            if company.target == Some(structure_id) {
+                company.target = None;
+            }
+        }
+    }
+
+    pub fn fatigue_rules(&self) -> &FatigueRules {
+        &self.fatigue_rules
+    }
+
+    /// Replaces the default fatigue rules, usually by the ones in `assets/fatigue.json`.
+    pub fn set_fatigue_rules(&mut self, rules: FatigueRules) {
+        self.fatigue_rules = rules;
+    }
+
+    pub fn deployment_zone(&self, player_id: PlayerId) -> Option<&Zone> {
+        self.deployment_zones.get(&player_id)
+    }
+
+    pub fn set_deployment_zone(&mut self, player_id: PlayerId, zone: Zone) {
+        self.deployment_zones.insert(player_id, zone);
+    }
+
+    /// Puts the company at `pos`, facing `rot`, with its soldiers standing in their slots.
+    /// Only before the first step, and only if every soldier ends up inside the zone of its
+    /// player, on walkable ground and out of the way of other companies.
+    pub fn deploy_company(
+        &mut self,
+        comp_id: CompId,
+        pos: Position,
+        rot: Rad<f32>,
+    ) -> Result<(), DeploymentError> {
+        if self.tick_count > 0 {
+            return Err(DeploymentError::BattleStarted);
+        }
+        let slots: Vec<Position> = {
+            let company = self.companies.get(&comp_id).expect("Bad company id");
+            company
+                .formation
+                .slots(company.members.len())
+                .into_iter()
+                .map(|local| pos.offset(local, rot))
+                .collect()
+        };
+        self.check_deployment(comp_id, &slots)?;
+        {
+            let company = self.companies.get_mut(&comp_id).unwrap();
+            company.pos = pos;
+            company.rot = rot;
+            company.vel = Vector2::new(0.0, 0.0);
+            company.order = None;
+            company.path.clear();
+        }
+        let members = self.companies[&comp_id].members.clone();
+        for (id, slot) in members.into_iter().zip(slots) {
+            let indiv = self
+                .indivs
+                .get_mut(&id)
+                .expect("Company member without indiv");
+            indiv.pos = slot;
+            indiv.rot = rot;
+            indiv.vel = Vector2::new(0.0, 0.0);
+            indiv.order = None;
+            indiv.target = Some(MoveOrder {
+                dest: slot,
+                facing: rot,
+            });
+            indiv.path.clear();
+            indiv.path_goal = None;
+            self.grid.update(id, slot);
+        }
+        Ok(())
+    }
+
+    /// Whether the company stands where it could have been deployed, see `deploy_company`.
+    pub fn check_company_deployment(&self, comp_id: CompId) -> Result<(), DeploymentError> {
+        let slots = self
+            .companies
+            .get(&comp_id)
+            .expect("Bad company id")
+            .slot_positions();
+        self.check_deployment(comp_id, &slots)
+    }
+
+    fn check_deployment(&self, comp_id: CompId, slots: &[Position]) -> Result<(), DeploymentError> {
+        let player_id = self.companies[&comp_id].player_id;
+        let zone = self.deployment_zones.get(&player_id);
+        let (w, h) = (self.map_size.w as f64, self.map_size.h as f64);
+        for &slot in slots {
+            let on_map = slot.x >= 0.0 && slot.y >= 0.0 && slot.x <= w && slot.y <= h;
+            if !on_map || !zone.is_none_or(|zone| zone.contains(slot)) {
+                return Err(DeploymentError::OutsideZone);
+            }
+            if !self.nav.is_walkable(slot) {
+                return Err(DeploymentError::Blocked);
+            }
+            for id in self.grid.query_radius(slot, DEPLOY_GAP) {
+                let other = self.indivs[&id].comp_id;
+                if other != comp_id {
+                    return Err(DeploymentError::Overlapping(other));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deploys every company of the player as close to the middle of the map as its zone
+    /// allows, facing the middle. Companies are placed in order of their ids; if one doesn't
+    /// fit, the ones after it aren't tried.
+    pub fn auto_deploy(&mut self, player_id: PlayerId) -> Result<(), DeploymentError> {
+        let (w, h) = (self.map_size.w as f64, self.map_size.h as f64);
+        let middle = Position::new(w / 2.0, h / 2.0);
+        let (min, max, start) = match self.deployment_zones.get(&player_id) {
+            Some(zone) => {
+                let (min, max) = zone.bounds();
+                (min, max, zone.centre())
+            }
+            None => (Position::new(0.0, 0.0), Position::new(w, h), middle),
+        };
+        let facing = Vector2::new(middle.x - start.x, middle.y - start.y);
+        let mut candidates = Vec::new();
+        let mut y = min.y.max(0.0).floor();
+        while y <= max.y.min(h) {
+            let mut x = min.x.max(0.0).floor();
+            while x <= max.x.min(w) {
+                candidates.push(Position::new(x, y));
+                x += 1.0;
+            }
+            y += 1.0;
+        }
+        candidates.sort_by(|a, b| {
+            a.dist(&middle)
+                .partial_cmp(&b.dist(&middle))
+                .unwrap()
+                .then(a.dist(&start).partial_cmp(&b.dist(&start)).unwrap())
+        });
+        let mut comp_ids: Vec<CompId> = self
+            .companies
+            .values()
+            .filter(|company| company.player_id == player_id)
+            .map(|company| company.id)
+            .collect();
+        comp_ids.sort();
+        for comp_id in comp_ids {
+            let rot = if facing.x == 0.0 && facing.y == 0.0 {
+                self.companies[&comp_id].rot
+            } else {
+                heading_of(facing)
+            };
+            if !candidates
+                .iter()
+                .any(|&pos| self.deploy_company(comp_id, pos, rot).is_ok())
+            {
+                return Err(DeploymentError::NoRoom(comp_id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries out `order` and records it, so the battle can be replayed.
+    pub fn give_order(&mut self, order: Order) {
+        self.carry_out(order);
+        self.orders.push(RecordedOrder {
+            tick: self.tick_count,
+            order,
+        });
+    }
+
+    fn carry_out(&mut self, order: Order) {
+        match order {
+            Order::SetFormation { comp_id, formation } => {
+                self.companies
+                    .get_mut(&comp_id)
+                    .expect("Bad company id")
+                    .formation = formation;
+            }
+            Order::MoveCompany {
+                comp_id,
+                dest,
+                facing,
+            } => {
+                if !self.companies[&comp_id].is_routing() {
+                    self.set_company_order(comp_id, MoveOrder { dest, facing });
+                    self.companies.get_mut(&comp_id).unwrap().target = None;
+                }
+            }
+            Order::Hold { comp_id } => {
+                let company = self.companies.get_mut(&comp_id).expect("Bad company id");
+                if !company.is_routing() {
+                    company.order = None;
+                    company.path.clear();
+                    company.vel = Vector2::new(0.0, 0.0);
+                    company.target = None;
+                }
+            }
+            Order::MoveIndiv {
+                indiv_id,
+                dest,
+                facing,
+            } => {
+                self.indivs.get_mut(&indiv_id).expect("Bad indiv id").order =
+                    Some(MoveOrder { dest, facing });
+            }
+            Order::RejoinCompany { indiv_id } => {
+                self.indivs.get_mut(&indiv_id).expect("Bad indiv id").order = None;
+            }
+            Order::AttackStructure {
+                comp_id,
+                structure_id,
+            } => {
+                let structure = self
+                    .structures
+                    .get(&structure_id)
+                    .expect("Bad structure id");
+                let company = self.companies.get(&comp_id).expect("Bad company id");
+                if company.is_routing() || structure.is_breached() {
+                    return;
+                }
+                // With the front rank right next to it
+                let slots = company.formation.slots(company.members.len());
+                let half_width = slots.iter().map(|slot| slot.x.abs()).fold(0.0, f64::max);
+                let front = slots.iter().map(|slot| slot.y).fold(0.0, f64::max);
+                let order = structure.approach(company.pos, half_width, front + STRIKE_DIST);
+                self.set_company_order(comp_id, order);
+                self.companies.get_mut(&comp_id).unwrap().target = Some(structure_id);
+            }
+            Order::SetController {
+                player_id,
+                controller,
+            } => self.change_controller(player_id, controller),
+        }
+    }
+
+    /// The commanders of the players the computer controls.
+    pub fn commanders(&self) -> &[Commander] {
+        &self.commanders
+    }
+
+    /// Hands `player_id` to a human or to the computer. Recorded like any order, since the
+    /// computer gives orders of its own from then on.
+    pub fn set_controller(&mut self, player_id: PlayerId, controller: Controller) {
+        self.give_order(Order::SetController {
+            player_id,
+            controller,
+        });
+    }
+
+    /// Keeps the commanders sorted by player, so they always think in the same order.
+    fn change_controller(&mut self, player_id: PlayerId, controller: Controller) {
+        self.players
+            .get_mut(&player_id)
+            .expect("Bad player id")
+            .controller = controller;
+        self.commanders
+            .retain(|commander| commander.player_id() != player_id);
+        if controller == Controller::Ai {
+            self.commanders.push(Commander::new(player_id));
+            self.commanders
+                .sort_by_key(|commander| commander.player_id());
+        }
+    }
+
+    /// Lets every commander give its orders. They aren't recorded: a replay runs the
+    /// commanders again.
+    fn command_ai(&mut self) {
+        let mut commanders = mem::take(&mut self.commanders);
+        for commander in &mut commanders {
+            for order in commander.think(self) {
+                self.carry_out(order);
+            }
+        }
+        self.commanders = commanders;
+    }
+
+    /// What `team` knows of its enemies, if it has looked around yet.
+    pub fn sight(&self, team: TeamId) -> Option<&Sight> {
+        self.sights.get(&team)
+    }
+
+    /// Whether `player_id` can see the soldier: its own and those of its allies always, enemies
+    /// only when its team has them in sight.
+    pub fn can_see(&self, player_id: PlayerId, indiv_id: IndivId) -> bool {
+        let indiv = self.indivs.get(&indiv_id).expect("Bad indiv id");
+        if self.are_allies(player_id, indiv.player_id) {
+            return true;
+        }
+        let team = self.players.get(&player_id).expect("Bad player id").team;
+        self.sights
+            .get(&team)
+            .is_some_and(|sight| sight.can_see(indiv_id))
+    }
+
+    /// Whether `player_id` can see the projectile: those of its own side always, enemy ones
+    /// only when its team has the shooter in sight.
+    pub fn can_see_projectile(&self, player_id: PlayerId, projectile: &Projectile) -> bool {
+        if self.are_allies(player_id, projectile.player_id) {
+            return true;
+        }
+        let team = self.players.get(&player_id).expect("Bad player id").team;
+        self.sights
+            .get(&team)
+            .is_some_and(|sight| sight.can_see(projectile.shooter_id))
+    }
+
+    /// Whether a soldier at `from` could see one at `to`, not taking sight range into account.
+    pub fn line_of_sight(&self, from: Position, to: Position) -> bool {
+        visibility::line_of_sight(&self.terrain, &self.nav, from, to)
+    }
+
+    /// Lets every team look around: which enemies it sees now, and which ghosts it can forget.
+    pub fn update_sight(&mut self) {
+        let mut teams: Vec<TeamId> = self.players.values().map(|player| player.team).collect();
+        teams.sort();
+        teams.dedup();
+        let mut indiv_ids: Vec<IndivId> = self.indivs.keys().cloned().collect();
+        indiv_ids.sort();
+        for team in teams {
+            let mut sight = self.sights.remove(&team).unwrap_or_default();
+            // Where the soldiers of the team look from, and how far they see
+            let eyes: Vec<(Position, f64)> = indiv_ids
+                .iter()
+                .map(|id| &self.indivs[id])
+                .filter(|indiv| self.players[&indiv.player_id].team == team)
+                .map(|indiv| {
+                    (
+                        indiv.pos,
+                        self.unit_types[indiv.type_id.id as usize].sight_range,
+                    )
+                })
+                .collect();
+            // Only the soldiers of the team near a ghost could see where it was
+            let max_range = eyes.iter().map(|&(_, range)| range).fold(0.0, f64::max);
+            let in_sight = |pos: Position| {
+                self.grid
+                    .query_radius(pos, max_range)
+                    .into_iter()
+                    .any(|id| {
+                        let indiv = &self.indivs[&id];
+                        let range = self.unit_types[indiv.type_id.id as usize].sight_range;
+                        self.players[&indiv.player_id].team == team
+                            && indiv.pos.dist(&pos) <= range
+                            && self.line_of_sight(indiv.pos, pos)
+                    })
+            };
+            // Only the enemies in range of an eye need a line of sight, the grid finds those
+            let mut seen: HashSet<IndivId> = HashSet::new();
+            for &(eye, range) in &eyes {
+                for id in self.grid.query_radius(eye, range) {
+                    let indiv = &self.indivs[&id];
+                    if self.players[&indiv.player_id].team != team
+                        && !seen.contains(&id)
+                        && self.line_of_sight(eye, indiv.pos)
+                    {
+                        seen.insert(id);
+                    }
+                }
+            }
+            let mut seen: Vec<IndivId> = seen.into_iter().collect();
+            seen.sort();
+            let visible: Vec<Sighting> = seen
+                .iter()
+                .map(|id| &self.indivs[id])
+                .map(|indiv| {
+                    let routing = self.companies[&indiv.comp_id].is_routing();
+                    Sighting::new(indiv, routing, self.tick_count)
+                })
+                .collect();
+            sight.update(visible, in_sight);
+            self.sights.insert(team, sight);
+        }
+    }
+
+    /// Every order given since the battlefield was made or loaded, in the order they were
+    /// given.
+    pub fn orders(&self) -> &[RecordedOrder] {
+        &self.orders
+    }
+
+    /// Forgets the orders given so far.
+    pub fn clear_orders(&mut self) {
+        self.orders.clear();
+    }
+
+    /// Puts the company in a new formation. The soldiers walk to their new slots.
+    pub fn set_formation(&mut self, comp_id: CompId, formation: Formation) {
+        self.give_order(Order::SetFormation { comp_id, formation });
+    }
+
+    /// Orders the company to march to `dest` and face `facing` once it gets there.
+    /// Routing companies don't listen.
+    pub fn order_company_move(&mut self, comp_id: CompId, dest: Position, facing: Rad<f32>) {
+        self.give_order(Order::MoveCompany {
+            comp_id,
+            dest,
+            facing,
+        });
+    }
+
+    /// Orders the company to stop where it stands. Routing companies don't listen.
+    pub fn order_company_hold(&mut self, comp_id: CompId) {
+        self.give_order(Order::Hold { comp_id });
+    }
+
+    /// Gives the company `order` and plans its way there. If there is no way, it heads
+    /// straight for it.
+    fn set_company_order(&mut self, comp_id: CompId, order: MoveOrder) {
+        let from = self.companies.get(&comp_id).expect("Bad company id").pos;
+        let path = self.nav.find_path(from, order.dest).unwrap_or_default();
+        let company = self.companies.get_mut(&comp_id).unwrap();
+        company.order = Some(order);
+        company.path = path;
+    }
+
+    /// Orders the company to march up to the structure and break it. Soldiers with shots left
+    /// shoot at it on the way.
+    pub fn order_company_attack(&mut self, comp_id: CompId, structure_id: StructureId) {
+        self.give_order(Order::AttackStructure {
+            comp_id,
+            structure_id,
+        });
+    }
+
+    /// Orders a single soldier to leave its slot and go to `dest`.
+    pub fn order_indiv_move(&mut self, indiv_id: IndivId, dest: Position, facing: Rad<f32>) {
+        self.give_order(Order::MoveIndiv {
+            indiv_id,
+            dest,
+            facing,
+        });
+    }
+
+    /// Sends a soldier with its own order back to its slot in the company.
+    pub fn order_indiv_rejoin(&mut self, indiv_id: IndivId) {
+        self.give_order(Order::RejoinCompany { indiv_id });
+    }
+
+    /// The seed the randomness of this battle started from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The ways to win the battle, in the order they were added.
+    pub fn victory_conditions(&self) -> &[VictoryCondition] {
+        &self.victory_conditions
+    }
+
+    /// Adds a way to win the battle. Without any the battle never ends.
+    pub fn add_victory_condition(&mut self, condition: VictoryCondition) {
+        self.victory_conditions.push(condition);
+        self.held_for.push(0.0);
+    }
+
+    /// How the battle ended, or `None` while it is still going on.
+    pub fn outcome(&self) -> Option<&BattleOutcome> {
+        self.outcome.as_ref()
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    /// Soldiers lost so far by every team that lost any.
+    pub fn casualties(&self) -> &HashMap<TeamId, u32> {
+        &self.casualties
+    }
+
+    /// Seconds of battle simulated so far.
+    pub fn duration(&self) -> f64 {
+        self.tick_count as f64 * TICK_TIME
+    }
+
+    /// Number of simulation steps done since the start of the battle.
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    /// Feeds `dtime` of real time into the simulation. The battle is advanced in
+    /// steps of exactly `TICK_TIME`; leftover time is kept for the next call.
+    /// This way the outcome only depends on the total time passed, not on how
+    /// it was split up over frames. Returns what happened during the steps done.
+    pub fn tick(&mut self, dtime: Time) -> Vec<BattleEvent> {
+        self.time_acc += dtime.n as f64;
+        let mut events = Vec::new();
+        let mut steps = 0;
+        while self.time_acc >= TICK_TIME {
+            self.time_acc -= TICK_TIME;
+            events.extend(self.step());
+            steps += 1;
+            if steps >= MAX_STEPS_PER_TICK {
+                self.time_acc = 0.0;
+                break;
+            }
+        }
+        events
+    }
+
+    /// Does a single simulation step of `TICK_TIME`. Returns what happened during it.
+    /// Once the battle is over nothing happens anymore.
+    pub fn step(&mut self) -> Vec<BattleEvent> {
+        if self.is_over() {
+            return Vec::new();
+        }
+        if self.tick_count.is_multiple_of(SIGHT_INTERVAL) {
+            self.update_sight();
+        }
+        if self.tick_count.is_multiple_of(THINK_INTERVAL) {
+            self.command_ai();
+        }
+        self.update_companies();
+        self.update_slots();
+        // Before any soldier hops through a connection, so the climb of the hop counts
+        let starts: HashMap<IndivId, Position> = self
+            .indivs
+            .iter()
+            .map(|(&id, indiv)| (id, indiv.pos))
+            .collect();
+        self.follow_paths();
+        for indiv in self.indivs.values_mut() {
+            let unit_type = &self.unit_types[indiv.type_id.id as usize];
+            let mut steering = unit_type.steering();
+            steering.max_speed *= self.terrain.speed_factor(indiv.pos, indiv.rot);
+            let fresh_speed = steering.max_speed;
+            steering.max_speed *= 1.0 - self.fatigue_rules.penalties(indiv.stamina).speed;
+            let old = indiv.pos;
+            indiv.update(&steering, TICK_TIME);
+            if self.nav.is_walkable(old) && !self.nav.is_walkable(indiv.pos) {
+                // Slide along whatever is in the way instead of walking into it
+                let (slide_x, slide_y) = (
+                    Position::on_level(indiv.pos.x, old.y, old.level),
+                    Position::on_level(old.x, indiv.pos.y, old.level),
+                );
+                if self.nav.is_walkable(slide_x) {
+                    indiv.pos = slide_x;
+                    indiv.vel.y = 0.0;
+                } else if self.nav.is_walkable(slide_y) {
+                    indiv.pos = slide_y;
+                    indiv.vel.x = 0.0;
+                } else {
+                    indiv.pos = old;
+                    indiv.vel = Vector2::new(0.0, 0.0);
+                }
+            }
+            self.grid.update(indiv.id, indiv.pos);
+            // Soldiers that can't move at all, like crews of an emplacement, are resting
+            let speed = if fresh_speed > 0.0 {
+                indiv.vel.x.hypot(indiv.vel.y) / fresh_speed
+            } else {
+                0.0
+            };
+            let exertion = Exertion {
+                fighting: indiv.attack_cooldown > 0.0,
+                speed,
+                climbed: indiv.pos.to_world_pos(&self.terrain).v.z
+                    - starts[&indiv.id].to_world_pos(&self.terrain).v.z,
+            };
+            indiv.stamina = self.fatigue_rules.stamina_after(
+                indiv.stamina,
+                &exertion,
+                unit_type.armor,
+                TICK_TIME,
+            );
+        }
+        self.fire_ranged();
+        self.update_projectiles();
+        self.resolve_melee();
+        self.attack_structures();
+        self.remove_dead();
+        self.update_morale();
+        self.tick_count += 1;
+        self.check_victory();
+        mem::take(&mut self.events)
+    }
+
+    fn morale_state_of(&self, indiv: &Indiv) -> MoraleState {
+        self.companies[&indiv.comp_id].morale.state
+    }
+
+    /// Time until `indiv` can attack again after an attack that takes `time`.
+    fn attack_delay(&self, indiv: &Indiv, time: f64) -> f64 {
+        if self.morale_state_of(indiv) == MoraleState::Wavering {
+            time * WAVERING_ATTACK_DELAY
+        } else {
+            time
+        }
+    }
+
+    /// The closest enemy within `range` of `indiv`, if any. Shots can go from one level to
+    /// another, so the enemy can be on any level.
+    pub fn nearest_enemy(&self, indiv: &Indiv, range: f64) -> Option<IndivId> {
+        self.nearest_enemy_where(indiv, range, |_| true)
+    }
+
+    /// The closest enemy `indiv` can fight in melee: in melee range, on the same level.
+    pub fn nearest_melee_enemy(&self, indiv: &Indiv) -> Option<IndivId> {
+        self.nearest_enemy_where(indiv, MELEE_RANGE, |other| {
+            other.pos.level == indiv.pos.level
+        })
+    }
+
+    fn nearest_enemy_where<F: Fn(&Indiv) -> bool>(
+        &self,
+        indiv: &Indiv,
+        range: f64,
+        filter: F,
+    ) -> Option<IndivId> {
+        self.grid.nearest(indiv.pos, range, |id| {
+            let other = &self.indivs[&id];
+            self.are_enemies(other.player_id, indiv.player_id) && filter(other)
+        })
+    }
+
+    /// Everyone within `radius` of `pos`, on any level, sorted by id.
+    pub fn indivs_in_radius(&self, pos: Position, radius: f64) -> Vec<IndivId> {
+        self.grid.query_radius(pos, radius)
+    }
+
+    /// Everyone inside the rectangle from `min` to `max`, on any level, sorted by id.
+    pub fn indivs_in_rect(&self, min: Position, max: Position) -> Vec<IndivId> {
+        self.grid.query_rect(min, max)
+    }
+
+    /// Lets every soldier that is ready and next to an enemy attack it. All attacks of a step
+    /// are rolled before any of them is applied, so it doesn't matter who goes first.
+    fn resolve_melee(&mut self) {
+        let mut ids: Vec<IndivId> = self.indivs.keys().cloned().collect();
+        ids.sort();
+        let mut attackers = Vec::new();
+        let mut wounded = Vec::new();
+        for id in &ids {
+            let attacker = &self.indivs[id];
+            if attacker.attack_cooldown > 0.0
+                || self.morale_state_of(attacker) == MoraleState::Routing
+            {
+                continue;
+            }
+            let target_id = match self.nearest_melee_enemy(attacker) {
+                Some(target_id) => target_id,
+                None => continue,
+            };
+            let defender = &self.indivs[&target_id];
+            let from_front = combat::is_from_front(defender.pos, defender.rot, attacker.pos);
+            let height_diff = attacker.pos.to_world_pos(&self.terrain).v.z
+                - defender.pos.to_world_pos(&self.terrain).v.z;
+            let attack_bonus = combat::height_bonus(height_diff)
+                + Rank::of(attacker.xp).attack_bonus()
+                - Rank::of(defender.xp).defence_bonus()
+                - self.fatigue_rules.penalties(attacker.stamina).attack
+                + self.fatigue_rules.penalties(defender.stamina).defence;
+            let result = combat::roll_attack(
+                &mut self.rng,
+                &self.unit_types[attacker.type_id.id as usize],
+                &self.unit_types[defender.type_id.id as usize],
+                from_front,
+                attack_bonus,
+            );
+            if result == AttackResult::Wound {
+                wounded.push((target_id, *id));
+            }
+            if result != AttackResult::Miss {
+                self.events.push(BattleEvent::IndivHit {
+                    indiv_id: target_id,
+                    attacker_id: *id,
+                    result,
+                });
+            }
+            attackers.push((*id, self.attack_delay(attacker, ATTACK_INTERVAL)));
+        }
+        for (id, delay) in attackers {
+            self.indivs.get_mut(&id).unwrap().attack_cooldown = delay;
+        }
+        for (id, attacker_id) in wounded {
+            self.wound(id, attacker_id);
+        }
+    }
+
+    /// Takes a hp from `indiv_id`. Whoever takes its last one gets the kill.
+    fn wound(&mut self, indiv_id: IndivId, attacker_id: IndivId) {
+        let indiv = self.indivs.get_mut(&indiv_id).unwrap();
+        indiv.hp -= 1;
+        if indiv.hp == 0 && self.indivs.contains_key(&attacker_id) {
+            self.give_xp(attacker_id, XP_PER_KILL);
+        }
+    }
+
+    /// Lets every soldier with a ranged weapon, a shot left and no enemy in melee range shoot at
+    /// the nearest enemy within range. Where the shot lands depends on the accuracy of the weapon.
+    fn fire_ranged(&mut self) {
+        let mut ids: Vec<IndivId> = self.indivs.keys().cloned().collect();
+        ids.sort();
+        let mut shots = Vec::new();
+        for id in &ids {
+            let shooter = &self.indivs[id];
+            let weapon_type = self.get_unit_type(shooter.type_id).weapon_type;
+            if !weapon_type.is_ranged()
+                || shooter.ammo == 0
+                || shooter.attack_cooldown > 0.0
+                || self.morale_state_of(shooter) == MoraleState::Routing
+            {
+                continue;
+            }
+            if self.nearest_melee_enemy(shooter).is_some() {
+                continue;
+            }
+            let stats = weapon_type.stats();
+            let target_pos = match self.nearest_enemy(shooter, stats.range) {
+                Some(target_id) => self.indivs[&target_id].pos,
+                None => match self.structure_in_range(shooter, stats.range) {
+                    Some(pos) => pos,
+                    None => continue,
+                },
+            };
+            let max_error = (1.0 - stats.accuracy) * shooter.pos.dist(&target_pos);
+            let error = max_error * self.rng.gen::<f64>().sqrt();
+            let angle = self.rng.gen_range(0.0, 2.0 * PI);
+            let to = Position {
+                x: target_pos.x + error * angle.cos(),
+                y: target_pos.y + error * angle.sin(),
+                level: target_pos.level,
+            };
+            let delay = self.attack_delay(shooter, stats.reload_time);
+            shots.push((*id, weapon_type, to, delay));
+        }
+        for (id, weapon_type, to, delay) in shots {
+            let projectile_id = ProjectileId {
+                id: self.next_projectile_id,
+            };
+            self.next_projectile_id += 1;
+            let shooter = self.indivs.get_mut(&id).unwrap();
+            shooter.ammo -= 1;
+            shooter.attack_cooldown = delay;
+            let projectile = Projectile::new(
+                projectile_id,
+                id,
+                shooter.player_id,
+                weapon_type,
+                shooter.pos,
+                to,
+            );
+            self.projectiles.insert(projectile_id, projectile);
+            self.events.push(BattleEvent::ProjectileFired {
+                projectile_id,
+                shooter_id: id,
+            });
+        }
+    }
+
+    /// The spot closest to `indiv` of the structure its company was ordered to break, if it is
+    /// within `range`.
+    fn structure_in_range(&self, indiv: &Indiv, range: f64) -> Option<Position> {
+        let structure_id = self.companies[&indiv.comp_id].target?;
+        let structure = &self.structures[&structure_id];
+        let spot = structure.closest_point(indiv.pos);
+        if spot.dist(&indiv.pos) <= range {
+            Some(Position::new(spot.x, spot.y))
+        } else {
+            None
+        }
+    }
+
+    /// Lets every soldier that is ready, on the ground and next to the structure its company
+    /// was ordered to break hit it. Fighting enemies comes first, see `resolve_melee`.
+    fn attack_structures(&mut self) {
+        let mut ids: Vec<IndivId> = self.indivs.keys().cloned().collect();
+        ids.sort();
+        for id in &ids {
+            let attacker = &self.indivs[id];
+            let structure_id = match self.companies[&attacker.comp_id].target {
+                Some(structure_id) => structure_id,
+                None => continue,
+            };
+            if attacker.attack_cooldown > 0.0
+                || attacker.pos.level != Level::Ground
+                || self.structures[&structure_id].dist(attacker.pos) > MELEE_RANGE
+            {
+                continue;
+            }
+            let delay = self.attack_delay(attacker, ATTACK_INTERVAL);
+            self.indivs.get_mut(id).unwrap().attack_cooldown = delay;
+            self.damage_structure(structure_id, MELEE_DAMAGE, DamageType::Blow);
+        }
+    }
+
+    /// Moves all projectiles along. The ones that land hit whoever stands closest to where they
+    /// came down, friend or foe, or else the structure they came down on.
+    fn update_projectiles(&mut self) {
+        let mut landed = Vec::new();
+        for (&id, projectile) in &mut self.projectiles {
+            projectile.update(TICK_TIME);
+            if projectile.has_landed() {
+                landed.push(id);
+            }
+        }
+        landed.sort();
+        for id in landed {
+            let projectile = self.projectiles.remove(&id).unwrap();
+            self.events.push(BattleEvent::ProjectileLanded {
+                projectile_id: id,
+                pos: projectile.to,
+            });
+            let target_id = match self.indiv_at(projectile.to, HIT_RADIUS, projectile.shooter_id) {
+                Some(target_id) => target_id,
+                None => {
+                    if let Some(structure_id) = self.structure_at(projectile.to) {
+                        let weapon_type = projectile.weapon_type;
+                        let damage = weapon_type.stats().damage;
+                        self.damage_structure(structure_id, damage, weapon_type.damage_type());
+                    }
+                    continue;
+                }
+            };
+            let defender = &self.indivs[&target_id];
+            let from_front = combat::is_from_front(defender.pos, defender.rot, projectile.from);
+            let result = combat::roll_projectile_hit(
+                &mut self.rng,
+                projectile.weapon_type.stats().damage,
+                &self.unit_types[defender.type_id.id as usize],
+                from_front,
+            );
+            if result == AttackResult::Wound {
+                self.wound(target_id, projectile.shooter_id);
+            }
+            self.events.push(BattleEvent::IndivHit {
+                indiv_id: target_id,
+                attacker_id: projectile.shooter_id,
+                result,
+            });
+        }
+    }
+
+    /// The indiv closest to `pos` within `radius` and on the same level, other than `exclude`.
+    fn indiv_at(&self, pos: Position, radius: f64, exclude: IndivId) -> Option<IndivId> {
+        self.grid.nearest(pos, radius, |id| {
+            id != exclude && self.indivs[&id].pos.level == pos.level
+        })
+    }
+
+    /// Takes soldiers without hp off the field. Companies without soldiers are disbanded.
+    fn remove_dead(&mut self) {
+        let mut dead: Vec<IndivId> = self
+            .indivs
+            .values()
+            .filter(|indiv| indiv.hp <= 0)
+            .map(|indiv| indiv.id)
+            .collect();
+        dead.sort();
+        for id in dead {
+            let indiv = self.indivs.remove(&id).unwrap();
+            self.grid.remove(id);
+            let team = self.players[&indiv.player_id].team;
+            *self.casualties.entry(team).or_insert(0) += 1;
+            self.events.push(BattleEvent::IndivDied {
+                indiv_id: id,
+                comp_id: indiv.comp_id,
+                player_id: indiv.player_id,
+            });
+            if let Some(company) = self.companies.get_mut(&indiv.comp_id) {
+                company.remove_member(id);
+                company.morale.on_casualty();
+            }
+        }
+        self.companies
+            .retain(|_, company| !company.members.is_empty());
+    }
+
+    /// Updates the morale of every company. Companies that break run for the nearest map edge,
+    /// companies that rally stop where they are.
+    fn update_morale(&mut self) {
+        let mut comp_ids: Vec<CompId> = self.companies.keys().cloned().collect();
+        comp_ids.sort();
+        let mut updates = Vec::new();
+        for comp_id in &comp_ids {
+            let company = &self.companies[comp_id];
+            let mut flanked = 0;
+            let mut fatigue_penalty = 0.0;
+            for id in &company.members {
+                let indiv = &self.indivs[id];
+                fatigue_penalty += self.fatigue_rules.penalties(indiv.stamina).morale;
+                if let Some(enemy_id) = self.nearest_melee_enemy(indiv) {
+                    let enemy_pos = self.indivs[&enemy_id].pos;
+                    if !combat::is_from_front(company.pos, company.rot, enemy_pos) {
+                        flanked += 1;
+                    }
+                }
+            }
+            let routing_friends = self
+                .companies
+                .values()
+                .filter(|other| {
+                    other.id != company.id
+                        && self.are_allies(other.player_id, company.player_id)
+                        && other.is_routing()
+                        && other.pos.dist(&company.pos) < ROUTING_FRIEND_DIST
+                })
+                .count();
+            let enemy_near = self
+                .grid
+                .nearest(company.pos, RALLY_DIST, |id| {
+                    self.are_enemies(self.indivs[&id].player_id, company.player_id)
+                })
+                .is_some();
+            let inputs = MoraleInputs {
+                strength: company.members.len(),
+                flanked: flanked as f64 / company.members.len() as f64,
+                routing_friends,
+                enemy_near,
+                fatigue_penalty: fatigue_penalty / company.members.len() as f64,
+            };
+            updates.push((*comp_id, inputs));
+        }
+        let mut broken = Vec::new();
+        for (comp_id, inputs) in updates {
+            let company = self.companies.get_mut(&comp_id).unwrap();
+            let was_routing = company.is_routing();
+            match company.morale.update(&inputs, TICK_TIME) {
+                Some(MoraleState::Routing) => broken.push(comp_id),
+                Some(_) if was_routing => {
+                    company.order = None;
+                    company.path.clear();
+                    self.events.push(BattleEvent::CompanyRallied { comp_id });
+                }
+                _ => {}
+            }
+        }
+        for comp_id in broken {
+            self.companies.get_mut(&comp_id).unwrap().target = None;
+            self.events.push(BattleEvent::CompanyRouted { comp_id });
+            let flee = self.nearest_edge(self.companies[&comp_id].pos);
+            self.set_company_order(comp_id, flee);
+        }
+    }
+
+    /// Ends the battle if one of the victory conditions is met. The first one in the list goes
+    /// first.
+    fn check_victory(&mut self) {
+        let mut result = None;
+        for i in 0..self.victory_conditions.len() {
+            let condition = self.victory_conditions[i];
+            let winner = match condition {
+                VictoryCondition::Annihilation => victory::last_team_standing(
+                    self.indivs
+                        .values()
+                        .map(|indiv| self.players[&indiv.player_id].team),
+                ),
+                VictoryCondition::AllEnemiesRouted => victory::last_team_standing(
+                    self.companies
+                        .values()
+                        .filter(|company| !company.is_routing())
+                        .map(|company| self.players[&company.player_id].team),
+                ),
+                VictoryCondition::HoldGate { gate, team, time } => {
+                    if self.holds_gate(gate, team) {
+                        self.held_for[i] += TICK_TIME;
+                    } else {
+                        self.held_for[i] = 0.0;
+                    }
+                    if self.held_for[i] >= time - 1e-9 {
+                        Some(Some(team))
+                    } else {
+                        None
+                    }
+                }
+                VictoryCondition::TimeLimit { time, winner } => {
+                    if self.duration() >= time - 1e-9 {
+                        Some(winner)
+                    } else {
+                        None
+                    }
+                }
+            };
+            if result.is_none() {
+                result = winner.map(|winner| (winner, condition));
+            }
+        }
+        if let Some((winner, condition)) = result {
+            for indiv in self.indivs.values_mut() {
+                indiv.xp = veterancy::add_xp(indiv.xp, XP_PER_BATTLE);
+            }
+            self.events.push(BattleEvent::BattleEnded { winner });
+            self.outcome = Some(BattleOutcome {
+                winner,
+                condition,
+                casualties: self.casualties.clone(),
+                duration: self.duration(),
+                veterans: self.veterans(),
+            });
+        }
+    }
+
+    /// The companies still on the field, as they would go into the next battle.
+    fn veterans(&self) -> Vec<VeteranCompany> {
+        let mut comp_ids: Vec<CompId> = self.companies.keys().cloned().collect();
+        comp_ids.sort();
+        comp_ids
+            .into_iter()
+            .map(|comp_id| {
+                let company = &self.companies[&comp_id];
+                let type_id = self.indivs[&company.members[0]].type_id;
+                VeteranCompany {
+                    player: self.players[&company.player_id].name.clone(),
+                    unit_type: self.unit_types[type_id.id as usize].name.clone(),
+                    xp: self.company_xp(comp_id),
+                }
+            })
+            .collect()
+    }
+
+    /// Whether soldiers of `team` stand at either end of `gate` and no enemies do.
+    fn holds_gate(&self, gate: ConnectionId, team: TeamId) -> bool {
+        let connection = self.nav.get_connection(&gate).expect("Bad connection id");
+        let mut held = false;
+        for &end in &[connection.a, connection.b] {
+            for id in self.grid.query_radius(end, HOLD_RADIUS) {
+                let indiv = &self.indivs[&id];
+                if indiv.pos.level != end.level {
+                    continue;
+                }
+                if self.players[&indiv.player_id].team == team {
+                    held = true;
+                } else {
+                    return false;
+                }
+            }
+        }
+        held
+    }
+
+    /// An order to the point on the map edge closest to `pos`, facing away from the map.
+    fn nearest_edge(&self, pos: Position) -> MoveOrder {
+        let w = self.map_size.w as f64;
+        let h = self.map_size.h as f64;
+        let candidates = [
+            (pos.x, Position::new(0.0, pos.y), Rad(PI as f32 / 2.0)),
+            (w - pos.x, Position::new(w, pos.y), Rad(-PI as f32 / 2.0)),
+            (pos.y, Position::new(pos.x, 0.0), Rad(PI as f32)),
+            (h - pos.y, Position::new(pos.x, h), Rad(0.0)),
+        ];
+        let mut best = candidates[0];
+        for &candidate in &candidates[1..] {
+            if candidate.0 < best.0 {
+                best = candidate;
+            }
+        }
+        MoveOrder {
+            dest: best.1,
+            facing: best.2,
+        }
+    }
+
+    /// Moves every company along its order, at the pace of its slowest soldier.
+    fn update_companies(&mut self) {
+        let indivs = &self.indivs;
+        let unit_types = &self.unit_types;
+        let fatigue_rules = &self.fatigue_rules;
+        for company in self.companies.values_mut() {
+            let slowest_speed = company
+                .members
+                .iter()
+                .map(|id| {
+                    let indiv = &indivs[id];
+                    let tired = 1.0 - fatigue_rules.penalties(indiv.stamina).speed;
+                    unit_types[indiv.type_id.id as usize].max_speed() * tired
+                })
+                .fold(f64::INFINITY, f64::min);
+            if slowest_speed.is_finite() {
+                let slope_factor = self.terrain.speed_factor(company.pos, company.rot);
+                company.update(slowest_speed * slope_factor, TICK_TIME);
+            }
+        }
+    }
+
+    /// Makes soldiers that can't walk straight to their target follow a path there instead.
+    /// Paths are only planned again once the target moved `REPATH_DIST` away from where the
+    /// path leads. Soldiers without a way to their target, for instance because their slot is
+    /// inside a wall, stay put.
+    fn follow_paths(&mut self) {
+        // Forget the flow fields no company is marching along any more
+        let nav = &self.nav;
+        let companies = &self.companies;
+        self.flow_fields.retain(|field| {
+            companies.values().any(|company| {
+                company
+                    .order
+                    .is_some_and(|order| field.leads_to(nav, order.dest))
+            })
+        });
+        let mut ids: Vec<IndivId> = self.indivs.keys().cloned().collect();
+        ids.sort();
+        for id in ids {
+            let (pos, target) = {
+                let indiv = &self.indivs[&id];
+                match indiv.target {
+                    Some(target) => (indiv.pos, target),
+                    None => continue,
+                }
+            };
+            if self.nav.line_walkable(pos, target.dest) {
+                let indiv = self.indivs.get_mut(&id).unwrap();
+                indiv.path.clear();
+                indiv.path_goal = None;
+                continue;
+            }
+            let indiv = &self.indivs[&id];
+            // With only the goal left the target must have moved, or it could be walked to
+            let stale = indiv.path.len() == 1
+                || indiv.path_goal.is_none_or(|goal| {
+                    goal.level != target.dest.level || goal.dist(&target.dest) > REPATH_DIST
+                });
+            if stale {
+                let path = self.plan_path(id, pos, target.dest);
+                let indiv = self.indivs.get_mut(&id).unwrap();
+                indiv.path = path;
+                indiv.path_goal = Some(target.dest);
+            }
+            let indiv = self.indivs.get_mut(&id).unwrap();
+            while let Some(waypoint) = indiv.path.first().cloned() {
+                let next_is_hop = indiv.path.get(1).is_some_and(|next| next.hop);
+                let reach_dist = if next_is_hop {
+                    CONNECTION_DIST
+                } else {
+                    WAYPOINT_DIST
+                };
+                let reached = waypoint.hop
+                    || (indiv.path.len() > 1 && indiv.pos.dist(&waypoint.pos) < reach_dist);
+                if !reached {
+                    break;
+                }
+                if waypoint.hop {
+                    let passable = self.nav.connections().values().any(|connection| {
+                        connection.ends(indiv.pos).is_some_and(|(_, exit)| {
+                            exit.dist(&waypoint.pos) < CONNECTION_DIST
+                                && exit.level == waypoint.pos.level
+                        })
+                    });
+                    if !passable {
+                        // Closed since the path was planned
+                        indiv.path.clear();
+                        indiv.path_goal = None;
+                        break;
+                    }
+                    indiv.pos = waypoint.pos;
+                    self.grid.update(id, waypoint.pos);
+                }
+                indiv.path.remove(0);
+            }
+            if indiv.path.len() > 1 {
+                let dest = indiv.path[0].pos;
+                let to_dest = Vector2::new(dest.x - indiv.pos.x, dest.y - indiv.pos.y);
+                indiv.target = Some(MoveOrder {
+                    dest,
+                    facing: heading_of(to_dest),
+                });
+            } else if indiv.path.is_empty() {
+                indiv.target = Some(MoveOrder {
+                    dest: indiv.pos,
+                    facing: indiv.rot,
+                });
+            }
+        }
+    }
+
+    /// The way for soldier `id` from `pos` to `dest`. Soldiers heading for their slot follow
+    /// the flow field to where their company marches, shared with the rest of the company,
+    /// until they can walk straight to the slot. Soldiers with an order of their own, and
+    /// those the field doesn't bring to their slot, get a path of their own.
+    fn plan_path(&mut self, id: IndivId, pos: Position, dest: Position) -> Path {
+        let indiv = &self.indivs[&id];
+        let march = match self.companies[&indiv.comp_id].order {
+            Some(order) if indiv.order.is_none() => Some(order.dest),
+            _ => None,
+        };
+        if let Some(goal) = march {
+            let nav = &self.nav;
+            let index = match self.flow_fields.iter().position(|f| f.leads_to(nav, goal)) {
+                Some(index) => index,
+                None => {
+                    self.flow_fields.push(nav.flow_field(goal));
+                    self.flow_fields.len() - 1
+                }
+            };
+            if let Some(path) = self.flow_fields[index].path_to(nav, pos, dest) {
+                return path;
+            }
+        }
+        self.nav.find_path(pos, dest).unwrap_or_default()
+    }
+
+    /// Points every soldier without an order of its own at its slot in the formation
+    /// of its company.
+    fn update_slots(&mut self) {
+        for company in self.companies.values() {
+            let facing = company.rot;
+            let routing = company.is_routing();
+            for (id, dest) in company.members.iter().zip(company.slot_positions()) {
+                let indiv = self
+                    .indivs
+                    .get_mut(id)
+                    .expect("Company member without indiv");
+                let slot = MoveOrder { dest, facing };
+                // Routing soldiers forget their own orders and run with the rest
+                indiv.target = if routing {
+                    Some(slot)
+                } else {
+                    indiv.order.or(Some(slot))
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{Battlefield, TICK_TIME};
+    use cgmath::Rad;
+    use core::combat::AttackResult;
+    use core::company::{CompId, Formation, FormationShape};
+    use core::deployment::{DeploymentError, Zone, DEPLOY_GAP};
+    use core::event::BattleEvent;
+    use core::level::{ConnectionKind, Level};
+    use core::morale::MoraleState;
+    use core::movement::angle_diff;
+    use core::player::{Controller, PlayerId, TeamId, MAX_PLAYERS};
+    use core::position::Position;
+    use core::save;
+    use core::structure::{StructureKind, StructureState};
+    use core::terrain::Terrain;
+    use core::unit::{test_unit_type, IndivId, UnitTypeId};
+    use core::veterancy::{Rank, XP_PER_BATTLE, XP_PER_KILL};
+    use core::victory::VictoryCondition;
+    use std::f32::consts::PI;
+    use types::{Size2, Time};
+
+    const COMP_ID: CompId = CompId { id: 0 };
+    const PLAYER: PlayerId = PlayerId { id: 0 };
+    const ENEMY: PlayerId = PlayerId { id: 1 };
+    const PLAYER_TEAM: TeamId = TeamId { id: 0 };
+    const ENEMY_TEAM: TeamId = TeamId { id: 1 };
+
+    /// A player and an enemy, and a single company of the player. Both are human, so no
+    /// commander gives orders the test didn't.
+    fn new_battlefield_with_seed(seed: u64) -> Battlefield {
+        let mut bf = Battlefield::new(vec![test_unit_type()], seed);
+        bf.add_player(
+            "Player",
+            "Rome",
+            PLAYER_TEAM,
+            [1.0, 0.8, 0.8, 1.0],
+            Controller::Human,
+        );
+        bf.add_player(
+            "Enemy",
+            "Rome",
+            ENEMY_TEAM,
+            [0.8, 0.8, 1.0, 1.0],
+            Controller::Human,
+        );
+        bf.add_company(
+            PLAYER,
+            UnitTypeId { id: 0 },
+            25,
+            Position::new(2.0, 2.0),
+            Rad(0.0),
+            Formation::new(FormationShape::Line, 5, 5),
+        );
+        bf
+    }
+
+    fn new_battlefield() -> Battlefield {
+        new_battlefield_with_seed(0)
+    }
+
+    fn assert_in_formation(bf: &Battlefield, comp_id: CompId) {
+        let company = bf.get_company(&comp_id).unwrap();
+        for (slot, id) in company.members.iter().enumerate() {
+            let indiv = bf.get_indiv(id).unwrap();
+            assert!(indiv.pos.dist(&company.slot_pos(slot)) < 0.1);
+            assert!(angle_diff(indiv.rot, company.rot).0.abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn test_tick_is_independent_of_frame_rate() {
+        let mut slow = new_battlefield();
+        let mut fast = new_battlefield();
+        for bf in &mut [&mut slow, &mut fast] {
+            bf.order_company_move(COMP_ID, Position::new(3.0, 2.5), Rad(0.0));
+        }
+        for _ in 0..30 {
+            slow.tick(Time { n: 1.0 / 30.0 });
+        }
+        for _ in 0..120 {
+            fast.tick(Time { n: 1.0 / 120.0 });
+        }
+        assert_eq!(slow.tick_count(), fast.tick_count());
+        let a = slow.get_indiv(&IndivId { id: 0 }).unwrap().pos;
+        let b = fast.get_indiv(&IndivId { id: 0 }).unwrap().pos;
+        assert!((a.x - b.x).abs() < 1e-9 && (a.y - b.y).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tick_keeps_leftover_time() {
+        let mut bf = new_battlefield();
+        bf.tick(Time {
+            n: (TICK_TIME * 0.6) as f32,
+        });
+        assert_eq!(bf.tick_count(), 0);
+        bf.tick(Time {
+            n: (TICK_TIME * 0.6) as f32,
+        });
+        assert_eq!(bf.tick_count(), 1);
+    }
+
+    #[test]
+    fn test_tick_limits_catch_up() {
+        let mut bf = new_battlefield();
+        bf.tick(Time { n: 60.0 });
+        assert_eq!(bf.tick_count(), super::MAX_STEPS_PER_TICK as u64);
+    }
+
+    #[test]
+    fn test_allegiance_follows_teams() {
+        let mut bf = new_battlefield();
+        let ally = bf.add_player(
+            "Ally",
+            "Rome",
+            TeamId { id: 0 },
+            [1.0, 1.0, 1.0, 1.0],
+            Controller::Ai,
+        );
+        assert!(bf.are_allies(PLAYER, PLAYER));
+        assert!(bf.are_allies(PLAYER, ally));
+        assert!(bf.are_enemies(ally, ENEMY));
+        assert!(bf.get_player(&PLAYER).unwrap().is_human());
+        assert!(!bf.get_player(&ally).unwrap().is_human());
+    }
+
+    #[test]
+    fn test_max_players_fit() {
+        let mut bf = Battlefield::new(vec![test_unit_type()], 0);
+        for _ in 0..MAX_PLAYERS {
+            bf.add_player("P", "Rome", TeamId { id: 0 }, [1.0; 4], Controller::Ai);
+        }
+        assert_eq!(bf.get_player_iter().count(), MAX_PLAYERS);
+    }
+
+    #[test]
+    fn test_soldiers_hold_slots_when_company_turns() {
+        let mut bf = new_battlefield();
+        bf.order_company_move(COMP_ID, Position::new(2.0, 2.0), Rad(PI));
+        for _ in 0..(20 * 20) {
+            bf.step();
+        }
+        assert_in_formation(&bf, COMP_ID);
+        // Front left is now where the back right used to be
+        let front_left = bf.get_indiv(&IndivId { id: 0 }).unwrap().pos;
+        assert!(front_left.dist(&Position::new(4.0, 0.0)) < 0.1);
+    }
+
+    #[test]
+    fn test_company_marches_to_order() {
+        let mut bf = new_battlefield();
+        let dest = Position::new(-20.0, 15.0);
+        bf.order_company_move(COMP_ID, dest, Rad(PI / 2.0));
+        for _ in 0..(20 * 60) {
+            bf.step();
+        }
+        let company = bf.get_company(&COMP_ID).unwrap();
+        assert!(company.pos.dist(&dest) < 0.1);
+        assert!(angle_diff(company.rot, Rad(PI / 2.0)).0.abs() < 0.001);
+        assert_in_formation(&bf, COMP_ID);
+    }
+
+    #[test]
+    fn test_indiv_order_overrides_slot() {
+        let mut bf = new_battlefield();
+        let id = IndivId { id: 3 };
+        let dest = Position::new(10.0, 10.0);
+        bf.order_indiv_move(id, dest, Rad(0.0));
+        for _ in 0..(20 * 20) {
+            bf.step();
+        }
+        assert!(bf.get_indiv(&id).unwrap().pos.dist(&dest) < 0.1);
+        bf.order_indiv_rejoin(id);
+        for _ in 0..(20 * 20) {
+            bf.step();
+        }
+        assert_in_formation(&bf, COMP_ID);
+    }
+
+    #[test]
+    fn test_company_climbs_stairs_to_walkway() {
+        let mut bf = new_battlefield();
+        bf.set_terrain(Terrain::flat(Size2 { w: 30, h: 30 }));
+        bf.add_walkable_area(
+            Level::Walkway,
+            Position::on_level(0.0, 13.0, Level::Walkway),
+            Position::on_level(8.0, 25.0, Level::Walkway),
+        );
+        bf.add_connection(
+            ConnectionKind::Stairs,
+            Position::new(2.0, 10.0),
+            Position::on_level(2.0, 14.0, Level::Walkway),
+        );
+        let dest = Position::on_level(2.0, 20.0, Level::Walkway);
+        bf.order_company_move(COMP_ID, dest, Rad(0.0));
+        for _ in 0..(20 * 60) {
+            bf.step();
+        }
+        assert_in_formation(&bf, COMP_ID);
+        for (_, indiv) in bf.get_indiv_iter() {
+            assert_eq!(indiv.pos.level, Level::Walkway);
+            assert!((indiv.pos.to_world_pos(bf.terrain()).v.z - 6.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_company_holds() {
+        let mut bf = new_battlefield();
+        bf.order_company_move(COMP_ID, Position::new(2.0, 30.0), Rad(0.0));
+        for _ in 0..(20 * 3) {
+            bf.step();
+        }
+        bf.order_company_hold(COMP_ID);
+        let pos = bf.get_company(&COMP_ID).unwrap().pos;
+        for _ in 0..(20 * 5) {
+            bf.step();
+        }
+        let company = bf.get_company(&COMP_ID).unwrap();
+        assert!(company.order.is_none());
+        assert!(company.pos.dist(&pos) < 1e-9);
+        assert_in_formation(&bf, COMP_ID);
+    }
+
+    #[test]
+    fn test_company_walks_around_wall() {
+        let mut bf = new_battlefield();
+        bf.set_terrain(Terrain::flat(Size2 { w: 30, h: 30 }));
+        // A wall across the field with a gap on the right
+        bf.add_obstacle(
+            Level::Ground,
+            Position::new(0.0, 10.0),
+            Position::new(19.9, 10.9),
+        );
+        let dest = Position::new(4.0, 20.0);
+        bf.order_company_move(COMP_ID, dest, Rad(0.0));
+        for _ in 0..(20 * 120) {
+            bf.step();
+            for (_, indiv) in bf.get_indiv_iter() {
+                let in_wall = indiv.pos.x < 19.5 && indiv.pos.y > 10.2 && indiv.pos.y < 10.7;
+                assert!(!in_wall, "{:?}", indiv.pos);
+            }
+        }
+        assert!(bf.get_company(&COMP_ID).unwrap().pos.dist(&dest) < 0.1);
+        assert_in_formation(&bf, COMP_ID);
+    }
+
+    #[test]
+    fn test_soldiers_stop_at_a_gate_that_closed() {
+        let mut bf = new_battlefield();
+        bf.set_terrain(Terrain::flat(Size2 { w: 30, h: 30 }));
+        bf.add_obstacle(
+            Level::Ground,
+            Position::new(0.0, 10.0),
+            Position::new(29.9, 10.9),
+        );
+        let gate = bf.add_connection(
+            ConnectionKind::GatePassage,
+            Position::new(15.5, 9.5),
+            Position::new(15.5, 11.5),
+        );
+        bf.order_company_move(COMP_ID, Position::new(15.0, 20.0), Rad(0.0));
+        let through = |bf: &Battlefield| {
+            bf.get_indiv_iter()
+                .filter(|&(_, indiv)| indiv.pos.y > 11.0)
+                .count()
+        };
+        for _ in 0..(20 * 60) {
+            bf.step();
+            if through(&bf) > 0 {
+                break;
+            }
+        }
+        let before = through(&bf);
+        assert!(before > 0 && before < 25);
+        bf.set_connection_open(gate, false);
+        for _ in 0..(20 * 60) {
+            bf.step();
+        }
+        assert_eq!(through(&bf), before);
+    }
+
+    #[test]
+    fn test_company_marches_slower_uphill() {
+        let mut flat = new_battlefield();
+        let mut hill = new_battlefield();
+        // Rises 1 m for every 5 m north
+        hill.set_terrain(Terrain::from_heights(
+            Size2 { w: 2, h: 2 },
+            50.0,
+            vec![0.0, 0.0, 10.0, 10.0],
+        ));
+        for bf in &mut [&mut flat, &mut hill] {
+            bf.order_company_move(COMP_ID, Position::new(2.0, 40.0), Rad(0.0));
+            for _ in 0..(20 * 10) {
+                bf.step();
+            }
+        }
+        let flat_y = flat.get_company(&COMP_ID).unwrap().pos.y;
+        let hill_y = hill.get_company(&COMP_ID).unwrap().pos.y;
+        assert!(hill_y < flat_y - 1.0, "{} {}", hill_y, flat_y);
+    }
+
+    #[test]
+    fn test_marching_tires_and_rest_recovers() {
+        let mut bf = new_battlefield();
+        bf.set_terrain(Terrain::flat(Size2 { w: 100, h: 100 }));
+        let average_stamina = |bf: &Battlefield| {
+            let total: f64 = bf.get_indiv_iter().map(|(_, i)| i.stamina).sum();
+            total / bf.get_indiv_iter().count() as f64
+        };
+        bf.order_company_move(COMP_ID, Position::new(2.0, 90.0), Rad(0.0));
+        for _ in 0..(20 * 20) {
+            bf.step();
+        }
+        let tired = average_stamina(&bf);
+        assert!(tired < 1.0);
+        let pos = bf.get_company(&COMP_ID).unwrap().pos;
+        bf.order_company_move(COMP_ID, pos, Rad(0.0));
+        for _ in 0..(20 * 30) {
+            bf.step();
+        }
+        assert!(average_stamina(&bf) > tired);
+    }
+
+    #[test]
+    fn test_climbing_a_ladder_tires() {
+        let mut flat = new_battlefield();
+        let mut ladder = new_battlefield();
+        for bf in &mut [&mut flat, &mut ladder] {
+            bf.set_terrain(Terrain::flat(Size2 { w: 30, h: 30 }));
+        }
+        ladder.add_walkable_area(
+            Level::Walkway,
+            Position::on_level(0.0, 11.0, Level::Walkway),
+            Position::on_level(8.0, 25.0, Level::Walkway),
+        );
+        ladder.add_connection(
+            ConnectionKind::Ladder,
+            Position::new(2.0, 10.0),
+            Position::on_level(2.0, 11.0, Level::Walkway),
+        );
+        let id = IndivId { id: 0 };
+        flat.order_indiv_move(id, Position::new(2.0, 16.0), Rad(0.0));
+        ladder.order_indiv_move(id, Position::on_level(2.0, 16.0, Level::Walkway), Rad(0.0));
+        for _ in 0..(20 * 20) {
+            flat.step();
+            ladder.step();
+        }
+        let climber = ladder.get_indiv(&id).unwrap();
+        assert_eq!(climber.pos.level, Level::Walkway);
+        assert!(climber.stamina < flat.get_indiv(&id).unwrap().stamina - 0.05);
+    }
+
+    #[test]
+    fn test_melee_kills_soldiers() {
+        let mut bf = new_battlefield();
+        let enemy = bf.add_company(
+            ENEMY,
+            UnitTypeId { id: 0 },
+            25,
+            Position::new(2.0, 7.0),
+            Rad(PI),
+            Formation::new(FormationShape::Line, 5, 5),
+        );
+        bf.order_company_move(COMP_ID, Position::new(2.0, 3.0), Rad(0.0));
+        bf.order_company_move(enemy, Position::new(2.0, 6.0), Rad(PI));
+        let start = bf.get_indiv_iter().count();
+        let mut died = 0;
+        let mut wounds = 0;
+        for _ in 0..(20 * 60) {
+            for event in bf.step() {
+                match event {
+                    BattleEvent::IndivDied { .. } => died += 1,
+                    BattleEvent::IndivHit {
+                        result: AttackResult::Wound,
+                        ..
+                    } => wounds += 1,
+                    _ => {}
+                }
+            }
+        }
+        let alive = bf.get_indiv_iter().count();
+        assert!(alive < start);
+        assert_eq!(died, start - alive);
+        assert!(wounds >= died * test_unit_type().hp as usize);
+        for (_, indiv) in bf.get_indiv_iter() {
+            assert!(indiv.hp > 0);
+        }
+        let members: usize = bf.get_company_iter().map(|(_, c)| c.members.len()).sum();
+        assert_eq!(members, alive);
+        // Killers that are still alive got their xp
+        let xp: usize = bf.get_indiv_iter().map(|(_, i)| i.xp as usize).sum();
+        assert!(xp > 0 && xp <= died * XP_PER_KILL as usize);
+    }
+
+    /// Two companies marching into each other, company 0 of the player and 1 of the enemy.
+    pub fn new_fight(seed: u64) -> Battlefield {
+        let mut bf = new_battlefield_with_seed(seed);
+        let enemy = bf.add_company(
+            ENEMY,
+            UnitTypeId { id: 0 },
+            25,
+            Position::new(2.0, 12.0),
+            Rad(PI),
+            Formation::new(FormationShape::Line, 5, 5),
+        );
+        bf.order_company_move(COMP_ID, Position::new(2.0, 5.0), Rad(0.0));
+        bf.order_company_move(enemy, Position::new(2.0, 8.0), Rad(PI));
+        bf
+    }
+
+    /// Who is where, and how hurt.
+    pub fn indiv_states(bf: &Battlefield) -> Vec<(IndivId, i8, f64, f64)> {
+        let mut states: Vec<(IndivId, i8, f64, f64)> = bf
+            .get_indiv_iter()
+            .map(|(&id, indiv)| (id, indiv.hp, indiv.pos.x, indiv.pos.y))
+            .collect();
+        states.sort_by_key(|s| s.0);
+        states
+    }
+
+    #[test]
+    fn test_same_seed_gives_same_battle() {
+        let fight = |seed| {
+            let mut bf = new_fight(seed);
+            for _ in 0..(20 * 30) {
+                bf.step();
+            }
+            indiv_states(&bf)
+        };
+        assert_eq!(fight(7), fight(7));
+        assert_ne!(fight(7), fight(8));
+    }
+
+    #[test]
+    fn test_loaded_battle_goes_on_the_same() {
+        let mut bf = new_fight(3);
+        for _ in 0..(20 * 10) {
+            bf.step();
+        }
+        let mut file = Vec::new();
+        save::write_battle(&mut file, &bf).unwrap();
+        let mut loaded = save::read_battle(&mut &file[..]).unwrap();
+        assert_eq!(loaded.tick_count(), bf.tick_count());
+        assert_eq!(indiv_states(&loaded), indiv_states(&bf));
+        for _ in 0..(20 * 20) {
+            bf.step();
+            loaded.step();
+        }
+        assert_eq!(indiv_states(&loaded), indiv_states(&bf));
+    }
+
+    #[test]
+    fn test_ranged_soldiers_fire_projectiles() {
+        let mut bf = new_battlefield();
+        bf.add_company(
+            ENEMY,
+            UnitTypeId { id: 0 },
+            25,
+            Position::new(2.0, 17.0),
+            Rad(PI),
+            Formation::new(FormationShape::Line, 5, 5),
+        );
+        let events = bf.step();
+        // The front ranks are in range of each other
+        let in_flight = bf.get_projectile_iter().count();
+        assert!(in_flight > 0);
+        let fired = events
+            .iter()
+            .filter(|event| matches!(**event, BattleEvent::ProjectileFired { .. }))
+            .count();
+        assert_eq!(fired, in_flight);
+        for (_, projectile) in bf.get_projectile_iter() {
+            let shooter = bf.get_indiv(&projectile.shooter_id).unwrap();
+            assert_eq!(shooter.ammo, test_unit_type().weapon_type.stats().ammo - 1);
+        }
+        for _ in 0..(20 * 5) {
+            bf.step();
+        }
+        assert_eq!(bf.get_projectile_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_broken_company_runs_for_map_edge() {
+        let mut bf = new_battlefield();
+        bf.add_company(
+            ENEMY,
+            UnitTypeId { id: 0 },
+            25,
+            Position::new(2.0, 12.0),
+            Rad(PI),
+            Formation::new(FormationShape::Line, 5, 5),
+        );
+        bf.companies.get_mut(&COMP_ID).unwrap().morale.value = 5.0;
+        let events = bf.step();
+        assert!(events.iter().any(|event| match *event {
+            BattleEvent::CompanyRouted { comp_id } => comp_id == COMP_ID,
+            _ => false,
+        }));
+        let company = bf.get_company(&COMP_ID).unwrap();
+        assert_eq!(company.morale.state, MoraleState::Routing);
+        let order = company.order.unwrap();
+        assert!(order.dest.dist(&Position::new(0.0, 2.0)) < 1e-9);
+        // It doesn't listen to orders anymore
+        bf.order_company_move(COMP_ID, Position::new(2.0, 10.0), Rad(0.0));
+        let company = bf.get_company(&COMP_ID).unwrap();
+        assert!(company.order.unwrap().dest.dist(&order.dest) < 1e-9);
+    }
+
+    #[test]
+    fn test_battle_ends_at_time_limit() {
+        let mut bf = new_battlefield();
+        bf.add_victory_condition(VictoryCondition::TimeLimit {
+            time: 1.0,
+            winner: Some(ENEMY_TEAM),
+        });
+        let mut ended = 0;
+        for _ in 0..30 {
+            for event in bf.step() {
+                if let BattleEvent::BattleEnded { winner } = event {
+                    assert_eq!(winner, Some(ENEMY_TEAM));
+                    ended += 1;
+                }
+            }
+        }
+        assert_eq!(ended, 1);
+        assert_eq!(bf.tick_count(), 20);
+        let outcome = bf.outcome().unwrap();
+        assert_eq!(outcome.winner, Some(ENEMY_TEAM));
+        assert!((outcome.duration - 1.0).abs() < 1e-9);
+        assert_eq!(outcome.casualties_of(PLAYER_TEAM), 0);
+    }
+
+    #[test]
+    fn test_survivors_take_xp_into_next_battle() {
+        let mut bf = new_battlefield();
+        bf.add_victory_condition(VictoryCondition::TimeLimit {
+            time: 1.0,
+            winner: None,
+        });
+        while !bf.is_over() {
+            bf.step();
+        }
+        let veterans = bf.outcome().unwrap().veterans.clone();
+        assert_eq!(veterans.len(), 1);
+        assert_eq!(veterans[0].player, "Player");
+        assert_eq!(veterans[0].xp, XP_PER_BATTLE);
+        let mut next = new_battlefield();
+        let recruit_morale = next.companies[&COMP_ID].morale.base;
+        assert_eq!(next.company_rank(COMP_ID), Rank::Recruit);
+        next.enlist_veterans(&veterans);
+        assert_eq!(next.company_rank(COMP_ID), Rank::Regular);
+        assert!(next.companies[&COMP_ID].morale.base > recruit_morale);
+    }
+
+    #[test]
+    fn test_promotion_raises_base_morale() {
+        let mut bf = new_battlefield();
+        let recruit_morale = bf.companies[&COMP_ID].morale.base;
+        for id in bf.companies[&COMP_ID].members.clone() {
+            bf.give_xp(id, XP_PER_BATTLE);
+        }
+        assert_eq!(bf.company_rank(COMP_ID), Rank::Regular);
+        assert_eq!(
+            bf.companies[&COMP_ID].morale.base,
+            recruit_morale + Rank::Regular.morale_bonus()
+        );
+    }
+
+    #[test]
+    fn test_routing_the_enemy_wins() {
+        let mut bf = new_battlefield();
+        bf.add_company(
+            ENEMY,
+            UnitTypeId { id: 0 },
+            25,
+            Position::new(2.0, 12.0),
+            Rad(PI),
+            Formation::new(FormationShape::Line, 5, 5),
+        );
+        bf.add_victory_condition(VictoryCondition::Annihilation);
+        bf.add_victory_condition(VictoryCondition::AllEnemiesRouted);
+        bf.step();
+        assert!(!bf.is_over());
+        bf.companies.get_mut(&COMP_ID).unwrap().morale.value = 5.0;
+        bf.step();
+        let outcome = bf.outcome().unwrap();
+        assert_eq!(outcome.winner, Some(ENEMY_TEAM));
+        assert_eq!(outcome.condition, VictoryCondition::AllEnemiesRouted);
+    }
+
+    #[test]
+    fn test_holding_a_gate_wins() {
+        let mut bf = new_battlefield();
+        bf.set_terrain(Terrain::flat(Size2 { w: 30, h: 30 }));
+        let gate = bf.add_connection(
+            ConnectionKind::GatePassage,
+            Position::new(2.0, 4.0),
+            Position::new(2.0, 6.0),
+        );
+        bf.add_victory_condition(VictoryCondition::HoldGate {
+            gate,
+            team: PLAYER_TEAM,
+            time: 2.0,
+        });
+        // A single tough enemy at the gate stops the clock
+        let enemy = bf.add_company(
+            ENEMY,
+            UnitTypeId { id: 0 },
+            1,
+            Position::new(2.0, 8.0),
+            Rad(0.0),
+            Formation::new(FormationShape::Line, 1, 1),
+        );
+        let enemy_soldier = bf.get_company(&enemy).unwrap().members[0];
+        bf.indivs.get_mut(&enemy_soldier).unwrap().hp = 100;
+        for _ in 0..(20 * 10) {
+            bf.step();
+        }
+        assert!(!bf.is_over());
+        bf.order_company_move(enemy, Position::new(25.0, 25.0), Rad(0.0));
+        for _ in 0..(20 * 30) {
+            bf.step();
+        }
+        let outcome = bf.outcome().unwrap();
+        assert_eq!(outcome.winner, Some(PLAYER_TEAM));
+        assert!(outcome.duration < 60.0);
+    }
+
+    #[test]
+    fn test_deployment_keeps_to_zones() {
+        let mut bf = new_battlefield();
+        bf.set_terrain(Terrain::flat(Size2 { w: 20, h: 20 }));
+        let corners = |y0, y1| {
+            Zone::new(vec![
+                Position::new(0.0, y0),
+                Position::new(20.0, y0),
+                Position::new(20.0, y1),
+                Position::new(0.0, y1),
+            ])
+        };
+        bf.set_deployment_zone(PLAYER, corners(0.0, 8.0));
+        bf.set_deployment_zone(ENEMY, corners(12.0, 20.0));
+        let enemy = bf.add_company(
+            ENEMY,
+            UnitTypeId { id: 0 },
+            25,
+            Position::new(10.0, 16.0),
+            Rad(PI),
+            Formation::new(FormationShape::Line, 5, 5),
+        );
+        assert_eq!(
+            bf.deploy_company(COMP_ID, Position::new(10.0, 9.0), Rad(0.0)),
+            Err(DeploymentError::OutsideZone)
+        );
+        let second = bf.add_company(
+            PLAYER,
+            UnitTypeId { id: 0 },
+            25,
+            Position::new(10.0, 4.0),
+            Rad(0.0),
+            Formation::new(FormationShape::Line, 5, 5),
+        );
+        assert_eq!(
+            bf.deploy_company(COMP_ID, Position::new(12.0, 4.0), Rad(0.0)),
+            Err(DeploymentError::Overlapping(second))
+        );
+        bf.deploy_company(COMP_ID, Position::new(3.0, 4.0), Rad(0.0))
+            .unwrap();
+        assert_in_formation(&bf, COMP_ID);
+        bf.add_obstacle(
+            Level::Ground,
+            Position::new(15.0, 0.0),
+            Position::new(20.0, 8.0),
+        );
+        assert_eq!(
+            bf.deploy_company(second, Position::new(17.0, 4.0), Rad(0.0)),
+            Err(DeploymentError::Blocked)
+        );
+        bf.auto_deploy(PLAYER).unwrap();
+        bf.auto_deploy(ENEMY).unwrap();
+        for (_, indiv) in bf.get_indiv_iter() {
+            let zone = bf.deployment_zone(indiv.player_id).unwrap();
+            assert!(zone.contains(indiv.pos));
+            for id in bf.indivs_in_radius(indiv.pos, DEPLOY_GAP) {
+                assert_eq!(bf.get_indiv(&id).unwrap().comp_id, indiv.comp_id);
+            }
+        }
+        // Both sides face the middle of the map
+        assert!(
+            angle_diff(bf.get_company(&COMP_ID).unwrap().rot, Rad(0.0))
+                .0
+                .abs()
+                < 0.001
+        );
+        assert!(
+            angle_diff(bf.get_company(&enemy).unwrap().rot, Rad(PI))
+                .0
+                .abs()
+                < 0.001
+        );
+        bf.step();
+        assert_eq!(
+            bf.deploy_company(COMP_ID, Position::new(3.0, 4.0), Rad(0.0)),
+            Err(DeploymentError::BattleStarted)
+        );
+    }
+
+    #[test]
+    fn test_enemies_out_of_sight_leave_ghosts() {
+        let mut bf = new_battlefield();
+        bf.set_terrain(Terrain::flat(Size2 { w: 40, h: 40 }));
+        bf.add_obstacle(
+            Level::Ground,
+            Position::new(0.0, 20.0),
+            Position::new(40.0, 21.0),
+        );
+        let enemy = bf.add_company(
+            ENEMY,
+            UnitTypeId { id: 0 },
+            4,
+            Position::new(10.0, 10.0),
+            Rad(PI),
+            Formation::new(FormationShape::Line, 2, 2),
+        );
+        let scout = bf.get_company(&enemy).unwrap().members[0];
+        bf.update_sight();
+        assert!(bf.can_see(PLAYER, scout));
+        assert!(bf.sight(PLAYER_TEAM).unwrap().ghosts().is_empty());
+        // From behind the wall the enemies are remembered where they were
+        bf.deploy_company(COMP_ID, Position::new(10.0, 30.0), Rad(PI))
+            .unwrap();
+        bf.update_sight();
+        assert!(!bf.can_see(PLAYER, scout));
+        let ghosts = bf.sight(PLAYER_TEAM).unwrap().ghosts();
+        assert_eq!(ghosts.len(), 4);
+        assert!(ghosts.iter().all(|ghost| ghost.pos.y < 12.0));
+        let player = bf.get_company(&COMP_ID).unwrap().members[0];
+        assert!(!bf.can_see(ENEMY, player));
+        // Until someone looks at the spot again and finds it empty
+        bf.deploy_company(enemy, Position::new(30.0, 4.0), Rad(PI))
+            .unwrap();
+        bf.deploy_company(COMP_ID, Position::new(10.0, 12.0), Rad(0.0))
+            .unwrap();
+        bf.update_sight();
+        assert!(bf.sight(PLAYER_TEAM).unwrap().ghosts().is_empty());
+        assert!(bf.can_see(PLAYER, scout));
+    }
+
+    #[test]
+    fn test_breaking_the_gate_opens_the_way() {
+        let mut bf = new_battlefield();
+        bf.set_terrain(Terrain::flat(Size2 { w: 40, h: 40 }));
+        bf.add_structure(
+            StructureKind::WallSegment,
+            Position::new(0.0, 20.0),
+            Position::new(18.0, 21.0),
+        );
+        let gate = bf.add_structure(
+            StructureKind::Gate,
+            Position::new(18.0, 20.0),
+            Position::new(22.0, 21.0),
+        );
+        bf.add_structure(
+            StructureKind::WallSegment,
+            Position::new(22.0, 20.0),
+            Position::new(40.0, 21.0),
+        );
+        let (outside, inside) = (Position::new(20.0, 10.0), Position::new(20.0, 30.0));
+        assert!(bf.find_path(outside, inside).is_none());
+        assert!(!bf.line_of_sight(outside, inside));
+        bf.order_company_attack(COMP_ID, gate);
+        let mut states = Vec::new();
+        for _ in 0..(20 * 300) {
+            for event in bf.step() {
+                if let BattleEvent::StructureDamaged {
+                    structure_id,
+                    state,
+                } = event
+                {
+                    assert_eq!(structure_id, gate);
+                    states.push(state);
+                }
+            }
+            if bf.get_structure(&gate).unwrap().is_breached() {
+                break;
+            }
+        }
+        assert_eq!(
+            states,
+            vec![StructureState::Damaged, StructureState::Breached]
+        );
+        assert!(bf.get_company(&COMP_ID).unwrap().target.is_none());
+        let passage = bf.get_structure(&gate).unwrap().passage.unwrap();
+        assert!(bf.nav().get_connection(&passage).unwrap().open);
+        assert!(bf.find_path(outside, inside).is_some());
+        assert!(bf.line_of_sight(outside, inside));
+    }
 }
@@ -0,0 +1,207 @@
+//! Melee combat between soldiers standing next to each other.
+//!
+//! Every `ATTACK_INTERVAL` seconds a soldier in melee range of an enemy attacks the nearest one.
+//! An attack goes through three rolls, each of which can stop it:
+//!
+//! 1. Hit: `p_hit = clamp(0.5 + 0.05 * (attack_skill - defence_skill), 0.05, 0.95)`.
+//!    Equal skills hit half the time; every point of difference is worth 5%.
+//! 2. Shield: only against attacks coming from the defender's front half.
+//!    `p_block = min(0.05 * shield, 0.75)`. A shield of 8 stops 40% of the hits from the front,
+//!    and none from the back or the flanks, which is what makes flanking pay off.
+//! 3. Armor: `p_wound = damage / (damage + armor)`. A blow with a damage equal to the armor
+//!    wounds half the time, twice the armor two thirds of the time.
+//!
+//! A wound takes one hp. So the expected number of hp an attack takes away is
+//! `p_hit * (1 - p_block) * p_wound`, see `expected_wounds`. Two legionaries (attack 6,
+//! defence 6, shield 8, armor 8, damage 8) fighting face to face get `0.5 * 0.6 * 0.5 = 0.15`,
+//! so with 3 hp a duel lasts 20 attacks, or half a minute.
+//!
+//! Fighting from higher ground helps: the attacker gets `height_bonus` added to its attack skill,
+//! one point for every `HEIGHT_PER_SKILL` m it stands above the defender, up to
+//! `MAX_HEIGHT_BONUS`. Fighting uphill costs the same.
+//!
+//! Projectiles skip the hit roll, the spot where they land already decides whether they hit.
+//! They do go through the shield and armor rolls, with the damage of the weapon they were shot
+//! from (see `weapon::WeaponStats`).
+
+use cgmath::{Rad, Vector2};
+use core::misc::clamp;
+use core::movement::{angle_diff, heading_of};
+use core::position::Position;
+use core::unit::UnitType;
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// Soldiers closer than this (in m) to an enemy are engaged in melee.
+pub const MELEE_RANGE: f64 = 1.5;
+
+/// Seconds between two attacks of the same soldier.
+pub const ATTACK_INTERVAL: f64 = 1.5;
+
+/// Damage of a melee blow.
+pub const MELEE_DAMAGE: u8 = 8;
+
+/// Height difference (in m) worth one point of attack skill.
+pub const HEIGHT_PER_SKILL: f64 = 0.5;
+pub const MAX_HEIGHT_BONUS: i32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum AttackResult {
+    Miss,
+    Blocked,
+    Deflected,
+    Wound,
+}
+
+pub fn hit_chance(attack_skill: i32, defence_skill: u8) -> f64 {
+    let diff = attack_skill - defence_skill as i32;
+    clamp(0.5 + 0.05 * diff as f64, 0.05, 0.95)
+}
+
+pub fn block_chance(shield: u8, from_front: bool) -> f64 {
+    if from_front {
+        (0.05 * shield as f64).min(0.75)
+    } else {
+        0.0
+    }
+}
+
+pub fn wound_chance(damage: u8, armor: u8) -> f64 {
+    if damage == 0 {
+        return 0.0;
+    }
+    damage as f64 / (damage as f64 + armor as f64)
+}
+
+/// Attack skill gained by an attacker standing `height_diff` m above its defender. Negative when
+/// attacking uphill.
+pub fn height_bonus(height_diff: f64) -> i32 {
+    clamp(
+        (height_diff / HEIGHT_PER_SKILL).trunc() as i32,
+        -MAX_HEIGHT_BONUS,
+        MAX_HEIGHT_BONUS,
+    )
+}
+
+/// Expected hp loss of `defender` from a single attack by `attacker`.
+pub fn expected_wounds(attacker: &UnitType, defender: &UnitType, from_front: bool) -> f64 {
+    hit_chance(attacker.attack_skill, defender.defence_skill)
+        * (1.0 - block_chance(defender.shield, from_front))
+        * wound_chance(MELEE_DAMAGE, defender.armor)
+}
+
+/// Rolls a single attack, following the model described at the top of this module.
+/// `attack_bonus` is added to the attack skill of the attacker, see `height_bonus`.
+pub fn roll_attack<R: Rng>(
+    rng: &mut R,
+    attacker: &UnitType,
+    defender: &UnitType,
+    from_front: bool,
+    attack_bonus: i32,
+) -> AttackResult {
+    let attack_skill = attacker.attack_skill + attack_bonus;
+    if !rng.gen_bool(hit_chance(attack_skill, defender.defence_skill)) {
+        AttackResult::Miss
+    } else if rng.gen_bool(block_chance(defender.shield, from_front)) {
+        AttackResult::Blocked
+    } else if !rng.gen_bool(wound_chance(MELEE_DAMAGE, defender.armor)) {
+        AttackResult::Deflected
+    } else {
+        AttackResult::Wound
+    }
+}
+
+/// Rolls whether a projectile that came down on `defender` hurts. Aiming is already done by
+/// where the projectile landed, so only the shield and armor rolls are left.
+pub fn roll_projectile_hit<R: Rng>(
+    rng: &mut R,
+    damage: u8,
+    defender: &UnitType,
+    from_front: bool,
+) -> AttackResult {
+    if rng.gen_bool(block_chance(defender.shield, from_front)) {
+        AttackResult::Blocked
+    } else if !rng.gen_bool(wound_chance(damage, defender.armor)) {
+        AttackResult::Deflected
+    } else {
+        AttackResult::Wound
+    }
+}
+
+/// Whether an attack from `attacker_pos` comes from the front half of a defender
+/// standing at `defender_pos`, facing `defender_rot`.
+pub fn is_from_front(
+    defender_pos: Position,
+    defender_rot: Rad<f32>,
+    attacker_pos: Position,
+) -> bool {
+    let to_attacker = Vector2::new(
+        attacker_pos.x - defender_pos.x,
+        attacker_pos.y - defender_pos.y,
+    );
+    angle_diff(defender_rot, heading_of(to_attacker)).0.abs() <= PI / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        block_chance, expected_wounds, height_bonus, hit_chance, is_from_front, roll_attack,
+        wound_chance, AttackResult,
+    };
+    use cgmath::Rad;
+    use core::position::Position;
+    use core::unit::test_unit_type;
+    use rand::prng::XorShiftRng;
+    use rand::SeedableRng;
+    use std::f32::consts::PI;
+
+    const EPS: f64 = 0.000_001;
+
+    #[test]
+    fn test_chances() {
+        assert!((hit_chance(6, 6) - 0.5).abs() < EPS);
+        assert!((hit_chance(100, 0) - 0.95).abs() < EPS);
+        assert!((hit_chance(0, 100) - 0.05).abs() < EPS);
+        assert!((block_chance(8, true) - 0.4).abs() < EPS);
+        assert!(block_chance(8, false) < EPS);
+        assert!((wound_chance(8, 8) - 0.5).abs() < EPS);
+        assert!(wound_chance(0, 8) < EPS);
+    }
+
+    #[test]
+    fn test_height_bonus() {
+        assert_eq!(height_bonus(0.2), 0);
+        assert_eq!(height_bonus(0.6), 1);
+        assert_eq!(height_bonus(-0.6), -1);
+        assert_eq!(height_bonus(6.0), 2);
+    }
+
+    #[test]
+    fn test_documented_duel() {
+        let legionary = test_unit_type();
+        let e = expected_wounds(&legionary, &legionary, true);
+        assert!((e - 0.15).abs() < EPS);
+    }
+
+    #[test]
+    fn test_rolls_follow_expectation() {
+        let legionary = test_unit_type();
+        let mut rng = XorShiftRng::from_seed([7; 16]);
+        let n = 20_000;
+        let wounds = (0..n)
+            .filter(|_| {
+                roll_attack(&mut rng, &legionary, &legionary, true, 0) == AttackResult::Wound
+            })
+            .count();
+        let rate = wounds as f64 / n as f64;
+        assert!((rate - 0.15).abs() < 0.01, "{}", rate);
+    }
+
+    #[test]
+    fn test_is_from_front() {
+        let pos = Position::new(0.0, 0.0);
+        assert!(is_from_front(pos, Rad(0.0), Position::new(0.0, 1.0)));
+        assert!(!is_from_front(pos, Rad(0.0), Position::new(0.0, -1.0)));
+        assert!(is_from_front(pos, Rad(PI), Position::new(0.0, -1.0)));
+    }
+}
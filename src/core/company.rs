@@ -0,0 +1,361 @@
+use cgmath::{Rad, Vector2};
+use core::morale::{Morale, MoraleState};
+use core::movement::{heading_of, steer, MoveOrder, Steering};
+use core::pathfinding::{Path, WAYPOINT_DIST};
+use core::player::PlayerId;
+use core::position::Position;
+use core::structure::StructureId;
+use core::unit::IndivId;
+
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CompId {
+    pub id: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum FormationShape {
+    Line,
+    Column,
+    Square,
+    Wedge,
+}
+
+/// The layout a company keeps its soldiers in.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Formation {
+    pub shape: FormationShape,
+    /// Files in the front rank. For a square the number of files along each face,
+    /// for a wedge the width at which it stops growing.
+    pub width: u32,
+    /// Ranks deep. For a square the thickness of each face.
+    pub depth: u32,
+    /// Distance between neighbouring soldiers
+    #[serde(default = "default_spacing")]
+    pub spacing: f64,
+}
+
+const DEFAULT_SPACING: f64 = 1.0;
+
+fn default_spacing() -> f64 {
+    DEFAULT_SPACING
+}
+
+impl Formation {
+    pub fn new(shape: FormationShape, width: u32, depth: u32) -> Formation {
+        assert!(width > 0 && depth > 0);
+        Formation {
+            shape,
+            width,
+            depth,
+            spacing: DEFAULT_SPACING,
+        }
+    }
+
+    /// A broad formation, four ranks deep.
+    pub fn line(count: usize) -> Formation {
+        let depth = 4;
+        Formation::new(FormationShape::Line, div_ceil(count, depth).max(1), depth)
+    }
+
+    /// A narrow formation for marching, four files wide.
+    pub fn column(count: usize) -> Formation {
+        let width = 4;
+        Formation::new(FormationShape::Column, width, div_ceil(count, width).max(1))
+    }
+
+    /// A hollow square, two ranks thick.
+    pub fn square(count: usize) -> Formation {
+        let depth = 2;
+        let mut width = 2 * depth;
+        while square_capacity(width, depth) < count {
+            width += 1;
+        }
+        Formation::new(FormationShape::Square, width, depth)
+    }
+
+    /// A triangle with its tip towards the enemy.
+    pub fn wedge(count: usize) -> Formation {
+        let mut depth = 1;
+        while depth * depth < count as u32 {
+            depth += 1;
+        }
+        Formation::new(FormationShape::Wedge, 2 * depth - 1, depth)
+    }
+
+    /// Returns the slot offsets for `count` soldiers, relative to the centre of the company.
+    /// x points to the right of the company, y points forward. Slot 0 is the front left
+    /// (or the tip of a wedge); the order of the slots never depends on anything but `count`.
+    pub fn slots(&self, count: usize) -> Vec<Vector2<f64>> {
+        let cells = match self.shape {
+            FormationShape::Line | FormationShape::Column => grid_cells(self.width, count),
+            FormationShape::Square => square_cells(self.width, self.depth, count),
+            FormationShape::Wedge => wedge_cells(self.width, count),
+        };
+        centre(&cells)
+            .into_iter()
+            .map(|v| v * self.spacing)
+            .collect()
+    }
+}
+
+fn div_ceil(a: usize, b: u32) -> u32 {
+    a.div_ceil(b as usize) as u32
+}
+
+fn square_capacity(width: u32, depth: u32) -> usize {
+    let inner = width.saturating_sub(2 * depth);
+    (width * width - inner * inner) as usize
+}
+
+/// Ranks of `width` files, front rank first. A partial last rank is centred.
+fn grid_cells(width: u32, count: usize) -> Vec<Vector2<f64>> {
+    let width = width as usize;
+    (0..count)
+        .map(|i| {
+            let rank = i / width;
+            let in_rank = (count - rank * width).min(width);
+            let file = (i % width) as f64 + (width - in_rank) as f64 / 2.0;
+            Vector2::new(file, -(rank as f64))
+        })
+        .collect()
+}
+
+/// Cells of a `width` x `width` square, `depth` cells thick, outer ring first.
+/// If the square is too small the rest stands in ranks inside it.
+fn square_cells(width: u32, depth: u32, count: usize) -> Vec<Vector2<f64>> {
+    let w = width as i32;
+    let mut cells: Vec<(i32, i32, i32)> = Vec::new();
+    for y in 0..w {
+        for x in 0..w {
+            let ring = x.min(y).min(w - 1 - x).min(w - 1 - y);
+            cells.push((ring, y, x));
+        }
+    }
+    cells.retain(|c| c.0 < depth as i32);
+    cells.sort();
+    let take = count.min(cells.len());
+    let mut result: Vec<_> = cells[..take]
+        .iter()
+        .map(|&(_, y, x)| Vector2::new(x as f64, -(y as f64)))
+        .collect();
+    // Anyone left over stands in the middle of the square
+    let inner = width.saturating_sub(2 * depth).max(1);
+    let offset = Vector2::new(depth as f64, -(depth as f64));
+    result.extend(
+        grid_cells(inner, count - take)
+            .into_iter()
+            .map(|v| v + offset),
+    );
+    result
+}
+
+/// Ranks growing by two files each, up to `width`.
+fn wedge_cells(width: u32, count: usize) -> Vec<Vector2<f64>> {
+    let width = width.max(1) as usize;
+    let mut cells = Vec::new();
+    let mut rank = 0;
+    while cells.len() < count {
+        let full = (2 * rank + 1).min(width);
+        let in_rank = (count - cells.len()).min(full);
+        for file in 0..in_rank {
+            let x = file as f64 - (in_rank - 1) as f64 / 2.0;
+            cells.push(Vector2::new(x, -(rank as f64)));
+        }
+        rank += 1;
+    }
+    cells
+}
+
+/// Moves the cells so the middle of their bounding box is at the origin.
+fn centre(cells: &[Vector2<f64>]) -> Vec<Vector2<f64>> {
+    if cells.is_empty() {
+        return Vec::new();
+    }
+    let (mut min, mut max) = (cells[0], cells[0]);
+    for c in cells {
+        min.x = min.x.min(c.x);
+        min.y = min.y.min(c.y);
+        max.x = max.x.max(c.x);
+        max.y = max.y.max(c.y);
+    }
+    let mid = (min + max) / 2.0;
+    cells.iter().map(|&c| c - mid).collect()
+}
+
+/// A group of soldiers fighting as one. This is what the player gives orders to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Company {
+    pub id: CompId,
+    pub player_id: PlayerId,
+    pub pos: Position,
+    pub rot: Rad<f32>,
+    pub vel: Vector2<f64>,
+    pub formation: Formation,
+    /// The members of this company. A soldier's index in here is its slot in the formation.
+    pub members: Vec<IndivId>,
+    pub order: Option<MoveOrder>,
+    /// The way to `order`, if it can't be reached in a straight line
+    pub path: Path,
+    pub morale: Morale,
+    /// The structure it was ordered to break, until it is breached
+    pub target: Option<StructureId>,
+}
+
+/// The company marches slower than its slowest soldier, so stragglers can catch up.
+const MARCH_SPEED_FACTOR: f64 = 0.8;
+
+impl Company {
+    /// How the company as a whole moves, given the top speed of its slowest soldier.
+    /// A company wheels much slower than a single soldier turns.
+    /// Routing companies don't bother to wait for stragglers.
+    pub fn steering(&self, slowest_speed: f64) -> Steering {
+        let speed_factor = if self.is_routing() {
+            1.0
+        } else {
+            MARCH_SPEED_FACTOR
+        };
+        Steering {
+            max_speed: slowest_speed * speed_factor,
+            accel: 1.0,
+            turn_rate: 0.5,
+            sidestep_dist: 5.0,
+            sidestep_factor: 0.5,
+        }
+    }
+
+    /// Moves the company one step along its path towards its order. The company passes
+    /// through connections at once; its soldiers still have to walk through them.
+    pub fn update(&mut self, slowest_speed: f64, dt: f64) {
+        let order = match self.order {
+            Some(order) => order,
+            None => return,
+        };
+        while self.path.len() > 1
+            && (self.path[0].hop || self.pos.dist(&self.path[0].pos) < WAYPOINT_DIST)
+        {
+            if self.path[0].hop {
+                self.pos = self.path[0].pos;
+            }
+            self.path.remove(0);
+        }
+        let target = if self.path.len() > 1 {
+            let dest = self.path[0].pos;
+            MoveOrder {
+                dest,
+                facing: heading_of(Vector2::new(dest.x - self.pos.x, dest.y - self.pos.y)),
+            }
+        } else {
+            order
+        };
+        let steering = self.steering(slowest_speed);
+        steer(
+            &mut self.pos,
+            &mut self.rot,
+            &mut self.vel,
+            &target,
+            &steering,
+            dt,
+        );
+    }
+
+    pub fn is_routing(&self) -> bool {
+        self.morale.state == MoraleState::Routing
+    }
+
+    /// The position the soldier in `slot` should be standing at.
+    pub fn slot_pos(&self, slot: usize) -> Position {
+        self.slot_positions()[slot]
+    }
+
+    /// The positions all members should be standing at, in the order of `members`.
+    pub fn slot_positions(&self) -> Vec<Position> {
+        self.formation
+            .slots(self.members.len())
+            .into_iter()
+            .map(|local| self.pos.offset(local, self.rot))
+            .collect()
+    }
+
+    /// Removes a soldier from the company. The others close ranks.
+    pub fn remove_member(&mut self, indiv_id: IndivId) {
+        self.members.retain(|&id| id != indiv_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompId, Company, Formation, FormationShape};
+    use cgmath::{Rad, Vector2};
+    use core::morale::Morale;
+    use core::player::PlayerId;
+    use core::position::Position;
+    use core::unit::IndivId;
+    use std::f32::consts::PI;
+
+    const EPS: f64 = 0.001;
+
+    fn assert_distinct(formation: &Formation, count: usize) {
+        let slots = formation.slots(count);
+        assert_eq!(slots.len(), count);
+        for i in 0..slots.len() {
+            for j in (i + 1)..slots.len() {
+                let d = slots[i] - slots[j];
+                assert!(
+                    d.x.abs() > EPS || d.y.abs() > EPS,
+                    "{:?}: {} == {}",
+                    formation,
+                    i,
+                    j
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_all_shapes_give_distinct_slots() {
+        for &count in &[1, 7, 25, 80, 120] {
+            assert_distinct(&Formation::line(count), count);
+            assert_distinct(&Formation::column(count), count);
+            assert_distinct(&Formation::square(count), count);
+            assert_distinct(&Formation::wedge(count), count);
+        }
+    }
+
+    #[test]
+    fn test_line_is_wide_and_column_is_deep() {
+        let line = Formation::line(80).slots(80);
+        let column = Formation::column(80).slots(80);
+        let extent = |s: &[Vector2<f64>]| {
+            let w = s.iter().map(|v| v.x).fold(0.0, f64::max) * 2.0;
+            let d = s.iter().map(|v| v.y).fold(0.0, f64::max) * 2.0;
+            (w, d)
+        };
+        let (lw, ld) = extent(&line);
+        let (cw, cd) = extent(&column);
+        assert!(lw > ld);
+        assert!(cd > cw);
+    }
+
+    #[test]
+    fn test_slots_turn_with_company() {
+        let company = Company {
+            id: CompId { id: 0 },
+            player_id: PlayerId { id: 0 },
+            pos: Position::new(10.0, 10.0),
+            rot: Rad(PI / 2.0),
+            vel: Vector2::new(0.0, 0.0),
+            formation: Formation::new(FormationShape::Line, 2, 1),
+            members: vec![IndivId { id: 0 }, IndivId { id: 1 }],
+            order: None,
+            path: Vec::new(),
+            morale: Morale::new(8, 2, 0.0),
+            target: None,
+        };
+        // Facing -x, the left file stands at the -y side
+        let left = company.slot_pos(0);
+        let right = company.slot_pos(1);
+        assert!((left.x - 10.0).abs() < EPS && (right.x - 10.0).abs() < EPS);
+        assert!(left.y < right.y);
+    }
+}
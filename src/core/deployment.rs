@@ -0,0 +1,361 @@
+//! Placing the armies before the battle. Every player may have a deployment zone, and has to
+//! put its companies inside it before the first step. Companies may not overlap, and must
+//! stand on ground their soldiers can walk on. Players without a zone may deploy anywhere on
+//! the map.
+//!
+//! The AI players are deployed automatically; the human players take turns placing their
+//! companies and saying they are ready, see `Deployment`.
+
+use core::battlefield::Battlefield;
+use core::company::CompId;
+use core::player::{Controller, PlayerId};
+use core::position::Position;
+use std::error::Error;
+use std::fmt;
+
+/// Room to keep between the soldiers of different companies, in m
+pub const DEPLOY_GAP: f64 = 1.5;
+
+/// How close to one of its soldiers a click has to be to pick up a company, in m
+pub const SELECT_DIST: f64 = 1.0;
+
+/// An area of the map, bounded by a polygon.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Zone {
+    pub points: Vec<Position>,
+}
+
+impl Zone {
+    pub fn new(points: Vec<Position>) -> Zone {
+        Zone { points }
+    }
+
+    /// Whether `pos` lies inside the polygon or on its edge. Levels are ignored.
+    pub fn contains(&self, pos: Position) -> bool {
+        let mut inside = false;
+        let n = self.points.len();
+        for i in 0..n {
+            let a = self.points[i];
+            let b = self.points[(i + n - 1) % n];
+            if on_edge(pos, a, b) {
+                return true;
+            }
+            if (a.y > pos.y) != (b.y > pos.y)
+                && pos.x < a.x + (b.x - a.x) * (pos.y - a.y) / (b.y - a.y)
+            {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// The smallest and largest corner of the rectangle around the zone.
+    pub fn bounds(&self) -> (Position, Position) {
+        let mut min = Position::new(f64::INFINITY, f64::INFINITY);
+        let mut max = Position::new(f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for point in &self.points {
+            min.x = min.x.min(point.x);
+            min.y = min.y.min(point.y);
+            max.x = max.x.max(point.x);
+            max.y = max.y.max(point.y);
+        }
+        (min, max)
+    }
+
+    /// The average of its corners.
+    pub fn centre(&self) -> Position {
+        let n = self.points.len().max(1) as f64;
+        Position::new(
+            self.points.iter().map(|p| p.x).sum::<f64>() / n,
+            self.points.iter().map(|p| p.y).sum::<f64>() / n,
+        )
+    }
+}
+
+/// Whether `pos` lies on the line from `a` to `b`, give or take rounding.
+fn on_edge(pos: Position, a: Position, b: Position) -> bool {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let (px, py) = (pos.x - a.x, pos.y - a.y);
+    let len2 = dx * dx + dy * dy;
+    let dot = px * dx + py * dy;
+    (px * dy - py * dx).abs() <= 1e-9 * len2.sqrt().max(1.0) && dot >= 0.0 && dot <= len2
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeploymentError {
+    /// Companies can only be deployed before the first step
+    BattleStarted,
+    /// A soldier would stand outside the zone of its player, or off the map
+    OutsideZone,
+    /// A soldier would stand where it can't walk
+    Blocked,
+    /// A soldier would stand too close to one of this company
+    Overlapping(CompId),
+    /// Automatic deployment found no place for this company
+    NoRoom(CompId),
+}
+
+impl fmt::Display for DeploymentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeploymentError::BattleStarted => write!(f, "The battle has already started"),
+            DeploymentError::OutsideZone => write!(f, "Not inside the deployment zone"),
+            DeploymentError::Blocked => write!(f, "The ground there can't be walked on"),
+            DeploymentError::Overlapping(comp_id) => {
+                write!(f, "Too close to company {}", comp_id.id)
+            }
+            DeploymentError::NoRoom(comp_id) => {
+                write!(f, "No room left in the zone for company {}", comp_id.id)
+            }
+        }
+    }
+}
+
+impl Error for DeploymentError {}
+
+/// A battle being deployed. The human players deploy one after the other.
+#[derive(Debug)]
+pub struct Deployment {
+    battlefield: Battlefield,
+    /// The human player deploying now, `None` once everyone is ready
+    player: Option<PlayerId>,
+    /// The company to place with the next click
+    selected: Option<CompId>,
+    /// Why the AI players couldn't deploy all their companies
+    ai_errors: Vec<(PlayerId, DeploymentError)>,
+}
+
+impl Deployment {
+    /// Deploys the AI players and lets the first human player start. Everyone gets a first look
+    /// at the enemy, see `Battlefield::update_sight`.
+    pub fn new(mut battlefield: Battlefield) -> Deployment {
+        let mut ai_errors = Vec::new();
+        for player_id in players_where(&battlefield, |controller| controller == Controller::Ai) {
+            // Companies that don't fit stay where the scenario put them
+            if let Err(err) = battlefield.auto_deploy(player_id) {
+                ai_errors.push((player_id, err));
+            }
+        }
+        battlefield.update_sight();
+        let player = players_where(&battlefield, |controller| controller == Controller::Human)
+            .into_iter()
+            .next();
+        Deployment {
+            battlefield,
+            player,
+            selected: None,
+            ai_errors,
+        }
+    }
+
+    pub fn battlefield(&self) -> &Battlefield {
+        &self.battlefield
+    }
+
+    /// The human player deploying now.
+    pub fn player(&self) -> Option<PlayerId> {
+        self.player
+    }
+
+    pub fn selected(&self) -> Option<CompId> {
+        self.selected
+    }
+
+    /// The AI players that couldn't deploy all their companies, and why.
+    pub fn ai_errors(&self) -> &[(PlayerId, DeploymentError)] {
+        &self.ai_errors
+    }
+
+    /// Picks up the company of the current player with a soldier near `pos`, or else puts the
+    /// company picked up before at `pos`, facing the same way as it did.
+    pub fn click(&mut self, pos: Position) -> Result<(), DeploymentError> {
+        let player = match self.player {
+            Some(player) => player,
+            None => return Ok(()),
+        };
+        let clicked = self
+            .battlefield
+            .indivs_in_radius(pos, SELECT_DIST)
+            .into_iter()
+            .map(|id| self.battlefield.get_indiv(&id).expect("Bad indiv id"))
+            .find(|indiv| indiv.player_id == player)
+            .map(|indiv| indiv.comp_id);
+        if clicked.is_some() {
+            self.selected = clicked;
+            return Ok(());
+        }
+        match self.selected {
+            Some(comp_id) => {
+                let rot = self
+                    .battlefield
+                    .get_company(&comp_id)
+                    .expect("Bad company id")
+                    .rot;
+                self.battlefield.deploy_company(comp_id, pos, rot)?;
+                self.battlefield.update_sight();
+                self.selected = None;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Deploys all companies of the current player automatically.
+    pub fn auto_deploy(&mut self) -> Result<(), DeploymentError> {
+        self.selected = None;
+        let result = match self.player {
+            Some(player) => self.battlefield.auto_deploy(player),
+            None => Ok(()),
+        };
+        self.battlefield.update_sight();
+        result
+    }
+
+    /// The current player is done. Returns whether everyone is, so the battle can start.
+    /// Refuses if one of its companies doesn't stand where it may be deployed.
+    pub fn ready(&mut self) -> Result<bool, DeploymentError> {
+        self.selected = None;
+        if let Some(current) = self.player {
+            for (&comp_id, company) in self.battlefield.get_company_iter() {
+                if company.player_id == current {
+                    self.battlefield.check_company_deployment(comp_id)?;
+                }
+            }
+            self.player = players_where(&self.battlefield, |controller| {
+                controller == Controller::Human
+            })
+            .into_iter()
+            .find(|&id| id > current);
+        }
+        Ok(self.player.is_none())
+    }
+
+    pub fn into_battlefield(self) -> Battlefield {
+        self.battlefield
+    }
+}
+
+/// The players with a controller for which `pred` holds, sorted by id.
+fn players_where<F: Fn(Controller) -> bool>(battlefield: &Battlefield, pred: F) -> Vec<PlayerId> {
+    let mut ids: Vec<PlayerId> = battlefield
+        .get_player_iter()
+        .filter(|&(_, player)| pred(player.controller))
+        .map(|(&id, _)| id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Deployment, DeploymentError, Zone};
+    use cgmath::Rad;
+    use core::battlefield::Battlefield;
+    use core::company::{CompId, Formation, FormationShape};
+    use core::player::{Controller, PlayerId, TeamId};
+    use core::position::Position;
+    use core::terrain::Terrain;
+    use core::unit::{test_unit_type, UnitTypeId};
+    use types::Size2;
+
+    #[test]
+    fn test_zone_contains() {
+        let zone = Zone::new(vec![
+            Position::new(0.0, 0.0),
+            Position::new(4.0, 0.0),
+            Position::new(0.0, 4.0),
+        ]);
+        assert!(zone.contains(Position::new(1.0, 1.0)));
+        assert!(!zone.contains(Position::new(3.0, 3.0)));
+        assert!(!zone.contains(Position::new(-1.0, 1.0)));
+        // The edges are part of it, all of them
+        assert!(zone.contains(Position::new(2.0, 0.0)));
+        assert!(zone.contains(Position::new(0.0, 4.0)));
+        assert!(zone.contains(Position::new(2.0, 2.0)));
+        assert!(!zone.contains(Position::new(5.0, 0.0)));
+    }
+
+    #[test]
+    fn test_players_take_turns() {
+        let mut bf = Battlefield::new(vec![test_unit_type()], 0);
+        bf.set_terrain(Terrain::flat(Size2 { w: 20, h: 20 }));
+        for (name, controller) in [("A", Controller::Human), ("B", Controller::Human)].iter() {
+            bf.add_player(name, "Rome", TeamId { id: 0 }, [1.0; 4], *controller);
+        }
+        let formation = Formation::new(FormationShape::Line, 3, 2);
+        for id in 0..2 {
+            bf.add_company(
+                PlayerId { id },
+                UnitTypeId { id: 0 },
+                6,
+                Position::new(5.0 + 10.0 * id as f64, 5.0),
+                Rad(0.0),
+                formation,
+            );
+        }
+        let mut deployment = Deployment::new(bf);
+        assert_eq!(deployment.player(), Some(PlayerId { id: 0 }));
+        // Clicking the other player's company picks up nothing
+        deployment.click(Position::new(15.0, 5.0)).unwrap();
+        assert_eq!(deployment.selected(), None);
+        deployment.click(Position::new(5.0, 5.0)).unwrap();
+        assert_eq!(deployment.selected(), Some(CompId { id: 0 }));
+        deployment.click(Position::new(5.0, 12.0)).unwrap();
+        let company = deployment
+            .battlefield()
+            .get_company(&CompId { id: 0 })
+            .unwrap();
+        assert_eq!((company.pos.x, company.pos.y), (5.0, 12.0));
+        assert_eq!(deployment.ready(), Ok(false));
+        assert_eq!(deployment.player(), Some(PlayerId { id: 1 }));
+        assert_eq!(deployment.ready(), Ok(true));
+    }
+
+    #[test]
+    fn test_ready_needs_every_company_deployed() {
+        let mut bf = Battlefield::new(vec![test_unit_type()], 0);
+        bf.set_terrain(Terrain::flat(Size2 { w: 20, h: 20 }));
+        for (name, controller) in [("A", Controller::Human), ("B", Controller::Ai)].iter() {
+            bf.add_player(name, "Rome", TeamId { id: 0 }, [1.0; 4], *controller);
+        }
+        let zone = Zone::new(vec![
+            Position::new(0.0, 0.0),
+            Position::new(20.0, 0.0),
+            Position::new(20.0, 8.0),
+            Position::new(0.0, 8.0),
+        ]);
+        bf.set_deployment_zone(PlayerId { id: 0 }, zone);
+        // Too small a zone for the computer's company
+        let tiny = Zone::new(vec![
+            Position::new(0.0, 18.0),
+            Position::new(1.0, 18.0),
+            Position::new(1.0, 19.0),
+        ]);
+        bf.set_deployment_zone(PlayerId { id: 1 }, tiny);
+        let formation = Formation::new(FormationShape::Line, 3, 2);
+        for id in 0..2 {
+            bf.add_company(
+                PlayerId { id },
+                UnitTypeId { id: 0 },
+                6,
+                Position::new(5.0, 14.0),
+                Rad(0.0),
+                formation,
+            );
+        }
+        let mut deployment = Deployment::new(bf);
+        assert_eq!(
+            deployment.ai_errors(),
+            &[(
+                PlayerId { id: 1 },
+                DeploymentError::NoRoom(CompId { id: 1 })
+            )]
+        );
+        assert_eq!(deployment.ready(), Err(DeploymentError::OutsideZone));
+        assert_eq!(deployment.player(), Some(PlayerId { id: 0 }));
+        deployment.click(Position::new(5.0, 14.0)).unwrap();
+        deployment.click(Position::new(5.0, 4.0)).unwrap();
+        assert_eq!(deployment.ready(), Ok(true));
+    }
+}
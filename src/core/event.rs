@@ -0,0 +1,56 @@
+//! What happened during a step of the battle. The simulation reports these so the scene, sound
+//! and statistics don't have to work it out by comparing the state of two frames.
+
+use core::combat::AttackResult;
+use core::company::CompId;
+use core::player::{PlayerId, TeamId};
+use core::position::Position;
+use core::projectile::ProjectileId;
+use core::structure::{StructureId, StructureState};
+use core::unit::IndivId;
+use core::veterancy::Rank;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum BattleEvent {
+    /// An attack or projectile reached `indiv_id`. Only a `Wound` took hp, the others were
+    /// stopped by its shield or armor.
+    IndivHit {
+        indiv_id: IndivId,
+        attacker_id: IndivId,
+        result: AttackResult,
+    },
+    IndivDied {
+        indiv_id: IndivId,
+        comp_id: CompId,
+        player_id: PlayerId,
+    },
+    CompanyRouted {
+        comp_id: CompId,
+    },
+    /// A routing company stopped running
+    CompanyRallied {
+        comp_id: CompId,
+    },
+    /// The soldiers of a company gained enough xp for it to reach `rank`
+    CompanyPromoted {
+        comp_id: CompId,
+        rank: Rank,
+    },
+    ProjectileFired {
+        projectile_id: ProjectileId,
+        shooter_id: IndivId,
+    },
+    ProjectileLanded {
+        projectile_id: ProjectileId,
+        pos: Position,
+    },
+    /// A structure took enough damage to be `Damaged`, or `Breached`.
+    StructureDamaged {
+        structure_id: StructureId,
+        state: StructureState,
+    },
+    /// A victory condition was met. `winner` is `None` for a draw.
+    BattleEnded {
+        winner: Option<TeamId>,
+    },
+}
@@ -0,0 +1,216 @@
+//! Stamina of single soldiers.
+//!
+//! Stamina runs from 1 (fresh) to 0 (spent). Every second a soldier loses `Drain::fighting` while
+//! it fights, `Drain::running` while it runs, `Drain::walking` while it walks and
+//! `Drain::climbing` for every m it climbs. Armor makes it all heavier: the loss is multiplied by
+//! `1 + armor_weight * armor`. Standing still it regains `resting` per second.
+//!
+//! Its stamina puts a soldier at a `FatigueLevel`, and every level but `Fresh` comes with
+//! `Penalties` to its speed, attack and defence skill, and to the morale of its company. The
+//! numbers are read from `assets/fatigue.json`; `FatigueRules::default` has the same ones.
+
+use core::misc::clamp;
+use serde_json;
+
+/// A soldier moving faster than this fraction of its top speed is running.
+pub const RUNNING_SPEED: f64 = 0.9;
+
+#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum FatigueLevel {
+    Fresh,
+    Winded,
+    Tired,
+    Exhausted,
+}
+
+impl FatigueLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            FatigueLevel::Fresh => "fresh",
+            FatigueLevel::Winded => "winded",
+            FatigueLevel::Tired => "tired",
+            FatigueLevel::Exhausted => "exhausted",
+        }
+    }
+}
+
+/// Stamina lost per second, or per m for `climbing`.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Drain {
+    pub fighting: f64,
+    pub running: f64,
+    pub walking: f64,
+    pub climbing: f64,
+}
+
+/// What a fatigue level costs a soldier.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Penalties {
+    /// The level starts below this stamina
+    pub below: f64,
+    /// Fraction of its top speed lost
+    pub speed: f64,
+    pub attack: i32,
+    pub defence: i32,
+    /// Taken from the morale of its company, averaged over its soldiers
+    pub morale: f64,
+}
+
+const NO_PENALTIES: Penalties = Penalties {
+    below: 1.0,
+    speed: 0.0,
+    attack: 0,
+    defence: 0,
+    morale: 0.0,
+};
+
+/// How hard a soldier worked during a step.
+#[derive(Clone, Copy, Debug)]
+pub struct Exertion {
+    pub fighting: bool,
+    /// Its speed as a fraction of its top speed when fresh
+    pub speed: f64,
+    /// Height gained, in m
+    pub climbed: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FatigueRules {
+    pub drain: Drain,
+    /// Stamina regained per second while standing still
+    pub resting: f64,
+    /// Extra drain per point of armor
+    pub armor_weight: f64,
+    pub winded: Penalties,
+    pub tired: Penalties,
+    pub exhausted: Penalties,
+}
+
+impl Default for FatigueRules {
+    fn default() -> FatigueRules {
+        FatigueRules {
+            drain: Drain {
+                fighting: 0.006,
+                running: 0.01,
+                walking: 0.0015,
+                climbing: 0.02,
+            },
+            resting: 0.01,
+            armor_weight: 0.05,
+            winded: Penalties {
+                below: 0.7,
+                speed: 0.1,
+                attack: 0,
+                defence: 0,
+                morale: 0.0,
+            },
+            tired: Penalties {
+                below: 0.4,
+                speed: 0.25,
+                attack: 1,
+                defence: 1,
+                morale: 5.0,
+            },
+            exhausted: Penalties {
+                below: 0.15,
+                speed: 0.5,
+                attack: 2,
+                defence: 2,
+                morale: 15.0,
+            },
+        }
+    }
+}
+
+impl FatigueRules {
+    pub fn from_json(json: &str) -> Result<FatigueRules, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn level(&self, stamina: f64) -> FatigueLevel {
+        if stamina < self.exhausted.below {
+            FatigueLevel::Exhausted
+        } else if stamina < self.tired.below {
+            FatigueLevel::Tired
+        } else if stamina < self.winded.below {
+            FatigueLevel::Winded
+        } else {
+            FatigueLevel::Fresh
+        }
+    }
+
+    pub fn penalties(&self, stamina: f64) -> &Penalties {
+        match self.level(stamina) {
+            FatigueLevel::Fresh => &NO_PENALTIES,
+            FatigueLevel::Winded => &self.winded,
+            FatigueLevel::Tired => &self.tired,
+            FatigueLevel::Exhausted => &self.exhausted,
+        }
+    }
+
+    /// The stamina of a soldier wearing `armor` after `dt` seconds of `exertion`.
+    pub fn stamina_after(&self, stamina: f64, exertion: &Exertion, armor: u8, dt: f64) -> f64 {
+        let mut drain = self.drain.climbing * exertion.climbed.max(0.0);
+        if exertion.fighting {
+            drain += self.drain.fighting * dt;
+        }
+        if exertion.speed > RUNNING_SPEED {
+            drain += self.drain.running * dt;
+        } else if exertion.speed > 0.05 {
+            drain += self.drain.walking * dt;
+        }
+        let stamina = if drain > 0.0 {
+            stamina - drain * (1.0 + self.armor_weight * armor as f64)
+        } else {
+            stamina + self.resting * dt
+        };
+        clamp(stamina, 0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Exertion, FatigueLevel, FatigueRules};
+
+    #[test]
+    fn test_bundled_rules_are_the_default() {
+        let json = include_str!("../../assets/fatigue.json");
+        assert_eq!(
+            FatigueRules::from_json(json).unwrap(),
+            FatigueRules::default()
+        );
+    }
+
+    #[test]
+    fn test_armor_tires_and_rest_recovers() {
+        let rules = FatigueRules::default();
+        let running = Exertion {
+            fighting: false,
+            speed: 1.0,
+            climbed: 0.0,
+        };
+        let run = |armor| {
+            let mut stamina = 1.0;
+            for _ in 0..700 {
+                stamina = rules.stamina_after(stamina, &running, armor, 0.1);
+            }
+            stamina
+        };
+        assert!(run(8) < run(0));
+        assert_eq!(rules.level(run(8)), FatigueLevel::Exhausted);
+        assert_eq!(rules.level(run(0)), FatigueLevel::Tired);
+        let resting = Exertion {
+            speed: 0.0,
+            ..running
+        };
+        let mut stamina = 0.0;
+        for _ in 0..1000 {
+            stamina = rules.stamina_after(stamina, &resting, 8, 0.1);
+        }
+        assert_eq!(stamina, 1.0);
+        assert!(rules.penalties(0.0).speed > rules.penalties(0.5).speed);
+    }
+}
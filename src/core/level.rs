@@ -0,0 +1,156 @@
+//! Levels of the battlefield. Most of the fighting happens on the ground, but soldiers can also
+//! stand on the walkways of walls and on top of towers. Levels are only connected to each other
+//! through `Connection`s: stairs, ladders and gate passages.
+
+use core::position::Position;
+
+/// Closer than this (in m) to the end of a connection counts as standing at it.
+pub const CONNECTION_DIST: f64 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+pub enum Level {
+    #[default]
+    Ground,
+    /// The walkway on top of a wall, behind the battlements
+    Walkway,
+    TowerTop,
+}
+
+impl Level {
+    /// Height above the ground, in m.
+    pub fn height(&self) -> f64 {
+        match *self {
+            Level::Ground => 0.0,
+            Level::Walkway => 6.0,
+            Level::TowerTop => 10.0,
+        }
+    }
+
+    /// The highest level at or below `height`.
+    pub fn from_height(height: f64) -> Level {
+        [Level::TowerTop, Level::Walkway]
+            .iter()
+            .cloned()
+            .find(|level| height >= level.height())
+            .unwrap_or(Level::Ground)
+    }
+}
+
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ConnectionId {
+    pub id: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ConnectionKind {
+    Stairs,
+    Ladder,
+    /// A passage through a wall, from the ground on one side to the ground on the other
+    GatePassage,
+}
+
+/// A way from one spot to another that can't be walked in a straight line, usually because
+/// the two are on a different level. It can be used in both directions.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Connection {
+    pub id: ConnectionId,
+    pub kind: ConnectionKind,
+    pub a: Position,
+    pub b: Position,
+    /// A closed gate can't be passed
+    pub open: bool,
+}
+
+impl ConnectionKind {
+    /// How much longer it takes to get through than to walk the same distance.
+    pub fn cost_factor(&self) -> f64 {
+        match *self {
+            ConnectionKind::Stairs => 2.0,
+            ConnectionKind::Ladder => 4.0,
+            ConnectionKind::GatePassage => 1.0,
+        }
+    }
+}
+
+impl Connection {
+    /// If this connection is open, the end a soldier at `from` goes in by and the end it comes
+    /// out at. It goes in by the end on its level, the nearer one if both are.
+    pub fn ends(&self, from: Position) -> Option<(Position, Position)> {
+        let (near, far) = if self.a.dist(&from) <= self.b.dist(&from) {
+            (self.a, self.b)
+        } else {
+            (self.b, self.a)
+        };
+        if !self.open {
+            None
+        } else if near.level == from.level {
+            Some((near, far))
+        } else if far.level == from.level {
+            Some((far, near))
+        } else {
+            None
+        }
+    }
+
+    /// The cost of passing through, in m of walking on level ground.
+    pub fn cost(&self) -> f64 {
+        self.a.dist(&self.b).max(1.0) * self.kind.cost_factor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Connection, ConnectionId, ConnectionKind, Level};
+    use core::position::Position;
+
+    #[test]
+    fn test_level_from_height() {
+        assert_eq!(Level::from_height(0.0), Level::Ground);
+        assert_eq!(Level::from_height(7.0), Level::Walkway);
+        assert_eq!(Level::from_height(12.0), Level::TowerTop);
+    }
+
+    #[test]
+    fn test_connection_works_both_ways() {
+        let mut stairs = Connection {
+            id: ConnectionId { id: 0 },
+            kind: ConnectionKind::Stairs,
+            a: Position::new(0.0, 0.0),
+            b: Position::on_level(0.0, 5.0, Level::Walkway),
+            open: true,
+        };
+        let (enter, exit) = stairs
+            .ends(Position::on_level(0.0, 3.0, Level::Walkway))
+            .unwrap();
+        assert_eq!(enter.level, Level::Walkway);
+        assert_eq!(exit.level, Level::Ground);
+        // The ground end, even though the walkway end is nearer
+        let (enter, _) = stairs.ends(Position::new(0.0, 4.0)).unwrap();
+        assert_eq!(enter.level, Level::Ground);
+        assert!(stairs
+            .ends(Position::on_level(0.0, 0.0, Level::TowerTop))
+            .is_none());
+        let gate = Connection {
+            kind: ConnectionKind::GatePassage,
+            b: Position::new(0.0, 2.0),
+            ..stairs.clone()
+        };
+        let (enter, exit) = gate.ends(Position::new(0.0, 2.3)).unwrap();
+        assert_eq!((enter.y, exit.y), (2.0, 0.0));
+        stairs.open = false;
+        assert!(stairs.ends(Position::new(0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_connection_cost() {
+        let stairs = Connection {
+            id: ConnectionId { id: 0 },
+            kind: ConnectionKind::Stairs,
+            a: Position::new(0.0, 0.0),
+            b: Position::on_level(0.0, 5.0, Level::Walkway),
+            open: true,
+        };
+        assert!((stairs.cost() - 10.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,297 @@
+//! Balancing unit types. Every pair of types fights a number of duels, one full company
+//! against another on open ground, and the results are gathered in a matrix.
+
+use cgmath::Rad;
+use core::battlefield::Battlefield;
+use core::company::Formation;
+use core::deployment::Zone;
+use core::player::{Controller, TeamId};
+use core::position::Position;
+use core::simulation::{self, DEFAULT_MAX_DURATION};
+use core::terrain::Terrain;
+use core::unit::{UnitType, UnitTypeId};
+use core::victory::VictoryCondition;
+use std::f32::consts::PI;
+use std::fmt::Write;
+use types::Size2;
+
+/// The field duels are fought on, in m
+const DUEL_MAP_SIZE: Size2 = Size2 { w: 60, h: 60 };
+
+/// How deep the deployment zones at either end of the field are, in m
+const DUEL_ZONE_DEPTH: f64 = 12.0;
+
+/// Duels still going on after this many seconds are a draw
+pub const DUEL_TIME_LIMIT: f64 = 600.0;
+
+/// How one unit type fared against another.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Matchup {
+    pub runs: u32,
+    pub wins: u32,
+    pub draws: u32,
+    /// Soldiers lost, summed over all runs
+    pub lost: u32,
+    /// Enemy soldiers killed, summed over all runs
+    pub killed: u32,
+    /// `cost_recruit` of the soldiers lost, summed over all runs
+    pub cost_lost: f64,
+    /// `cost_recruit` of the enemy soldiers killed, summed over all runs
+    pub cost_killed: f64,
+}
+
+impl Matchup {
+    pub fn win_rate(&self) -> f64 {
+        self.wins as f64 / self.runs.max(1) as f64
+    }
+
+    pub fn draw_rate(&self) -> f64 {
+        self.draws as f64 / self.runs.max(1) as f64
+    }
+
+    /// Soldiers lost per duel.
+    pub fn avg_lost(&self) -> f64 {
+        self.lost as f64 / self.runs.max(1) as f64
+    }
+
+    /// Enemy soldiers killed per duel.
+    pub fn avg_killed(&self) -> f64 {
+        self.killed as f64 / self.runs.max(1) as f64
+    }
+
+    /// Worth of the enemies killed for every unit of worth lost. `None` if nothing was lost.
+    pub fn cost_efficiency(&self) -> Option<f64> {
+        if self.cost_lost > 0.0 {
+            Some(self.cost_killed / self.cost_lost)
+        } else {
+            None
+        }
+    }
+}
+
+/// The matchups of every unit type against every unit type, itself included.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatchupMatrix {
+    pub names: Vec<String>,
+    /// Row by row: the type of the row against the type of the column
+    matchups: Vec<Matchup>,
+}
+
+impl MatchupMatrix {
+    /// Fights `runs` duels for every pair of `unit_types`. Duel `i` of every pair gets seed
+    /// `seed + i`, and the sides are swapped every other duel, as the side may matter.
+    pub fn run(unit_types: &[UnitType], runs: u32, seed: u64) -> MatchupMatrix {
+        let n = unit_types.len();
+        let mut matrix = MatchupMatrix {
+            names: unit_types.iter().map(|unit| unit.name.clone()).collect(),
+            matchups: vec![Matchup::default(); n * n],
+        };
+        for a in 0..n {
+            for b in a..n {
+                for run in 0..runs {
+                    let (a, b) = (UnitTypeId { id: a as u16 }, UnitTypeId { id: b as u16 });
+                    let (south, north) = if run % 2 == 0 { (a, b) } else { (b, a) };
+                    let battlefield = duel(unit_types.to_vec(), south, north, seed + run as u64);
+                    let report = simulation::run(battlefield, DEFAULT_MAX_DURATION);
+                    let lost = |id| report.casualties.get(&TeamId { id }).cloned().unwrap_or(0);
+                    let (south_lost, north_lost) = (lost(0), lost(1));
+                    let results = [
+                        (south, north, TeamId { id: 0 }, south_lost, north_lost),
+                        (north, south, TeamId { id: 1 }, north_lost, south_lost),
+                    ];
+                    // A mirror match counts for both sides, so it ends up with twice the runs
+                    for &(own, enemy, team, lost, killed) in results.iter() {
+                        let matchup = matrix.get_mut(own, enemy);
+                        matchup.runs += 1;
+                        match report.winner {
+                            Some(winner) if winner == team => matchup.wins += 1,
+                            Some(_) => (),
+                            None => matchup.draws += 1,
+                        }
+                        matchup.lost += lost;
+                        matchup.killed += killed;
+                        matchup.cost_lost +=
+                            lost as f64 * soldier_cost(&unit_types[own.id as usize]);
+                        matchup.cost_killed +=
+                            killed as f64 * soldier_cost(&unit_types[enemy.id as usize]);
+                    }
+                }
+            }
+        }
+        matrix
+    }
+
+    /// How `own` fared against `enemy`.
+    pub fn get(&self, own: UnitTypeId, enemy: UnitTypeId) -> &Matchup {
+        &self.matchups[own.id as usize * self.names.len() + enemy.id as usize]
+    }
+
+    fn get_mut(&mut self, own: UnitTypeId, enemy: UnitTypeId) -> &mut Matchup {
+        let n = self.names.len();
+        &mut self.matchups[own.id as usize * n + enemy.id as usize]
+    }
+
+    /// One line per pair of unit types, with a header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "unit,enemy,runs,win_rate,draw_rate,avg_lost,avg_killed,cost_efficiency\n",
+        );
+        for (own, enemy, matchup) in self.iter() {
+            let efficiency = matchup
+                .cost_efficiency()
+                .map_or(String::new(), |eff| format!("{:.3}", eff));
+            writeln!(
+                csv,
+                "{},{},{},{:.3},{:.3},{:.2},{:.2},{}",
+                self.names[own.id as usize],
+                self.names[enemy.id as usize],
+                matchup.runs,
+                matchup.win_rate(),
+                matchup.draw_rate(),
+                matchup.avg_lost(),
+                matchup.avg_killed(),
+                efficiency
+            )
+            .unwrap();
+        }
+        csv
+    }
+
+    /// A table each for the win rates, the soldiers lost and the cost efficiency. Rows are
+    /// the unit types, columns their enemies.
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        self.write_table(&mut md, "Win rate", |matchup| {
+            format!("{:.0}%", matchup.win_rate() * 100.0)
+        });
+        self.write_table(&mut md, "Soldiers lost per duel", |matchup| {
+            format!("{:.1}", matchup.avg_lost())
+        });
+        self.write_table(&mut md, "Cost efficiency", |matchup| {
+            matchup
+                .cost_efficiency()
+                .map_or("-".to_string(), |eff| format!("{:.2}", eff))
+        });
+        md
+    }
+
+    fn write_table<F: Fn(&Matchup) -> String>(&self, md: &mut String, title: &str, cell: F) {
+        if !md.is_empty() {
+            md.push('\n');
+        }
+        writeln!(md, "### {}\n", title).unwrap();
+        writeln!(md, "| | {} |", self.names.join(" | ")).unwrap();
+        writeln!(md, "|---|{}", "---|".repeat(self.names.len())).unwrap();
+        for (own, name) in self.names.iter().enumerate() {
+            let cells: Vec<String> = (0..self.names.len())
+                .map(|enemy| {
+                    let (own, enemy) = (
+                        UnitTypeId { id: own as u16 },
+                        UnitTypeId { id: enemy as u16 },
+                    );
+                    cell(self.get(own, enemy))
+                })
+                .collect();
+            writeln!(md, "| {} | {} |", name, cells.join(" | ")).unwrap();
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (UnitTypeId, UnitTypeId, &Matchup)> {
+        let n = self.names.len();
+        self.matchups.iter().enumerate().map(move |(i, matchup)| {
+            let own = UnitTypeId { id: (i / n) as u16 };
+            let enemy = UnitTypeId { id: (i % n) as u16 };
+            (own, enemy, matchup)
+        })
+    }
+}
+
+/// What one soldier of `unit_type` costs to recruit.
+fn soldier_cost(unit_type: &UnitType) -> f64 {
+    unit_type.cost_recruit as f64 / unit_type.count.max(1) as f64
+}
+
+/// A full company of `south` against one of `north`, each starting in a zone at its end of the
+/// field. Both sides are left to the computer.
+pub fn duel(
+    unit_types: Vec<UnitType>,
+    south: UnitTypeId,
+    north: UnitTypeId,
+    seed: u64,
+) -> Battlefield {
+    let (w, h) = (DUEL_MAP_SIZE.w as f64, DUEL_MAP_SIZE.h as f64);
+    let counts = [
+        unit_types[south.id as usize].count as usize,
+        unit_types[north.id as usize].count as usize,
+    ];
+    let mut battlefield = Battlefield::new(unit_types, seed);
+    battlefield.set_terrain(Terrain::flat(DUEL_MAP_SIZE));
+    let sides = [
+        ("South", south, 0.0, DUEL_ZONE_DEPTH, 0.0),
+        ("North", north, h - DUEL_ZONE_DEPTH, h, PI),
+    ];
+    for (i, &(name, type_id, min_y, max_y, rot)) in sides.iter().enumerate() {
+        let player_id =
+            battlefield.add_player(name, name, TeamId { id: i as u8 }, [1.0; 4], Controller::Ai);
+        let zone = Zone::new(vec![
+            Position::new(0.0, min_y),
+            Position::new(w, min_y),
+            Position::new(w, max_y),
+            Position::new(0.0, max_y),
+        ]);
+        battlefield.set_deployment_zone(player_id, zone);
+        battlefield.add_company(
+            player_id,
+            type_id,
+            counts[i],
+            Position::new(w / 2.0, (min_y + max_y) / 2.0),
+            Rad(rot),
+            Formation::line(counts[i]),
+        );
+    }
+    battlefield.add_victory_condition(VictoryCondition::Annihilation);
+    battlefield.add_victory_condition(VictoryCondition::AllEnemiesRouted);
+    battlefield.add_victory_condition(VictoryCondition::TimeLimit {
+        time: DUEL_TIME_LIMIT,
+        winner: None,
+    });
+    battlefield
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MatchupMatrix;
+    use core::unit::{test_unit_type, UnitType, UnitTypeId};
+
+    fn unit_type(name: &str, attack_skill: i32, cost_recruit: f32) -> UnitType {
+        UnitType {
+            name: name.to_string(),
+            count: 12,
+            attack_skill,
+            cost_recruit,
+            ..test_unit_type()
+        }
+    }
+
+    #[test]
+    fn test_matchup_matrix() {
+        let unit_types = vec![unit_type("weak", 1, 100.0), unit_type("strong", 14, 100.0)];
+        let matrix = MatchupMatrix::run(&unit_types, 2, 1);
+        let (weak, strong) = (UnitTypeId { id: 0 }, UnitTypeId { id: 1 });
+        assert_eq!(matrix.get(weak, strong).runs, 2);
+        assert_eq!(matrix.get(strong, weak).runs, 2);
+        assert_eq!(matrix.get(weak, weak).runs, 4);
+        // Both sides of a pair come from the same duels
+        let (ws, sw) = (matrix.get(weak, strong), matrix.get(strong, weak));
+        assert_eq!(ws.lost, sw.killed);
+        assert_eq!(ws.wins + sw.wins + ws.draws, 2);
+        assert_eq!(ws.draws, sw.draws);
+        assert!(sw.avg_killed() > ws.avg_killed());
+        let csv = matrix.to_csv();
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.lines().any(|line| line.starts_with("strong,weak,2,")));
+        let md = matrix.to_markdown();
+        assert!(md.contains("| | weak | strong |"));
+        assert_eq!(md.matches("### ").count(), 3);
+    }
+}
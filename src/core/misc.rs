@@ -1,5 +1,6 @@
 #![allow(dead_code)]
-use rand::{thread_rng, Rng};
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
 use std::cmp;
 use std::sync::mpsc::Receiver;
 
@@ -15,12 +16,22 @@ where
     }
 }
 
-pub fn get_shuffled_indices<T>(v: &[T]) -> Vec<usize> {
+pub fn get_shuffled_indices<T, R: Rng>(rng: &mut R, v: &[T]) -> Vec<usize> {
     let mut indices: Vec<_> = (0..v.len()).collect();
-    thread_rng().shuffle(&mut indices);
+    rng.shuffle(&mut indices);
     indices
 }
 
+/// A fast random number generator that always gives the same numbers for the same `seed`.
+pub fn seeded_rng(seed: u64) -> XorShiftRng {
+    let mut bytes = [0; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        // Spread the seed over both halves, the generator doesn't like zeros
+        *byte = (seed >> (8 * (i % 8))) as u8 ^ (0x5a + i as u8);
+    }
+    XorShiftRng::from_seed(bytes)
+}
+
 pub fn rx_collect<T>(rx: &Receiver<T>) -> Vec<T> {
     let mut v = Vec::new();
     while let Ok(data) = rx.try_recv() {
@@ -39,7 +50,8 @@ pub fn opt_rx_collect<T>(rx: &Option<Receiver<T>>) -> Vec<T> {
 
 #[cfg(test)]
 mod tests {
-    use core::misc::{clamp, get_shuffled_indices, opt_rx_collect, rx_collect};
+    use core::misc::{clamp, get_shuffled_indices, opt_rx_collect, rx_collect, seeded_rng};
+    use rand::Rng;
     use std::sync::mpsc::channel;
 
     #[test]
@@ -54,15 +66,26 @@ mod tests {
     #[test]
     fn test_shuffle_touches_all_fields() {
         let mut v = [false; 10];
-        let indices = get_shuffled_indices(&v);
+        let indices = get_shuffled_indices(&mut seeded_rng(1), &v);
         for i in indices {
             v[i] = true;
         }
         for n in &v {
-            assert_eq!(*n, true);
+            assert!(*n);
         }
     }
 
+    #[test]
+    fn test_seeded_rng_repeats() {
+        let a: Vec<u32> = (0..10).map(|_| seeded_rng(42).gen()).collect();
+        let mut rng = seeded_rng(42);
+        let b: Vec<u32> = (0..10).map(|_| rng.gen()).collect();
+        assert!(a.iter().all(|&n| n == a[0]));
+        assert_eq!(a[0], b[0]);
+        assert!(b.iter().any(|&n| n != b[0]));
+        assert_ne!(seeded_rng(1).gen::<u64>(), seeded_rng(2).gen::<u64>());
+    }
+
     #[test]
     fn test_rx_collect() {
         let (tx, rx) = channel();
@@ -1,4 +1,29 @@
+pub mod ai;
 pub mod battlefield;
+pub mod combat;
+pub mod company;
+pub mod deployment;
+pub mod event;
+pub mod fatigue;
+pub mod level;
+pub mod matchup;
 pub mod misc;
+pub mod morale;
+pub mod movement;
+pub mod order;
+pub mod pathfinding;
+pub mod player;
 pub mod position;
+pub mod projectile;
+pub mod replay;
+pub mod save;
+pub mod scenario;
+pub mod simulation;
+pub mod spatial;
+pub mod structure;
+pub mod terrain;
 pub mod unit;
+pub mod veterancy;
+pub mod victory;
+pub mod visibility;
+pub mod weapon;
@@ -0,0 +1,210 @@
+//! Company morale.
+//!
+//! Morale runs from 0 to 100. A fresh company starts at its base morale, which is the
+//! `morale` of its unit type times `MORALE_SCALE`, plus the bonus of its rank (see
+//! `veterancy::Rank`). Every step the situation of the company sets a target, and morale drifts
+//! towards it: quickly down, slowly up.
+//!
+//! The target is the base morale minus:
+//! - `CASUALTY_PENALTY` times the fraction of the company that has fallen,
+//! - `FLANK_PENALTY` times the fraction of its soldiers fighting enemies on their flank or rear,
+//! - `ROUTING_FRIEND_PENALTY` for every friendly company routing nearby, up to
+//!   `MAX_ROUTING_FRIENDS_PENALTY`,
+//! - the morale penalty of the fatigue level of its soldiers, on average (see `fatigue`).
+//!
+//! On top of that, every soldier that falls is a shock that takes away `CASUALTY_SHOCK` divided
+//! by the starting strength right away.
+//!
+//! Below `WAVER_THRESHOLD` a company wavers: its soldiers attack and reload slower. Below
+//! `ROUT_THRESHOLD` it routs: it stops fighting and runs for the nearest map edge. A routing
+//! company only recovers morale while no enemy is within `RALLY_DIST`, and rallies once it is
+//! back at `RALLY_THRESHOLD`.
+
+pub const MORALE_SCALE: f64 = 10.0;
+pub const MAX_MORALE: f64 = 100.0;
+
+pub const CASUALTY_PENALTY: f64 = 60.0;
+pub const FLANK_PENALTY: f64 = 25.0;
+pub const ROUTING_FRIEND_PENALTY: f64 = 10.0;
+pub const MAX_ROUTING_FRIENDS_PENALTY: f64 = 30.0;
+pub const CASUALTY_SHOCK: f64 = 50.0;
+
+/// Points per second morale falls with when above its target
+pub const FALL_RATE: f64 = 5.0;
+/// Points per second morale rises with when below its target
+pub const RECOVER_RATE: f64 = 1.0;
+
+pub const WAVER_THRESHOLD: f64 = 40.0;
+pub const ROUT_THRESHOLD: f64 = 20.0;
+pub const RALLY_THRESHOLD: f64 = 50.0;
+
+/// Routing companies within this distance (in m) hurt the morale of their friends
+pub const ROUTING_FRIEND_DIST: f64 = 30.0;
+/// A routing company can't rally with an enemy this close (in m)
+pub const RALLY_DIST: f64 = 40.0;
+
+/// Wavering soldiers take this much longer to attack or reload
+pub const WAVERING_ATTACK_DELAY: f64 = 1.5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum MoraleState {
+    Steady,
+    Wavering,
+    Routing,
+}
+
+/// What a company is going through this step, as far as its morale is concerned.
+#[derive(Clone, Copy, Debug)]
+pub struct MoraleInputs {
+    /// Soldiers left in the company
+    pub strength: usize,
+    /// Fraction of the company fighting enemies that are not in front of it
+    pub flanked: f64,
+    /// Friendly companies routing within `ROUTING_FRIEND_DIST`
+    pub routing_friends: usize,
+    /// Whether an enemy is within `RALLY_DIST`
+    pub enemy_near: bool,
+    /// Average morale penalty of the fatigue of its soldiers
+    pub fatigue_penalty: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Morale {
+    pub value: f64,
+    pub base: f64,
+    pub state: MoraleState,
+    /// Soldiers in the company at the start of the battle
+    pub start_strength: usize,
+}
+
+impl Morale {
+    /// `bonus` is added to the base morale, for experienced companies.
+    pub fn new(unit_morale: u8, start_strength: usize, bonus: f64) -> Morale {
+        let base = Morale::base_for(unit_morale, bonus);
+        Morale {
+            value: base,
+            base,
+            state: MoraleState::Steady,
+            start_strength,
+        }
+    }
+
+    /// The base morale of a company of a unit type with `unit_morale`, with `bonus` added.
+    pub fn base_for(unit_morale: u8, bonus: f64) -> f64 {
+        (unit_morale as f64 * MORALE_SCALE + bonus).min(MAX_MORALE)
+    }
+
+    /// The morale the company drifts towards in its current situation.
+    pub fn target(&self, inputs: &MoraleInputs) -> f64 {
+        let casualties = if self.start_strength == 0 {
+            0.0
+        } else {
+            1.0 - inputs.strength as f64 / self.start_strength as f64
+        };
+        let routing_friends = (inputs.routing_friends as f64 * ROUTING_FRIEND_PENALTY)
+            .min(MAX_ROUTING_FRIENDS_PENALTY);
+        let target = self.base
+            - CASUALTY_PENALTY * casualties
+            - FLANK_PENALTY * inputs.flanked
+            - routing_friends
+            - inputs.fatigue_penalty;
+        target.max(0.0)
+    }
+
+    /// The shock of seeing a comrade fall.
+    pub fn on_casualty(&mut self) {
+        if self.start_strength > 0 {
+            self.value -= CASUALTY_SHOCK / self.start_strength as f64;
+            self.value = self.value.max(0.0);
+        }
+    }
+
+    /// Moves morale towards its target and updates the state. Returns the new state if it
+    /// changed.
+    pub fn update(&mut self, inputs: &MoraleInputs, dt: f64) -> Option<MoraleState> {
+        let target = self.target(inputs);
+        let can_recover = self.state != MoraleState::Routing || !inputs.enemy_near;
+        if self.value > target {
+            self.value = (self.value - FALL_RATE * dt).max(target);
+        } else if can_recover {
+            self.value = (self.value + RECOVER_RATE * dt).min(target);
+        }
+        let new_state = match self.state {
+            MoraleState::Routing if self.value < RALLY_THRESHOLD || inputs.enemy_near => {
+                MoraleState::Routing
+            }
+            // Rallied companies are still shaken for a while
+            MoraleState::Routing => MoraleState::Wavering,
+            _ if self.value < ROUT_THRESHOLD => MoraleState::Routing,
+            _ if self.value < WAVER_THRESHOLD => MoraleState::Wavering,
+            _ => MoraleState::Steady,
+        };
+        if new_state == self.state {
+            None
+        } else {
+            self.state = new_state;
+            Some(new_state)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Morale, MoraleInputs, MoraleState};
+
+    fn calm(strength: usize) -> MoraleInputs {
+        MoraleInputs {
+            strength,
+            flanked: 0.0,
+            routing_friends: 0,
+            enemy_near: false,
+            fatigue_penalty: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_losses_and_flanking_cause_rout() {
+        let mut morale = Morale::new(8, 100, 0.0);
+        let mut inputs = calm(50);
+        for _ in 0..100 {
+            morale.update(&inputs, 0.1);
+        }
+        assert_eq!(morale.state, MoraleState::Steady);
+        inputs.flanked = 1.0;
+        inputs.routing_friends = 1;
+        inputs.enemy_near = true;
+        let mut states = Vec::new();
+        for _ in 0..100 {
+            if let Some(state) = morale.update(&inputs, 0.1) {
+                states.push(state);
+            }
+        }
+        assert_eq!(states, [MoraleState::Wavering, MoraleState::Routing]);
+    }
+
+    #[test]
+    fn test_routing_company_rallies_when_safe() {
+        let mut morale = Morale::new(8, 100, 0.0);
+        morale.value = 10.0;
+        morale.state = MoraleState::Routing;
+        let mut inputs = calm(100);
+        inputs.enemy_near = true;
+        for _ in 0..1000 {
+            morale.update(&inputs, 0.1);
+        }
+        assert_eq!(morale.state, MoraleState::Routing);
+        assert!(morale.value <= 10.0);
+        inputs.enemy_near = false;
+        for _ in 0..1000 {
+            morale.update(&inputs, 0.1);
+        }
+        assert_eq!(morale.state, MoraleState::Steady);
+    }
+
+    #[test]
+    fn test_casualty_shock() {
+        let mut morale = Morale::new(8, 100, 0.0);
+        morale.on_casualty();
+        assert!((morale.value - 79.5).abs() < 1e-9);
+    }
+}
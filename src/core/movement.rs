@@ -0,0 +1,173 @@
+use cgmath::{Angle, Rad, Vector2};
+use core::position::Position;
+use std::f32::consts::PI;
+
+/// Closer than this to the destination counts as arrived.
+const ARRIVE_DIST: f64 = 0.05;
+
+/// An order to go to `dest` and end up facing `facing`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct MoveOrder {
+    pub dest: Position,
+    pub facing: Rad<f32>,
+}
+
+/// How fast something can move, speed up and turn.
+#[derive(Clone, Copy, Debug)]
+pub struct Steering {
+    /// m/s
+    pub max_speed: f64,
+    /// m/s², used both to speed up and to slow down
+    pub accel: f64,
+    /// rad/s
+    pub turn_rate: f32,
+    /// Below this distance the mover steps sideways or backwards to its destination,
+    /// keeping the ordered facing, instead of turning around to walk there.
+    pub sidestep_dist: f64,
+    /// Fraction of `max_speed` that can be reached while sidestepping
+    pub sidestep_factor: f64,
+}
+
+/// The unit vector pointing in the direction of `rot`. A rotation of 0 faces +y.
+pub fn forward(rot: Rad<f32>) -> Vector2<f64> {
+    let (sin, cos) = (rot.0 as f64).sin_cos();
+    Vector2::new(-sin, cos)
+}
+
+/// The rotation that faces along `v`. Inverse of `forward`.
+pub fn heading_of(v: Vector2<f64>) -> Rad<f32> {
+    Rad(-v.x.atan2(v.y) as f32).normalize()
+}
+
+/// The angle to turn from `from` to `to`, in (-PI, PI].
+pub fn angle_diff(from: Rad<f32>, to: Rad<f32>) -> Rad<f32> {
+    let mut diff = (to - from).normalize().0;
+    if diff > PI {
+        diff -= 2.0 * PI;
+    }
+    Rad(diff)
+}
+
+/// Turns `rot` towards `goal`, but not more than `max_turn`.
+pub fn turn_towards(rot: Rad<f32>, goal: Rad<f32>, max_turn: f32) -> Rad<f32> {
+    let diff = angle_diff(rot, goal).0;
+    if diff.abs() <= max_turn {
+        goal.normalize()
+    } else {
+        (rot + Rad(max_turn * diff.signum())).normalize()
+    }
+}
+
+/// Moves a mover one step of `dt` seconds towards `order`.
+///
+/// Far from the destination the mover turns towards it and walks forward; it only gets
+/// up to speed once it roughly faces where it is going. Close to the destination it
+/// sidesteps there while turning to the ordered facing. It always slows down in time to
+/// stop at the destination instead of overshooting it.
+pub fn steer(
+    pos: &mut Position,
+    rot: &mut Rad<f32>,
+    vel: &mut Vector2<f64>,
+    order: &MoveOrder,
+    steering: &Steering,
+    dt: f64,
+) {
+    let to_dest = Vector2::new(order.dest.x - pos.x, order.dest.y - pos.y);
+    let dist = to_dest.x.hypot(to_dest.y);
+    // The speed from which we can still brake before reaching the destination
+    let arrive_speed = (2.0 * steering.accel * dist)
+        .sqrt()
+        .min(dist / dt)
+        .min(steering.max_speed);
+    let (desired_rot, desired_vel) = if dist < ARRIVE_DIST {
+        (order.facing, Vector2::new(0.0, 0.0))
+    } else if dist < steering.sidestep_dist {
+        let speed = arrive_speed.min(steering.max_speed * steering.sidestep_factor);
+        (order.facing, to_dest * (speed / dist))
+    } else {
+        let heading = heading_of(to_dest);
+        let alignment = (angle_diff(*rot, heading).0 as f64).cos().max(0.0);
+        (heading, forward(*rot) * (arrive_speed * alignment))
+    };
+    *rot = turn_towards(*rot, desired_rot, steering.turn_rate * dt as f32);
+    let dv = desired_vel - *vel;
+    let dv_len = dv.x.hypot(dv.y);
+    let max_dv = steering.accel * dt;
+    *vel += if dv_len > max_dv {
+        dv * (max_dv / dv_len)
+    } else {
+        dv
+    };
+    pos.x += vel.x * dt;
+    pos.y += vel.y * dt;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{angle_diff, forward, heading_of, steer, turn_towards, MoveOrder, Steering};
+    use cgmath::{Rad, Vector2};
+    use core::position::Position;
+    use std::f32::consts::PI;
+
+    const EPS: f32 = 0.001;
+
+    const STEERING: Steering = Steering {
+        max_speed: 2.0,
+        accel: 2.0,
+        turn_rate: PI,
+        sidestep_dist: 1.0,
+        sidestep_factor: 0.5,
+    };
+
+    #[test]
+    fn test_heading_is_inverse_of_forward() {
+        for i in 0..12 {
+            let rot = Rad(i as f32 * PI / 6.0);
+            let diff = angle_diff(rot, heading_of(forward(rot)));
+            assert!(diff.0.abs() < EPS, "{:?}", rot);
+        }
+    }
+
+    #[test]
+    fn test_turn_towards_takes_shortest_way() {
+        let rot = turn_towards(Rad(0.1), Rad(2.0 * PI - 0.1), 0.1);
+        assert!(rot.0.abs() < EPS);
+        let rot = turn_towards(Rad(0.0), Rad(1.0), 2.0);
+        assert!((rot.0 - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn test_steer_arrives_with_facing() {
+        let mut pos = Position::new(0.0, 0.0);
+        let mut rot = Rad(0.0);
+        let mut vel = Vector2::new(0.0, 0.0);
+        let order = MoveOrder {
+            dest: Position::new(-10.0, -5.0),
+            facing: Rad(PI / 2.0),
+        };
+        let mut max_speed: f64 = 0.0;
+        for _ in 0..(20 * 30) {
+            steer(&mut pos, &mut rot, &mut vel, &order, &STEERING, 0.05);
+            max_speed = max_speed.max(vel.x.hypot(vel.y));
+        }
+        assert!(pos.dist(&order.dest) < 0.05);
+        assert!(vel.x.hypot(vel.y) < 1e-6);
+        assert!(angle_diff(rot, order.facing).0.abs() < EPS);
+        assert!(max_speed <= STEERING.max_speed + 1e-9);
+    }
+
+    #[test]
+    fn test_steer_turns_before_walking() {
+        let mut pos = Position::new(0.0, 0.0);
+        let mut rot = Rad(0.0);
+        let mut vel = Vector2::new(0.0, 0.0);
+        // Straight behind us
+        let order = MoveOrder {
+            dest: Position::new(0.0, -10.0),
+            facing: Rad(0.0),
+        };
+        steer(&mut pos, &mut rot, &mut vel, &order, &STEERING, 0.05);
+        assert!(vel.x.hypot(vel.y) < 1e-6);
+        assert!(rot.0 > 0.0);
+    }
+}
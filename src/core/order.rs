@@ -0,0 +1,54 @@
+//! Orders given to the battlefield by the players and the AI. They are the only way the battle
+//! is steered from outside, so recording them together with the seed is enough to play a
+//! battle again.
+
+use cgmath::Rad;
+use core::company::{CompId, Formation};
+use core::player::{Controller, PlayerId};
+use core::position::Position;
+use core::structure::StructureId;
+use core::unit::IndivId;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Order {
+    SetFormation {
+        comp_id: CompId,
+        formation: Formation,
+    },
+    MoveCompany {
+        comp_id: CompId,
+        dest: Position,
+        facing: Rad<f32>,
+    },
+    MoveIndiv {
+        indiv_id: IndivId,
+        dest: Position,
+        facing: Rad<f32>,
+    },
+    RejoinCompany {
+        indiv_id: IndivId,
+    },
+    /// Stop where it stands and keep its ground. Calls off a march or an attack.
+    Hold {
+        comp_id: CompId,
+    },
+    /// March up to the structure and break it. Another move order calls it off.
+    AttackStructure {
+        comp_id: CompId,
+        structure_id: StructureId,
+    },
+    /// Hands a player to a human or to the computer
+    SetController {
+        player_id: PlayerId,
+        controller: Controller,
+    },
+}
+
+/// An order and when it was given.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RecordedOrder {
+    /// The number of steps done before the order was given. It is carried out from the next
+    /// step on.
+    pub tick: u64,
+    pub order: Order,
+}
@@ -0,0 +1,738 @@
+//! Finding a way around walls, buildings and steep slopes, and up and down between levels.
+//!
+//! Every level is covered by a grid of `NAV_CELL_SIZE` cells that are either walkable or not.
+//! The ground is walkable unless it is too steep or blocked by an obstacle; walkways and tower
+//! tops are only walkable where a walkable area was added. Walking happens between neighbouring
+//! cells, diagonals included. `Connection`s (stairs, ladders, gate passages) link a cell to a
+//! cell that is not its neighbour, usually on another level.
+//!
+//! `NavGrid::find_path` runs A* for a single mover. When many soldiers head for the same spot,
+//! `NavGrid::flow_field` computes once how far every cell is from the goal, after which every
+//! soldier can just look up which way to go.
+
+use core::level::{Connection, ConnectionId, Level};
+use core::position::Position;
+use core::terrain::Terrain;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::f64;
+use std::f64::consts::SQRT_2;
+use types::Size2;
+
+/// Side of a navigation cell, in m.
+pub const NAV_CELL_SIZE: f64 = 1.0;
+
+/// Closer than this (in m) to a waypoint counts as reached.
+pub const WAYPOINT_DIST: f64 = 1.0;
+
+/// A path is planned again once the goal has moved this far (in m) from where it leads.
+pub const REPATH_DIST: f64 = 2.0;
+
+/// Ground steeper than this (rise over run) can't be walked on.
+pub const MAX_WALKABLE_SLOPE: f64 = 1.0;
+
+const LEVELS: [Level; 3] = [Level::Ground, Level::Walkway, Level::TowerTop];
+
+fn level_index(level: Level) -> usize {
+    match level {
+        Level::Ground => 0,
+        Level::Walkway => 1,
+        Level::TowerTop => 2,
+    }
+}
+
+/// A point on a path.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Waypoint {
+    pub pos: Position,
+    /// This waypoint is the far end of a connection that starts at the previous waypoint.
+    /// It is reached by passing through the connection, not by walking.
+    pub hop: bool,
+}
+
+/// The waypoints from (but not including) the start to the goal. The last one is the goal.
+pub type Path = Vec<Waypoint>;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct Area {
+    level: Level,
+    min: Position,
+    max: Position,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NavGrid {
+    /// Number of cells along x and y
+    size: Size2,
+    /// For every level, whether each cell can be walked on. Row by row, starting at y = 0
+    walkable: Vec<Vec<bool>>,
+    /// For every cell, the highest level an obstacle covers it on. Same order as `walkable`
+    obstacle_levels: Vec<Option<Level>>,
+    obstacles: Vec<Area>,
+    walkable_areas: Vec<Area>,
+    connections: HashMap<ConnectionId, Connection>,
+    /// Goes up with every change, so flow fields can tell whether they still hold
+    version: u64,
+}
+
+/// An entry in the open list of A* and Dijkstra.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Open {
+    priority: f64,
+    node: usize,
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    /// Reversed, so the `BinaryHeap` pops the lowest priority first. Ties go to the lowest node
+    /// so the search doesn't depend on the order things were pushed in.
+    fn cmp(&self, other: &Open) -> Ordering {
+        other
+            .priority
+            .partial_cmp(&self.priority)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Open) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// For every node reached: the node it was reached from, and the connection passed on the way.
+type CameFrom = HashMap<usize, (usize, Option<(Position, Position)>)>;
+
+/// A way out of a node: to a neighbour, or through a connection.
+#[derive(Clone, Debug)]
+struct Edge {
+    to: usize,
+    cost: f64,
+    /// The ends of the connection, if this edge goes through one
+    connection: Option<(Position, Position)>,
+}
+
+impl NavGrid {
+    pub fn new(terrain: &Terrain) -> NavGrid {
+        let mut nav = NavGrid {
+            size: Size2 { w: 0, h: 0 },
+            walkable: Vec::new(),
+            obstacle_levels: Vec::new(),
+            obstacles: Vec::new(),
+            walkable_areas: Vec::new(),
+            connections: HashMap::new(),
+            version: 0,
+        };
+        nav.rebuild(terrain);
+        nav
+    }
+
+    /// Redoes the walkable cells for a new terrain, keeping obstacles, walkable areas and
+    /// connections.
+    pub fn rebuild(&mut self, terrain: &Terrain) {
+        self.version += 1;
+        let map_size = terrain.size();
+        self.size = Size2 {
+            w: ((map_size.w as f64 / NAV_CELL_SIZE).ceil() as i32).max(1),
+            h: ((map_size.h as f64 / NAV_CELL_SIZE).ceil() as i32).max(1),
+        };
+        let cell_count = (self.size.w * self.size.h) as usize;
+        self.walkable = vec![vec![false; cell_count]; LEVELS.len()];
+        self.obstacle_levels = vec![None; cell_count];
+        for j in 0..self.size.h {
+            for i in 0..self.size.w {
+                let center = self.cell_center(Level::Ground, i, j);
+                let slope = terrain.slope(center.x, center.y);
+                self.walkable[0][(j * self.size.w + i) as usize] = slope <= MAX_WALKABLE_SLOPE;
+            }
+        }
+        for area in self.walkable_areas.clone() {
+            self.fill(area, true);
+        }
+        for area in self.obstacles.clone() {
+            self.fill(area, false);
+            self.raise_obstacle_level(area);
+        }
+    }
+
+    fn fill(&mut self, area: Area, walkable: bool) {
+        let (min_i, min_j) = self.cell_of(area.min);
+        let (max_i, max_j) = self.cell_of(area.max);
+        for j in min_j..(max_j + 1) {
+            for i in min_i..(max_i + 1) {
+                self.walkable[level_index(area.level)][(j * self.size.w + i) as usize] = walkable;
+            }
+        }
+    }
+
+    /// Blocks the rectangle from `min` to `max` on `level`, for walls and buildings.
+    pub fn add_obstacle(&mut self, level: Level, min: Position, max: Position) {
+        let area = Area { level, min, max };
+        self.version += 1;
+        self.obstacles.push(area);
+        self.fill(area, false);
+        self.raise_obstacle_level(area);
+    }
+
+    fn raise_obstacle_level(&mut self, area: Area) {
+        let (min_i, min_j) = self.cell_of(area.min);
+        let (max_i, max_j) = self.cell_of(area.max);
+        for j in min_j..(max_j + 1) {
+            for i in min_i..(max_i + 1) {
+                let level = &mut self.obstacle_levels[(j * self.size.w + i) as usize];
+                if level.is_none_or(|level| level_index(level) < level_index(area.level)) {
+                    *level = Some(area.level);
+                }
+            }
+        }
+    }
+
+    /// Takes away an obstacle added by `add_obstacle` with the same rectangle, for breached
+    /// walls. The cells under it are walkable again if `terrain` isn't too steep there.
+    pub fn remove_obstacle(
+        &mut self,
+        terrain: &Terrain,
+        level: Level,
+        min: Position,
+        max: Position,
+    ) {
+        let same = |a: &Position, b: &Position| a.x == b.x && a.y == b.y && a.level == b.level;
+        self.obstacles.retain(|area| {
+            !(area.level == level && same(&area.min, &min) && same(&area.max, &max))
+        });
+        self.rebuild(terrain);
+    }
+
+    /// Makes the rectangle from `min` to `max` on `level` walkable, for wall walkways and
+    /// tower tops. Obstacles still win.
+    pub fn add_walkable_area(&mut self, level: Level, min: Position, max: Position) {
+        let area = Area { level, min, max };
+        self.version += 1;
+        self.walkable_areas.push(area);
+        self.fill(area, true);
+        for area in self.obstacles.clone() {
+            self.fill(area, false);
+        }
+    }
+
+    /// The highest level with an obstacle covering the cell `(x, y)` is in, if any.
+    pub fn obstacle_level(&self, x: f64, y: f64) -> Option<Level> {
+        let (i, j) = self.cell_of(Position::new(x, y));
+        self.obstacle_levels[(j * self.size.w + i) as usize]
+    }
+
+    pub fn add_connection(&mut self, connection: Connection) {
+        self.version += 1;
+        self.connections.insert(connection.id, connection);
+    }
+
+    pub fn get_connection(&self, id: &ConnectionId) -> Option<&Connection> {
+        self.connections.get(id)
+    }
+
+    pub fn get_connection_mut(&mut self, id: &ConnectionId) -> Option<&mut Connection> {
+        self.version += 1;
+        self.connections.get_mut(id)
+    }
+
+    pub fn connections(&self) -> &HashMap<ConnectionId, Connection> {
+        &self.connections
+    }
+
+    /// The cell `pos` is in. Positions off the map count as the nearest cell on the edge.
+    fn cell_of(&self, pos: Position) -> (i32, i32) {
+        let i = (pos.x / NAV_CELL_SIZE).floor() as i32;
+        let j = (pos.y / NAV_CELL_SIZE).floor() as i32;
+        (i.max(0).min(self.size.w - 1), j.max(0).min(self.size.h - 1))
+    }
+
+    fn cell_center(&self, level: Level, i: i32, j: i32) -> Position {
+        Position::on_level(
+            (i as f64 + 0.5) * NAV_CELL_SIZE,
+            (j as f64 + 0.5) * NAV_CELL_SIZE,
+            level,
+        )
+    }
+
+    fn node_of(&self, pos: Position) -> usize {
+        let (i, j) = self.cell_of(pos);
+        self.node(level_index(pos.level), i, j)
+    }
+
+    fn node(&self, level: usize, i: i32, j: i32) -> usize {
+        level * (self.size.w * self.size.h) as usize + (j * self.size.w + i) as usize
+    }
+
+    /// Level index and cell of `node`.
+    fn unpack(&self, node: usize) -> (usize, i32, i32) {
+        let cell_count = (self.size.w * self.size.h) as usize;
+        let cell = (node % cell_count) as i32;
+        (node / cell_count, cell % self.size.w, cell / self.size.w)
+    }
+
+    fn node_walkable(&self, node: usize) -> bool {
+        let cell_count = (self.size.w * self.size.h) as usize;
+        self.walkable[node / cell_count][node % cell_count]
+    }
+
+    pub fn is_walkable(&self, pos: Position) -> bool {
+        self.node_walkable(self.node_of(pos))
+    }
+
+    /// Whether one can walk in a straight line from `from` to `to` without leaving the level
+    /// or crossing an unwalkable cell.
+    pub fn line_walkable(&self, from: Position, to: Position) -> bool {
+        if from.level != to.level {
+            return false;
+        }
+        let steps = (from.dist(&to) / (NAV_CELL_SIZE / 2.0)).ceil().max(1.0) as i32;
+        (0..(steps + 1)).all(|step| {
+            let t = step as f64 / steps as f64;
+            let pos = Position::on_level(
+                from.x + (to.x - from.x) * t,
+                from.y + (to.y - from.y) * t,
+                from.level,
+            );
+            self.is_walkable(pos)
+        })
+    }
+
+    fn edges(&self, node: usize, connections_by_node: &HashMap<usize, Vec<Edge>>) -> Vec<Edge> {
+        let (level, i, j) = self.unpack(node);
+        let mut edges = Vec::new();
+        for &(di, dj) in &[
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ] {
+            let (ni, nj) = (i + di, j + dj);
+            if ni < 0 || nj < 0 || ni >= self.size.w || nj >= self.size.h {
+                continue;
+            }
+            let to = self.node(level, ni, nj);
+            if !self.node_walkable(to) {
+                continue;
+            }
+            let diagonal = di != 0 && dj != 0;
+            // No cutting corners past something in the way
+            if diagonal
+                && (!self.node_walkable(self.node(level, i + di, j))
+                    || !self.node_walkable(self.node(level, i, j + dj)))
+            {
+                continue;
+            }
+            let cost = if diagonal { SQRT_2 } else { 1.0 } * NAV_CELL_SIZE;
+            edges.push(Edge {
+                to,
+                cost,
+                connection: None,
+            });
+        }
+        if let Some(connection_edges) = connections_by_node.get(&node) {
+            for edge in connection_edges {
+                edges.push(Edge {
+                    to: edge.to,
+                    cost: edge.cost,
+                    connection: edge.connection,
+                });
+            }
+        }
+        edges
+    }
+
+    /// The edges through all open connections, both ways, by the node they start from.
+    fn connection_edges(&self) -> HashMap<usize, Vec<Edge>> {
+        let mut ids: Vec<&ConnectionId> = self.connections.keys().collect();
+        ids.sort();
+        let mut edges: HashMap<usize, Vec<Edge>> = HashMap::new();
+        for id in ids {
+            let connection = &self.connections[id];
+            for &end in &[connection.a, connection.b] {
+                let (from, to) = match connection.ends(end) {
+                    Some(ends) => ends,
+                    None => continue,
+                };
+                let (from_node, to_node) = (self.node_of(from), self.node_of(to));
+                // Never less than the heuristic, or A* could miss a shorter way
+                let cost = connection.cost().max(self.heuristic(from_node, to_node));
+                edges.entry(from_node).or_default().push(Edge {
+                    to: to_node,
+                    cost,
+                    connection: Some((from, to)),
+                });
+            }
+        }
+        edges
+    }
+
+    /// Octile distance between the cells on the map, ignoring levels. Never more than the real
+    /// cost, since `connection_edges` makes connections cost at least this much.
+    fn heuristic(&self, a: usize, b: usize) -> f64 {
+        let (_, ai, aj) = self.unpack(a);
+        let (_, bi, bj) = self.unpack(b);
+        let dx = (ai - bi).abs() as f64;
+        let dy = (aj - bj).abs() as f64;
+        (dx.max(dy) - dx.min(dy) + SQRT_2 * dx.min(dy)) * NAV_CELL_SIZE
+    }
+
+    /// The shortest path from `from` to `to`, or `None` if there is no way there.
+    pub fn find_path(&self, from: Position, to: Position) -> Option<Path> {
+        let start = self.node_of(from);
+        let goal = self.node_of(to);
+        if !self.node_walkable(goal) {
+            return None;
+        }
+        let connections = self.connection_edges();
+        let mut cost: HashMap<usize, f64> = HashMap::new();
+        let mut came_from: CameFrom = HashMap::new();
+        let mut open = BinaryHeap::new();
+        cost.insert(start, 0.0);
+        open.push(Open {
+            priority: self.heuristic(start, goal),
+            node: start,
+        });
+        while let Some(Open { node, .. }) = open.pop() {
+            if node == goal {
+                return Some(self.build_path(start, goal, from, to, &came_from));
+            }
+            let node_cost = cost[&node];
+            for edge in self.edges(node, &connections) {
+                let new_cost = node_cost + edge.cost;
+                if cost.get(&edge.to).is_none_or(|&old| new_cost < old) {
+                    cost.insert(edge.to, new_cost);
+                    came_from.insert(edge.to, (node, edge.connection));
+                    open.push(Open {
+                        priority: new_cost + self.heuristic(edge.to, goal),
+                        node: edge.to,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn build_path(
+        &self,
+        start: usize,
+        goal: usize,
+        from: Position,
+        to: Position,
+        came_from: &CameFrom,
+    ) -> Path {
+        let mut reversed = Vec::new();
+        let mut node = goal;
+        while node != start {
+            let (prev, connection) = came_from[&node];
+            match connection {
+                Some((enter, exit)) => {
+                    reversed.push(Waypoint {
+                        pos: exit,
+                        hop: true,
+                    });
+                    reversed.push(Waypoint {
+                        pos: enter,
+                        hop: false,
+                    });
+                }
+                None => {
+                    let (level, i, j) = self.unpack(node);
+                    reversed.push(Waypoint {
+                        pos: self.cell_center(LEVELS[level], i, j),
+                        hop: false,
+                    });
+                }
+            }
+            node = prev;
+        }
+        reversed.reverse();
+        let mut path = reversed;
+        // End exactly on the goal instead of on the centre of its cell
+        match path.last() {
+            Some(last) if last.hop => {}
+            Some(_) => {
+                path.pop();
+            }
+            None => {}
+        }
+        path.push(Waypoint {
+            pos: to,
+            hop: false,
+        });
+        self.smooth(from, path)
+    }
+
+    /// Where following `edge` leads: the far end of its connection, or else the centre of the
+    /// cell it goes to.
+    fn edge_waypoint(&self, edge: &Edge) -> Waypoint {
+        match edge.connection {
+            Some((_, exit)) => Waypoint {
+                pos: exit,
+                hop: true,
+            },
+            None => {
+                let (level, i, j) = self.unpack(edge.to);
+                Waypoint {
+                    pos: self.cell_center(LEVELS[level], i, j),
+                    hop: false,
+                }
+            }
+        }
+    }
+
+    /// Drops every waypoint that can be skipped by walking straight to the one after it.
+    fn smooth(&self, from: Position, path: Path) -> Path {
+        let mut smoothed: Path = Vec::new();
+        let mut anchor = from;
+        for (k, waypoint) in path.iter().enumerate() {
+            let next = path.get(k + 1);
+            let needed = waypoint.hop
+                || next.is_none_or(|next| next.hop || !self.line_walkable(anchor, next.pos));
+            if needed {
+                smoothed.push(*waypoint);
+                anchor = waypoint.pos;
+            }
+        }
+        smoothed
+    }
+
+    /// How far every cell is from `to`, walking and using connections.
+    pub fn flow_field(&self, to: Position) -> FlowField {
+        let goal = self.node_of(to);
+        let connections = self.connection_edges();
+        let mut cost = vec![f64::INFINITY; LEVELS.len() * (self.size.w * self.size.h) as usize];
+        let mut open = BinaryHeap::new();
+        if self.node_walkable(goal) {
+            cost[goal] = 0.0;
+            open.push(Open {
+                priority: 0.0,
+                node: goal,
+            });
+        }
+        // Edges are the same both ways, so walking out from the goal gives the cost to get there
+        while let Some(Open { priority, node }) = open.pop() {
+            if priority > cost[node] {
+                continue;
+            }
+            for edge in self.edges(node, &connections) {
+                let new_cost = priority + edge.cost;
+                if new_cost < cost[edge.to] {
+                    cost[edge.to] = new_cost;
+                    open.push(Open {
+                        priority: new_cost,
+                        node: edge.to,
+                    });
+                }
+            }
+        }
+        FlowField {
+            goal: to,
+            cost,
+            connections,
+            version: self.version,
+        }
+    }
+}
+
+/// The distance to a goal from every cell, see `NavGrid::flow_field`.
+#[derive(Clone, Debug)]
+pub struct FlowField {
+    pub goal: Position,
+    cost: Vec<f64>,
+    /// The connections as they were when the field was built
+    connections: HashMap<usize, Vec<Edge>>,
+    /// `NavGrid::version` when the field was built
+    version: u64,
+}
+
+impl FlowField {
+    /// The distance left to the goal from `pos`, or `None` if it can't be reached from there.
+    pub fn cost(&self, nav: &NavGrid, pos: Position) -> Option<f64> {
+        let cost = self.cost[nav.node_of(pos)];
+        if cost.is_finite() {
+            Some(cost)
+        } else {
+            None
+        }
+    }
+
+    /// Whether this field still leads to `to` on `nav`: its goal is in the same cell and
+    /// nothing changed since it was built.
+    pub fn leads_to(&self, nav: &NavGrid, to: Position) -> bool {
+        self.version == nav.version && nav.node_of(self.goal) == nav.node_of(to)
+    }
+
+    /// The way out of `pos` that gets closest to the goal.
+    fn next_edge(&self, nav: &NavGrid, pos: Position) -> Option<Edge> {
+        let node = nav.node_of(pos);
+        if node == nav.node_of(self.goal) || !self.cost[node].is_finite() {
+            return None;
+        }
+        let mut best: Option<(f64, Edge)> = None;
+        for edge in nav.edges(node, &self.connections) {
+            let total = edge.cost + self.cost[edge.to];
+            if best
+                .as_ref()
+                .is_none_or(|&(best_cost, _)| total < best_cost)
+            {
+                best = Some((total, edge));
+            }
+        }
+        best.map(|(_, edge)| edge)
+    }
+
+    /// Where to go next from `pos` to get closer to the goal. `None` when already in the goal
+    /// cell or when the goal can't be reached from there.
+    pub fn next_waypoint(&self, nav: &NavGrid, pos: Position) -> Option<Waypoint> {
+        self.next_edge(nav, pos)
+            .map(|edge| nav.edge_waypoint(&edge))
+    }
+
+    /// A path from `from` to `to` that follows the field until `to` can be walked to in a
+    /// straight line. `None` if the field doesn't come by `to` that way, e.g. when `to` lies
+    /// behind the goal.
+    pub fn path_to(&self, nav: &NavGrid, from: Position, to: Position) -> Option<Path> {
+        let mut path: Path = Vec::new();
+        let mut pos = from;
+        // Every step gets closer to the goal, so this ends
+        while !nav.line_walkable(pos, to) {
+            let edge = self.next_edge(nav, pos)?;
+            if let Some((enter, _)) = edge.connection {
+                path.push(Waypoint {
+                    pos: enter,
+                    hop: false,
+                });
+            }
+            let waypoint = nav.edge_waypoint(&edge);
+            path.push(waypoint);
+            pos = waypoint.pos;
+        }
+        path.push(Waypoint {
+            pos: to,
+            hop: false,
+        });
+        Some(nav.smooth(from, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NavGrid;
+    use core::level::{Connection, ConnectionId, ConnectionKind, Level};
+    use core::position::Position;
+    use core::terrain::Terrain;
+    use types::Size2;
+
+    /// A 20 by 20 m field with a wall across it at y = 10, and stairs up onto the wall.
+    fn walled_field() -> NavGrid {
+        let mut nav = NavGrid::new(&Terrain::flat(Size2 { w: 20, h: 20 }));
+        nav.add_obstacle(
+            Level::Ground,
+            Position::new(0.0, 10.0),
+            Position::new(19.9, 10.9),
+        );
+        nav.add_walkable_area(
+            Level::Walkway,
+            Position::on_level(0.0, 10.0, Level::Walkway),
+            Position::on_level(19.9, 10.9, Level::Walkway),
+        );
+        nav.add_connection(Connection {
+            id: ConnectionId { id: 0 },
+            kind: ConnectionKind::Stairs,
+            a: Position::new(15.5, 8.5),
+            b: Position::on_level(15.5, 10.5, Level::Walkway),
+            open: true,
+        });
+        nav
+    }
+
+    fn add_gate(nav: &mut NavGrid, open: bool) {
+        nav.add_connection(Connection {
+            id: ConnectionId { id: 1 },
+            kind: ConnectionKind::GatePassage,
+            a: Position::new(3.5, 9.5),
+            b: Position::new(3.5, 11.5),
+            open,
+        });
+    }
+
+    #[test]
+    fn test_straight_path_is_smoothed() {
+        let nav = walled_field();
+        let to = Position::new(18.0, 2.0);
+        let path = nav.find_path(Position::new(1.0, 1.0), to).unwrap();
+        assert_eq!(path.len(), 1);
+        assert!(path[0].pos.dist(&to) < 1e-9);
+    }
+
+    #[test]
+    fn test_path_goes_through_gate() {
+        let mut nav = walled_field();
+        let from = Position::new(2.0, 2.0);
+        let to = Position::new(2.0, 18.0);
+        assert!(nav.find_path(from, to).is_none());
+        add_gate(&mut nav, false);
+        assert!(nav.find_path(from, to).is_none());
+        add_gate(&mut nav, true);
+        let path = nav.find_path(from, to).unwrap();
+        let hops: Vec<_> = path.iter().filter(|w| w.hop).collect();
+        assert_eq!(hops.len(), 1);
+        assert!(hops[0].pos.dist(&Position::new(3.5, 11.5)) < 1e-9);
+        for waypoint in &path {
+            assert!(nav.is_walkable(waypoint.pos));
+        }
+    }
+
+    #[test]
+    fn test_path_climbs_stairs() {
+        let nav = walled_field();
+        let to = Position::on_level(2.0, 10.5, Level::Walkway);
+        let path = nav.find_path(Position::new(2.0, 2.0), to).unwrap();
+        let hop = path.iter().position(|w| w.hop).unwrap();
+        assert!(path[hop - 1].pos.dist(&Position::new(15.5, 8.5)) < 1e-9);
+        assert_eq!(path[hop].pos.level, Level::Walkway);
+        assert_eq!(path.last().unwrap().pos.level, Level::Walkway);
+    }
+
+    #[test]
+    fn test_flow_field_leads_to_goal() {
+        let mut nav = walled_field();
+        add_gate(&mut nav, true);
+        let goal = Position::new(10.0, 18.0);
+        let field = nav.flow_field(goal);
+        let mut pos = Position::new(18.0, 1.0);
+        let mut hops = 0;
+        for _ in 0..100 {
+            match field.next_waypoint(&nav, pos) {
+                Some(waypoint) => {
+                    if waypoint.hop {
+                        hops += 1;
+                    }
+                    pos = waypoint.pos;
+                }
+                None => break,
+            }
+        }
+        assert_eq!(hops, 1);
+        assert!(pos.dist(&goal) < 1.0);
+        // Off to the side of the goal, through the same gate
+        let to = Position::new(2.0, 15.0);
+        let path = field.path_to(&nav, Position::new(18.0, 1.0), to).unwrap();
+        assert_eq!(path.iter().filter(|w| w.hop).count(), 1);
+        assert!(path.last().unwrap().pos.dist(&to) < 1e-9);
+        assert!(field.leads_to(&nav, Position::new(10.2, 18.3)));
+        nav.get_connection_mut(&ConnectionId { id: 1 })
+            .unwrap()
+            .open = false;
+        assert!(!field.leads_to(&nav, goal));
+        assert!(field
+            .cost(&nav, Position::on_level(5.0, 5.0, Level::TowerTop))
+            .is_none());
+    }
+}
@@ -0,0 +1,43 @@
+/// Players a battle can have. One less than the ids that fit in a `PlayerId`, so the counter
+/// handing them out doesn't overflow.
+pub const MAX_PLAYERS: usize = 255;
+
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PlayerId {
+    pub id: u8,
+}
+
+/// Players on the same team are allies, all others are enemies.
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TeamId {
+    pub id: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Controller {
+    Human,
+    Ai,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Player {
+    pub id: PlayerId,
+    pub name: String,
+    /// The people this player leads, e.g. "Rome"
+    pub faction: String,
+    pub team: TeamId,
+    /// Tint of the soldiers of this player, RGBA
+    pub colour: [f32; 4],
+    pub controller: Controller,
+}
+
+impl Player {
+    pub fn is_human(&self) -> bool {
+        self.controller == Controller::Human
+    }
+
+    pub fn is_ally_of(&self, other: &Player) -> bool {
+        self.team == other.team
+    }
+}
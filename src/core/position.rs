@@ -1,29 +1,52 @@
-use cgmath::Vector3;
+use cgmath::{Rad, Vector2, Vector3};
+use core::level::Level;
+use core::terrain::Terrain;
 use types::WorldPos;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Position {
     pub x: f64,
     pub y: f64,
-    pub level: u8,
+    #[serde(default)]
+    pub level: Level,
 }
 
 impl Position {
     pub fn new(x: f64, y: f64) -> Position {
         Position {
-            x: x,
-            y: y,
-            level: 0,
+            x,
+            y,
+            level: Level::Ground,
         }
     }
 
-    pub fn to_world_pos(&self) -> WorldPos {
+    pub fn on_level(x: f64, y: f64, level: Level) -> Position {
+        Position { x, y, level }
+    }
+
+    /// The point in the world, on the ground of `terrain` or on top of the level it is on.
+    pub fn to_world_pos(&self, terrain: &Terrain) -> WorldPos {
         WorldPos {
             v: Vector3 {
                 x: self.x,
                 y: self.y,
-                z: 0.0,
+                z: terrain.height(self.x, self.y) + self.level.height(),
             },
         }
     }
+
+    /// Returns the position at `local` from this one, where `local` is expressed in a frame
+    /// facing `rot`: x points to the right, y points forward.
+    pub fn offset(&self, local: Vector2<f64>, rot: Rad<f32>) -> Position {
+        let (sin, cos) = (rot.0 as f64).sin_cos();
+        Position {
+            x: self.x + local.x * cos - local.y * sin,
+            y: self.y + local.x * sin + local.y * cos,
+            level: self.level,
+        }
+    }
+
+    pub fn dist(&self, other: &Position) -> f64 {
+        (other.x - self.x).hypot(other.y - self.y)
+    }
 }
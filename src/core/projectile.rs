@@ -0,0 +1,137 @@
+use cgmath::{Rad, Vector2};
+use core::movement::heading_of;
+use core::player::PlayerId;
+use core::position::Position;
+use core::terrain::Terrain;
+use core::unit::IndivId;
+use core::weapon::WeaponType;
+use types::WorldPos;
+
+/// Anyone standing closer than this (in m) to where a projectile lands can be hit by it.
+pub const HIT_RADIUS: f64 = 0.5;
+
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ProjectileId {
+    pub id: u32,
+}
+
+/// A javelin, arrow or stone in flight. It flies in an arc from `from` to `to` and only
+/// hits something once it lands.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Projectile {
+    pub id: ProjectileId,
+    pub shooter_id: IndivId,
+    pub player_id: PlayerId,
+    pub weapon_type: WeaponType,
+    pub from: Position,
+    pub to: Position,
+    /// Seconds from launch to landing
+    pub flight_time: f64,
+    /// Seconds since launch
+    pub time: f64,
+}
+
+impl Projectile {
+    pub fn new(
+        id: ProjectileId,
+        shooter_id: IndivId,
+        player_id: PlayerId,
+        weapon_type: WeaponType,
+        from: Position,
+        to: Position,
+    ) -> Projectile {
+        let speed = weapon_type.stats().projectile_speed;
+        Projectile {
+            id,
+            shooter_id,
+            player_id,
+            weapon_type,
+            from,
+            to,
+            flight_time: from.dist(&to) / speed,
+            time: 0.0,
+        }
+    }
+
+    /// Fraction of the flight done, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        if self.flight_time <= 0.0 {
+            1.0
+        } else {
+            (self.time / self.flight_time).min(1.0)
+        }
+    }
+
+    pub fn has_landed(&self) -> bool {
+        self.progress() >= 1.0
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        self.time += dt;
+    }
+
+    /// Where the projectile is above the ground.
+    pub fn pos(&self) -> Position {
+        let t = self.progress();
+        Position {
+            x: self.from.x + (self.to.x - self.from.x) * t,
+            y: self.from.y + (self.to.y - self.from.y) * t,
+            level: self.to.level,
+        }
+    }
+
+    /// Height above the straight line from `from` to `to`. The arc peaks halfway at a quarter
+    /// of the distance, like a throw at 45 degrees.
+    pub fn height(&self) -> f64 {
+        let t = self.progress();
+        let apex = self.from.dist(&self.to) / 4.0;
+        4.0 * apex * t * (1.0 - t)
+    }
+
+    pub fn to_world_pos(&self, terrain: &Terrain) -> WorldPos {
+        let t = self.progress();
+        let from_z = self.from.to_world_pos(terrain).v.z;
+        let to_z = self.to.to_world_pos(terrain).v.z;
+        let mut pos = self.pos().to_world_pos(terrain);
+        pos.v.z = from_z + (to_z - from_z) * t + self.height();
+        pos
+    }
+
+    /// The direction the projectile flies in, seen from above.
+    pub fn rot(&self) -> Rad<f32> {
+        heading_of(Vector2::new(
+            self.to.x - self.from.x,
+            self.to.y - self.from.y,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Projectile, ProjectileId};
+    use core::player::PlayerId;
+    use core::position::Position;
+    use core::unit::IndivId;
+    use core::weapon::WeaponType;
+
+    #[test]
+    fn test_projectile_flies_in_arc() {
+        let mut p = Projectile::new(
+            ProjectileId { id: 0 },
+            IndivId { id: 0 },
+            PlayerId { id: 0 },
+            WeaponType::Bow,
+            Position::new(0.0, 0.0),
+            Position::new(90.0, 0.0),
+        );
+        assert!(p.height() < 1e-9);
+        p.update(p.flight_time / 2.0);
+        assert!((p.pos().x - 45.0).abs() < 1e-9);
+        assert!((p.height() - 22.5).abs() < 1e-9);
+        assert!(!p.has_landed());
+        p.update(p.flight_time);
+        assert!(p.has_landed());
+        assert!((p.pos().x - 90.0).abs() < 1e-9);
+        assert!(p.height() < 1e-9);
+    }
+}
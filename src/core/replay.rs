@@ -0,0 +1,244 @@
+//! Recording a battle and watching it again. A replay is the battle as it was when recording
+//! started plus every order given after that. Since the simulation is deterministic, giving
+//! the same orders at the same ticks plays out the same battle.
+
+use core::battlefield::{Battlefield, MAX_STEPS_PER_TICK, TICKS_PER_SECOND, TICK_TIME};
+use core::event::BattleEvent;
+use core::order::RecordedOrder;
+use types::Time;
+
+/// Ticks between the snapshots kept while playing back, to seek back from.
+pub const SNAPSHOT_INTERVAL: u64 = 10 * TICKS_PER_SECOND as u64;
+
+pub const MIN_SPEED: f64 = 0.25;
+pub const MAX_SPEED: f64 = 8.0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    start: Battlefield,
+    orders: Vec<RecordedOrder>,
+    /// In ticks
+    length: u64,
+}
+
+impl Replay {
+    /// Starts recording `battlefield` from where it is now.
+    pub fn new(battlefield: &Battlefield) -> Replay {
+        Replay {
+            start: battlefield.clone(),
+            orders: Vec::new(),
+            length: 0,
+        }
+    }
+
+    /// Brings the recording up to date with `battlefield`, which must be the recorded one.
+    pub fn record(&mut self, battlefield: &Battlefield) {
+        assert!(battlefield.tick_count() >= self.start.tick_count());
+        let first = self.start.orders().len();
+        self.orders = battlefield.orders()[first..].to_vec();
+        self.length = battlefield.tick_count() - self.start.tick_count();
+    }
+
+    /// The battle as it was when recording started.
+    pub fn start(&self) -> &Battlefield {
+        &self.start
+    }
+
+    pub fn orders(&self) -> &[RecordedOrder] {
+        &self.orders
+    }
+
+    /// Number of ticks recorded.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+}
+
+/// Plays a replay back. Seeking goes back to the nearest snapshot before the wanted tick and
+/// simulates from there.
+#[derive(Debug)]
+pub struct Playback {
+    replay: Replay,
+    battlefield: Battlefield,
+    /// Index in `replay.orders` of the next order to give
+    next_order: usize,
+    /// A copy of the battle every `SNAPSHOT_INTERVAL` ticks, as far as it was played
+    snapshots: Vec<Battlefield>,
+    paused: bool,
+    speed: f64,
+    time_acc: f64,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        let battlefield = replay.start.clone();
+        let snapshots = vec![battlefield.clone()];
+        Playback {
+            replay,
+            battlefield,
+            next_order: 0,
+            snapshots,
+            paused: false,
+            speed: 1.0,
+            time_acc: 0.0,
+        }
+    }
+
+    pub fn battlefield(&self) -> &Battlefield {
+        &self.battlefield
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Ticks played since the start of the replay.
+    pub fn position(&self) -> u64 {
+        self.battlefield.tick_count() - self.replay.start.tick_count()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position() >= self.replay.length
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// How many times faster than real time the replay plays, between `MIN_SPEED` and
+    /// `MAX_SPEED`.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    /// Like `Battlefield::tick`, but `dtime` is scaled by the speed and nothing happens while
+    /// paused or after the end of the replay.
+    pub fn tick(&mut self, dtime: Time) -> Vec<BattleEvent> {
+        let mut events = Vec::new();
+        if self.paused {
+            return events;
+        }
+        self.time_acc += dtime.n as f64 * self.speed;
+        let max_steps = (MAX_STEPS_PER_TICK as f64 * self.speed).ceil() as u32;
+        let mut steps = 0;
+        while self.time_acc >= TICK_TIME && !self.is_finished() {
+            self.time_acc -= TICK_TIME;
+            events.extend(self.step());
+            steps += 1;
+            if steps >= max_steps {
+                self.time_acc = 0.0;
+                break;
+            }
+        }
+        events
+    }
+
+    /// Gives the orders of the current tick and does a step.
+    fn step(&mut self) -> Vec<BattleEvent> {
+        while let Some(recorded) = self.replay.orders.get(self.next_order).cloned() {
+            if recorded.tick > self.battlefield.tick_count() {
+                break;
+            }
+            self.battlefield.give_order(recorded.order);
+            self.next_order += 1;
+        }
+        // The replay has them already, the snapshots don't need a copy each
+        self.battlefield.clear_orders();
+        let events = self.battlefield.step();
+        let position = self.position();
+        if position.is_multiple_of(SNAPSHOT_INTERVAL)
+            && position / SNAPSHOT_INTERVAL == self.snapshots.len() as u64
+        {
+            self.snapshots.push(self.battlefield.clone());
+        }
+        events
+    }
+
+    /// Jumps to `position` ticks after the start of the replay, or its end if that is
+    /// earlier. What happened on the way is not reported.
+    pub fn seek(&mut self, position: u64) {
+        let position = position.min(self.replay.length);
+        let snapshot = ((position / SNAPSHOT_INTERVAL) as usize).min(self.snapshots.len() - 1);
+        if position < self.position() || snapshot as u64 * SNAPSHOT_INTERVAL > self.position() {
+            self.battlefield = self.snapshots[snapshot].clone();
+            let tick = self.battlefield.tick_count();
+            self.next_order = self
+                .replay
+                .orders
+                .iter()
+                .position(|recorded| recorded.tick >= tick)
+                .unwrap_or(self.replay.orders.len());
+        }
+        while self.position() < position {
+            self.step();
+        }
+        self.time_acc = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Playback, Replay, SNAPSHOT_INTERVAL};
+    use cgmath::Rad;
+    use core::battlefield::tests::{indiv_states, new_fight};
+    use core::battlefield::Battlefield;
+    use core::company::{CompId, Formation, FormationShape};
+    use core::player::{Controller, PlayerId};
+    use core::position::Position;
+    use core::unit::IndivId;
+    use std::f32::consts::PI;
+
+    /// A fight with orders given during the battle too, so there is something to replay.
+    fn recorded_fight() -> (Battlefield, Replay) {
+        let mut bf = new_fight(11);
+        let (ours, theirs) = (CompId { id: 0 }, CompId { id: 1 });
+        let mut replay = Replay::new(&bf);
+        bf.order_company_move(ours, Position::new(2.0, 6.0), Rad(0.0));
+        for tick in 0..(20 * 30) {
+            match tick {
+                40 => bf.order_indiv_move(IndivId { id: 3 }, Position::new(0.0, 1.0), Rad(0.0)),
+                50 => bf.order_company_move(theirs, Position::new(2.0, 9.0), Rad(PI)),
+                90 => bf.order_indiv_rejoin(IndivId { id: 3 }),
+                120 => bf.set_formation(ours, Formation::new(FormationShape::Column, 4, 7)),
+                150 => bf.set_controller(PlayerId { id: 1 }, Controller::Ai),
+                _ => {}
+            }
+            bf.step();
+        }
+        replay.record(&bf);
+        (bf, replay)
+    }
+
+    #[test]
+    fn test_playback_repeats_the_battle() {
+        let (bf, replay) = recorded_fight();
+        assert_eq!(replay.orders().len(), 6);
+        let mut playback = Playback::new(replay);
+        playback.seek(u64::MAX);
+        assert!(playback.is_finished());
+        assert_eq!(playback.battlefield().tick_count(), bf.tick_count());
+        assert_eq!(indiv_states(playback.battlefield()), indiv_states(&bf));
+    }
+
+    #[test]
+    fn test_seek_back_and_forth() {
+        let (_, replay) = recorded_fight();
+        let mut straight = Playback::new(replay.clone());
+        straight.seek(SNAPSHOT_INTERVAL + 70);
+        let expected = indiv_states(straight.battlefield());
+        let mut playback = Playback::new(replay);
+        playback.seek(2 * SNAPSHOT_INTERVAL + 30);
+        playback.seek(40);
+        assert_eq!(playback.position(), 40);
+        playback.seek(SNAPSHOT_INTERVAL + 70);
+        assert_eq!(indiv_states(playback.battlefield()), expected);
+    }
+}
@@ -0,0 +1,123 @@
+//! Saving a battle in progress and picking it up again exactly where it was left, and saving
+//! replays.
+//!
+//! A save file starts with `MAGIC` and the version of the format it was written in, followed
+//! by the whole `Battlefield` in bincode. Bincode keeps every float to the bit, so a loaded
+//! battle goes on exactly like the original would have. Replay files are the same, but start
+//! with `REPLAY_MAGIC` and hold a `Replay`.
+
+use bincode;
+use core::battlefield::Battlefield;
+use core::replay::Replay;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Write};
+
+/// The first bytes of every save file
+pub const MAGIC: [u8; 4] = *b"ATGS";
+
+/// The first bytes of every replay file
+pub const REPLAY_MAGIC: [u8; 4] = *b"ATGR";
+
+/// Version of the format written by `write_battle` and `write_replay`. Bump it whenever
+/// something saved in `Battlefield` changes, and teach `read_battle` to upgrade or reject the
+/// older versions.
+pub const SAVE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(String),
+    /// Not a save file at all
+    NotASave,
+    /// Written by a version of the game whose saves can't be read anymore, or by a newer one
+    UnsupportedVersion(u32),
+    Corrupt(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveError::Io(ref reason) => write!(f, "Can't access the save file ({})", reason),
+            SaveError::NotASave => write!(f, "Not a save file"),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "Save file version {} can't be loaded, only version {}",
+                version, SAVE_VERSION
+            ),
+            SaveError::Corrupt(ref reason) => write!(f, "Save file is damaged ({})", reason),
+        }
+    }
+}
+
+impl Error for SaveError {}
+
+pub fn write_battle<W: Write>(writer: &mut W, battlefield: &Battlefield) -> Result<(), SaveError> {
+    write(writer, MAGIC, battlefield)
+}
+
+pub fn read_battle<R: Read>(reader: &mut R) -> Result<Battlefield, SaveError> {
+    read(reader, MAGIC)
+}
+
+pub fn write_replay<W: Write>(writer: &mut W, replay: &Replay) -> Result<(), SaveError> {
+    write(writer, REPLAY_MAGIC, replay)
+}
+
+pub fn read_replay<R: Read>(reader: &mut R) -> Result<Replay, SaveError> {
+    read(reader, REPLAY_MAGIC)
+}
+
+fn write<W: Write, T: Serialize>(
+    writer: &mut W,
+    magic: [u8; 4],
+    value: &T,
+) -> Result<(), SaveError> {
+    writer
+        .write_all(&magic)
+        .map_err(|err| SaveError::Io(err.to_string()))?;
+    bincode::serialize_into(&mut *writer, &SAVE_VERSION)
+        .and_then(|_| bincode::serialize_into(&mut *writer, value))
+        .map_err(|err| SaveError::Io(err.to_string()))
+}
+
+fn read<R: Read, T: DeserializeOwned>(reader: &mut R, expected: [u8; 4]) -> Result<T, SaveError> {
+    let mut magic = [0; 4];
+    if reader.read_exact(&mut magic).is_err() || magic != expected {
+        return Err(SaveError::NotASave);
+    }
+    let version: u32 = bincode::deserialize_from(&mut *reader).map_err(|_| SaveError::NotASave)?;
+    match version {
+        SAVE_VERSION => bincode::deserialize_from(&mut *reader)
+            .map_err(|err| SaveError::Corrupt(err.to_string())),
+        _ => Err(SaveError::UnsupportedVersion(version)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_battle, read_replay, write_battle, SaveError, MAGIC};
+    use bincode;
+    use core::battlefield::Battlefield;
+
+    #[test]
+    fn test_other_files_are_rejected() {
+        match read_battle(&mut &b"{ \"name\": \"Test\" }"[..]) {
+            Err(SaveError::NotASave) => {}
+            other => panic!("{:?}", other),
+        }
+        let mut future = MAGIC.to_vec();
+        future.extend(bincode::serialize(&99u32).unwrap());
+        match read_battle(&mut &future[..]) {
+            Err(SaveError::UnsupportedVersion(99)) => {}
+            other => panic!("{:?}", other),
+        }
+        let mut save = Vec::new();
+        write_battle(&mut save, &Battlefield::new(Vec::new(), 0)).unwrap();
+        match read_replay(&mut &save[..]) {
+            Err(SaveError::NotASave) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
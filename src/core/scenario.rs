@@ -0,0 +1,553 @@
+//! Battles described in files. A scenario lists everything a battle starts with: the map,
+//! the structures on it, the players and their armies, and how the battle is won.
+//!
+//! Scenarios are JSON files in `assets/scenarios/`, see `skirmish.json` for an example.
+
+use cgmath::Rad;
+use core::battlefield::Battlefield;
+use core::company::Formation;
+use core::deployment::Zone;
+use core::level::{ConnectionKind, Level};
+use core::misc::seeded_rng;
+use core::player::{Controller, TeamId, MAX_PLAYERS};
+use core::position::Position;
+use core::structure::StructureKind;
+use core::terrain::Terrain;
+use core::unit::UnitType;
+use core::victory::VictoryCondition;
+use serde_json;
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fmt;
+use types::Size2;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    /// In m
+    pub map_size: Size2,
+    /// Seed of the randomness of the battle. Without one every battle goes differently.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub terrain: TerrainDesc,
+    #[serde(default)]
+    pub structures: Vec<Structure>,
+    pub players: Vec<PlayerDesc>,
+    #[serde(default)]
+    pub victory_conditions: Vec<VictoryCondition>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum TerrainDesc {
+    #[default]
+    Flat,
+    /// Rolling hills, at most `max_height` m high
+    Hills { max_height: f64 },
+    /// A greyscale png in the assets folder, see `assets::load_heightmap`
+    Heightmap {
+        file: String,
+        spacing: f64,
+        max_height: f64,
+    },
+}
+
+/// Something built on the map. Connections and the passages of gates get their ids in the order
+/// they are listed, starting at 0, so victory conditions can refer to them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum Structure {
+    Obstacle {
+        level: Level,
+        min: Position,
+        max: Position,
+    },
+    WalkableArea {
+        level: Level,
+        min: Position,
+        max: Position,
+    },
+    Connection {
+        kind: ConnectionKind,
+        a: Position,
+        b: Position,
+        #[serde(default = "default_open")]
+        open: bool,
+    },
+    /// A wall segment, gate or tower on the ground that can be broken down, see `structure`
+    Fortification {
+        kind: StructureKind,
+        min: Position,
+        max: Position,
+    },
+}
+
+fn default_open() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerDesc {
+    pub name: String,
+    pub faction: String,
+    pub team: TeamId,
+    pub colour: [f32; 4],
+    pub controller: Controller,
+    pub companies: Vec<CompanyDesc>,
+    /// Where the player deploys its companies before the battle. Without one it may deploy
+    /// anywhere.
+    #[serde(default)]
+    pub deployment_zone: Option<Zone>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompanyDesc {
+    /// Name of the unit type, the name of its folder in `assets/units/`
+    pub unit_type: String,
+    pub count: usize,
+    pub pos: Position,
+    /// In degrees, clockwise from north (+y)
+    #[serde(default)]
+    pub facing: f32,
+    pub formation: Formation,
+    /// Starting xp of its soldiers, see `veterancy`
+    #[serde(default)]
+    pub xp: i8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScenarioError {
+    /// The file, or a file it refers to, couldn't be read
+    Read {
+        path: String,
+        reason: String,
+    },
+    /// Not valid JSON, or not a scenario
+    Parse(String),
+    BadMapSize(Size2),
+    TerrainSizeMismatch {
+        expected: Size2,
+        found: Size2,
+    },
+    NoPlayers,
+    TooManyPlayers(usize),
+    UnknownUnitType {
+        player: String,
+        unit_type: String,
+    },
+    EmptyCompany {
+        player: String,
+        company: usize,
+    },
+    BadFormation {
+        player: String,
+        company: usize,
+    },
+    OutsideMap {
+        player: String,
+        company: usize,
+    },
+    /// Fewer than 3 corners, or some outside the map
+    BadDeploymentZone(String),
+    /// The index of a structure that doesn't lie entirely on the map
+    StructureOutsideMap(usize),
+    UnknownTeam(TeamId),
+    UnknownGate(u32),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScenarioError::Read {
+                ref path,
+                ref reason,
+            } => write!(f, "Can't read '{}' ({})", path, reason),
+            ScenarioError::Parse(ref reason) => write!(f, "Bad scenario: {}", reason),
+            ScenarioError::BadMapSize(size) => {
+                write!(f, "Map size must be positive, not {}x{}", size.w, size.h)
+            }
+            ScenarioError::TerrainSizeMismatch { expected, found } => write!(
+                f,
+                "Terrain is {}x{} m, but the map is {}x{} m",
+                found.w, found.h, expected.w, expected.h
+            ),
+            ScenarioError::NoPlayers => write!(f, "A scenario needs at least one player"),
+            ScenarioError::TooManyPlayers(count) => {
+                write!(
+                    f,
+                    "{} players is too many, at most {} fit",
+                    count, MAX_PLAYERS
+                )
+            }
+            ScenarioError::UnknownUnitType {
+                ref player,
+                ref unit_type,
+            } => write!(
+                f,
+                "Player '{}' has unknown unit type '{}'",
+                player, unit_type
+            ),
+            ScenarioError::EmptyCompany {
+                ref player,
+                company,
+            } => write!(
+                f,
+                "Company {} of player '{}' has no soldiers",
+                company, player
+            ),
+            ScenarioError::BadFormation {
+                ref player,
+                company,
+            } => write!(
+                f,
+                "Company {} of player '{}' needs a formation at least 1 wide and deep",
+                company, player
+            ),
+            ScenarioError::OutsideMap {
+                ref player,
+                company,
+            } => write!(
+                f,
+                "Company {} of player '{}' stands outside the map",
+                company, player
+            ),
+            ScenarioError::BadDeploymentZone(ref player) => write!(
+                f,
+                "Deployment zone of player '{}' needs at least 3 corners, all on the map",
+                player
+            ),
+            ScenarioError::StructureOutsideMap(i) => {
+                write!(f, "Structure {} lies outside the map", i)
+            }
+            ScenarioError::UnknownTeam(team) => {
+                write!(f, "Victory condition for team {} without players", team.id)
+            }
+            ScenarioError::UnknownGate(id) => {
+                write!(
+                    f,
+                    "Victory condition for connection {}, which isn't there",
+                    id
+                )
+            }
+        }
+    }
+}
+
+impl Error for ScenarioError {}
+
+impl Scenario {
+    pub fn from_json(json: &str) -> Result<Scenario, ScenarioError> {
+        serde_json::from_str(json).map_err(|err| ScenarioError::Parse(err.to_string()))
+    }
+
+    /// Sets up the battle. `seed` is only used when the scenario has none of its own.
+    /// `load_heightmap` loads a heightmap from a file, with a spacing and a maximum height.
+    pub fn build<F>(
+        &self,
+        unit_types: Vec<UnitType>,
+        seed: u64,
+        load_heightmap: F,
+    ) -> Result<Battlefield, ScenarioError>
+    where
+        F: FnOnce(&str, f64, f64) -> Result<Terrain, ScenarioError>,
+    {
+        let size = self.map_size;
+        if size.w <= 0 || size.h <= 0 {
+            return Err(ScenarioError::BadMapSize(size));
+        }
+        if self.players.is_empty() {
+            return Err(ScenarioError::NoPlayers);
+        }
+        if self.players.len() > MAX_PLAYERS {
+            return Err(ScenarioError::TooManyPlayers(self.players.len()));
+        }
+        let seed = self.seed.unwrap_or(seed);
+        let terrain = match self.terrain {
+            TerrainDesc::Flat => Terrain::flat(size),
+            TerrainDesc::Hills { max_height } => {
+                Terrain::generate(&mut seeded_rng(seed), size, max_height)
+            }
+            TerrainDesc::Heightmap {
+                ref file,
+                spacing,
+                max_height,
+            } => load_heightmap(file, spacing, max_height)?,
+        };
+        let found = terrain.size();
+        if found != size {
+            return Err(ScenarioError::TerrainSizeMismatch {
+                expected: size,
+                found,
+            });
+        }
+        let mut battlefield = Battlefield::new(unit_types, seed);
+        battlefield.set_terrain(terrain);
+        let mut connections = 0;
+        for (i, structure) in self.structures.iter().enumerate() {
+            self.check_structure(i, structure)?;
+            match *structure {
+                Structure::Obstacle { level, min, max } => {
+                    battlefield.add_obstacle(level, min, max)
+                }
+                Structure::WalkableArea { level, min, max } => {
+                    battlefield.add_walkable_area(level, min, max)
+                }
+                Structure::Connection { kind, a, b, open } => {
+                    let id = battlefield.add_connection(kind, a, b);
+                    battlefield.set_connection_open(id, open);
+                    connections += 1;
+                }
+                Structure::Fortification { kind, min, max } => {
+                    battlefield.add_structure(kind, min, max);
+                    if kind == StructureKind::Gate {
+                        connections += 1;
+                    }
+                }
+            }
+        }
+        for player in &self.players {
+            let player_id = battlefield.add_player(
+                &player.name,
+                &player.faction,
+                player.team,
+                player.colour,
+                player.controller,
+            );
+            if let Some(ref zone) = player.deployment_zone {
+                self.check_zone(player, zone)?;
+                battlefield.set_deployment_zone(player_id, zone.clone());
+            }
+            for (i, company) in player.companies.iter().enumerate() {
+                let type_id = battlefield
+                    .get_unit_type_id(&company.unit_type)
+                    .ok_or_else(|| ScenarioError::UnknownUnitType {
+                        player: player.name.clone(),
+                        unit_type: company.unit_type.clone(),
+                    })?;
+                self.check_company(player, i, company)?;
+                let comp_id = battlefield.add_company(
+                    player_id,
+                    type_id,
+                    company.count,
+                    company.pos,
+                    Rad(-company.facing * PI / 180.0),
+                    company.formation,
+                );
+                if company.xp > 0 {
+                    battlefield.set_company_xp(comp_id, company.xp);
+                }
+            }
+        }
+        for &condition in &self.victory_conditions {
+            match condition {
+                VictoryCondition::HoldGate { gate, team, .. } => {
+                    if gate.id >= connections {
+                        return Err(ScenarioError::UnknownGate(gate.id));
+                    }
+                    if !self.players.iter().any(|player| player.team == team) {
+                        return Err(ScenarioError::UnknownTeam(team));
+                    }
+                }
+                VictoryCondition::TimeLimit {
+                    winner: Some(team), ..
+                } if !self.players.iter().any(|player| player.team == team) => {
+                    return Err(ScenarioError::UnknownTeam(team));
+                }
+                _ => {}
+            }
+            battlefield.add_victory_condition(condition);
+        }
+        Ok(battlefield)
+    }
+
+    fn check_company(
+        &self,
+        player: &PlayerDesc,
+        i: usize,
+        company: &CompanyDesc,
+    ) -> Result<(), ScenarioError> {
+        let player = player.name.clone();
+        if company.count == 0 {
+            return Err(ScenarioError::EmptyCompany { player, company: i });
+        }
+        if company.formation.width == 0 || company.formation.depth == 0 {
+            return Err(ScenarioError::BadFormation { player, company: i });
+        }
+        if !self.on_map(company.pos) {
+            return Err(ScenarioError::OutsideMap { player, company: i });
+        }
+        Ok(())
+    }
+
+    fn check_structure(&self, i: usize, structure: &Structure) -> Result<(), ScenarioError> {
+        let (a, b) = match *structure {
+            Structure::Obstacle { min, max, .. }
+            | Structure::WalkableArea { min, max, .. }
+            | Structure::Fortification { min, max, .. } => (min, max),
+            Structure::Connection { a, b, .. } => (a, b),
+        };
+        if !self.on_map(a) || !self.on_map(b) {
+            return Err(ScenarioError::StructureOutsideMap(i));
+        }
+        Ok(())
+    }
+
+    fn on_map(&self, p: Position) -> bool {
+        let (w, h) = (self.map_size.w as f64, self.map_size.h as f64);
+        p.x >= 0.0 && p.y >= 0.0 && p.x <= w && p.y <= h
+    }
+
+    fn check_zone(&self, player: &PlayerDesc, zone: &Zone) -> Result<(), ScenarioError> {
+        let on_map = zone.points.iter().all(|&p| self.on_map(p));
+        if zone.points.len() < 3 || !on_map {
+            return Err(ScenarioError::BadDeploymentZone(player.name.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Scenario, ScenarioError};
+    use core::level::ConnectionId;
+    use core::player::PlayerId;
+    use core::position::Position;
+    use core::structure::StructureKind;
+    use core::unit::test_unit_type;
+
+    const SCENARIO: &str = r#"{
+        "name": "Test",
+        "map_size": { "w": 20, "h": 20 },
+        "seed": 3,
+        "structures": [
+            { "Obstacle": { "level": "Ground", "min": { "x": 0, "y": 9 }, "max": { "x": 8, "y": 10 } } },
+            { "Connection": { "kind": "GatePassage", "a": { "x": 10, "y": 8 }, "b": { "x": 10, "y": 11 }, "open": false } },
+            { "Fortification": { "kind": "Gate", "min": { "x": 14, "y": 9 }, "max": { "x": 17, "y": 10 } } },
+            { "Fortification": { "kind": "Tower", "min": { "x": 17, "y": 8 }, "max": { "x": 20, "y": 11 } } }
+        ],
+        "players": [
+            {
+                "name": "Player", "faction": "Rome", "team": 0, "colour": [1, 0.8, 0.8, 1],
+                "controller": "Human",
+                "deployment_zone": [{ "x": 0, "y": 0 }, { "x": 20, "y": 0 }, { "x": 20, "y": 8 }, { "x": 0, "y": 8 }],
+                "companies": [
+                    {
+                        "unit_type": "test", "count": 12, "pos": { "x": 5, "y": 4 },
+                        "formation": { "shape": "Line", "width": 6, "depth": 2 }
+                    }
+                ]
+            },
+            {
+                "name": "Enemy", "faction": "Gaul", "team": 1, "colour": [0.8, 0.8, 1, 1],
+                "controller": "Ai",
+                "companies": [
+                    {
+                        "unit_type": "test", "count": 9, "pos": { "x": 5, "y": 16 }, "facing": 180,
+                        "formation": { "shape": "Square", "width": 3, "depth": 1 }
+                    }
+                ]
+            }
+        ],
+        "victory_conditions": [
+            "Annihilation",
+            { "HoldGate": { "gate": 0, "team": 1, "time": 60 } }
+        ]
+    }"#;
+
+    #[test]
+    fn test_build_scenario() {
+        let scenario = Scenario::from_json(SCENARIO).unwrap();
+        let bf = scenario
+            .build(vec![test_unit_type()], 0, |_, _, _| unreachable!())
+            .unwrap();
+        assert_eq!(bf.seed(), 3);
+        assert_eq!(bf.map_size.w, 20);
+        assert_eq!(bf.get_player_iter().count(), 2);
+        let counts: Vec<usize> = {
+            let mut companies: Vec<_> = bf.get_company_iter().collect();
+            companies.sort_by_key(|&(id, _)| *id);
+            companies.iter().map(|(_, c)| c.members.len()).collect()
+        };
+        assert_eq!(counts, vec![12, 9]);
+        let gate = bf.nav().get_connection(&ConnectionId { id: 0 }).unwrap();
+        assert!(!gate.open);
+        assert_eq!(bf.get_structure_iter().count(), 2);
+        let passage = ConnectionId { id: 1 };
+        assert_eq!(bf.gate_of(passage).unwrap().kind, StructureKind::Gate);
+        assert!(!bf.nav().get_connection(&passage).unwrap().open);
+        assert!(!bf.nav().is_walkable(Position::new(18.5, 9.5)));
+        let zone = bf.deployment_zone(PlayerId { id: 0 }).unwrap();
+        assert!(zone.contains(Position::new(5.0, 4.0)));
+        assert!(bf.deployment_zone(PlayerId { id: 1 }).is_none());
+    }
+
+    #[test]
+    fn test_bundled_scenarios_parse() {
+        let skirmish = include_str!("../../assets/scenarios/skirmish.json");
+        assert_eq!(Scenario::from_json(skirmish).unwrap().players.len(), 2);
+        let gate_assault = include_str!("../../assets/scenarios/gate_assault.json");
+        let bf = Scenario::from_json(&gate_assault.replace("legionair", "test"))
+            .unwrap()
+            .build(vec![test_unit_type()], 0, |_, _, _| unreachable!())
+            .unwrap();
+        assert_eq!(bf.commanders().len(), 1);
+    }
+
+    #[test]
+    fn test_bad_scenarios_are_reported() {
+        let build = |json: &str| {
+            Scenario::from_json(json)
+                .and_then(|scenario| {
+                    scenario.build(vec![test_unit_type()], 0, |_, _, _| unreachable!())
+                })
+                .err()
+        };
+        match build("{ \"name\": \"Test\" }") {
+            Some(ScenarioError::Parse(reason)) => assert!(reason.contains("map_size")),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            build(&SCENARIO.replace(
+                "\"unit_type\": \"test\", \"count\": 9",
+                "\"unit_type\": \"elephant\", \"count\": 9"
+            )),
+            Some(ScenarioError::UnknownUnitType {
+                player: "Enemy".to_string(),
+                unit_type: "elephant".to_string(),
+            })
+        );
+        assert_eq!(
+            build(&SCENARIO.replace("\"x\": 5, \"y\": 16", "\"x\": 5, \"y\": 26")),
+            Some(ScenarioError::OutsideMap {
+                player: "Enemy".to_string(),
+                company: 0,
+            })
+        );
+        assert_eq!(
+            build(&SCENARIO.replace("\"gate\": 0", "\"gate\": 2")),
+            Some(ScenarioError::UnknownGate(2))
+        );
+        assert_eq!(
+            build(&SCENARIO.replace("\"y\": 8 }, { \"x\": 0", "\"y\": 28 }, { \"x\": 0")),
+            Some(ScenarioError::BadDeploymentZone("Player".to_string()))
+        );
+        assert_eq!(
+            build(&SCENARIO.replace("\"x\": 20, \"y\": 11", "\"x\": 21, \"y\": 11")),
+            Some(ScenarioError::StructureOutsideMap(3))
+        );
+        for (from, to) in &[
+            ("\"open\": false", "\"opne\": false"),
+            ("\"width\": 6", "\"widht\": 6"),
+            ("\"kind\": \"Tower\"", "\"kind\": \"Tower\", \"height\": 3"),
+        ] {
+            match build(&SCENARIO.replace(from, to)) {
+                Some(ScenarioError::Parse(reason)) => assert!(reason.contains("unknown field")),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Fighting out a battle without anyone watching. Every player is handed to the computer, the
+//! armies are deployed automatically and the battle is stepped as fast as it goes.
+
+use core::battlefield::Battlefield;
+use core::deployment::Deployment;
+use core::player::{Controller, PlayerId, TeamId};
+use core::victory::VictoryCondition;
+use std::collections::HashMap;
+
+/// Battles still going on after this many seconds are called off, in s
+pub const DEFAULT_MAX_DURATION: f64 = 3600.0;
+
+/// What came of a simulated battle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BattleReport {
+    pub seed: u64,
+    /// `None` for a draw, or when the battle was called off
+    pub winner: Option<TeamId>,
+    /// The condition that ended the battle, `None` when it was called off
+    pub condition: Option<VictoryCondition>,
+    /// Soldiers lost by every team that lost any
+    pub casualties: HashMap<TeamId, u32>,
+    /// In seconds
+    pub duration: f64,
+}
+
+/// Lets the computer fight out `battlefield`, which has to be at its first step. Gives up after
+/// `max_duration` seconds.
+pub fn run(mut battlefield: Battlefield, max_duration: f64) -> BattleReport {
+    let mut player_ids: Vec<PlayerId> = battlefield.get_player_iter().map(|(&id, _)| id).collect();
+    player_ids.sort();
+    for player_id in player_ids {
+        battlefield.set_controller(player_id, Controller::Ai);
+    }
+    let mut battlefield = Deployment::new(battlefield).into_battlefield();
+    while !battlefield.is_over() && battlefield.duration() < max_duration {
+        battlefield.step();
+    }
+    match battlefield.outcome() {
+        Some(outcome) => BattleReport {
+            seed: battlefield.seed(),
+            winner: outcome.winner,
+            condition: Some(outcome.condition),
+            casualties: outcome.casualties.clone(),
+            duration: outcome.duration,
+        },
+        None => BattleReport {
+            seed: battlefield.seed(),
+            winner: None,
+            condition: None,
+            casualties: battlefield.casualties().clone(),
+            duration: battlefield.duration(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use cgmath::Rad;
+    use core::battlefield::Battlefield;
+    use core::company::{Formation, FormationShape};
+    use core::player::{Controller, PlayerId, TeamId};
+    use core::position::Position;
+    use core::terrain::Terrain;
+    use core::unit::{test_unit_type, UnitTypeId};
+    use core::victory::VictoryCondition;
+    use std::f32::consts::PI;
+    use types::Size2;
+
+    fn new_battlefield(seed: u64, time_limit: f64) -> Battlefield {
+        let mut bf = Battlefield::new(vec![test_unit_type()], seed);
+        bf.set_terrain(Terrain::flat(Size2 { w: 20, h: 30 }));
+        bf.add_player("A", "Rome", TeamId { id: 0 }, [1.0; 4], Controller::Human);
+        bf.add_player("B", "Gaul", TeamId { id: 1 }, [1.0; 4], Controller::Human);
+        let formation = Formation::new(FormationShape::Line, 4, 2);
+        for (id, y, rot) in [(0, 5.0, 0.0), (1, 25.0, PI)].iter() {
+            bf.add_company(
+                PlayerId { id: *id },
+                UnitTypeId { id: 0 },
+                8,
+                Position::new(10.0, *y),
+                Rad(*rot),
+                formation,
+            );
+        }
+        bf.add_victory_condition(VictoryCondition::Annihilation);
+        bf.add_victory_condition(VictoryCondition::TimeLimit {
+            time: time_limit,
+            winner: None,
+        });
+        bf
+    }
+
+    #[test]
+    fn test_run_to_the_end() {
+        let report = run(new_battlefield(4, 300.0), 600.0);
+        assert!(report.condition.is_some());
+        assert!(report.duration <= 300.0);
+        assert!(report.casualties.values().sum::<u32>() > 0);
+        // The same seed fights the same battle
+        let again = run(new_battlefield(4, 300.0), 600.0);
+        assert_eq!(again.winner, report.winner);
+        assert_eq!(again.casualties, report.casualties);
+        assert_eq!(again.duration, report.duration);
+    }
+
+    #[test]
+    fn test_run_gives_up() {
+        let report = run(new_battlefield(4, 300.0), 2.0);
+        assert!(report.condition.is_none());
+        assert!(report.duration >= 2.0 && report.duration < 2.1);
+    }
+}
@@ -0,0 +1,244 @@
+//! A uniform grid over the battlefield, so "who is near here" only has to look at the soldiers
+//! in the cells around a point instead of at everyone.
+//!
+//! The grid only knows where soldiers stand on the map, not on which level. Queries that care
+//! about levels or allegiance filter the results themselves.
+
+use core::position::Position;
+use core::unit::IndivId;
+use std::collections::HashMap;
+
+/// Side of a grid cell, in m. A bit more than the melee range, so melee queries only need the
+/// cells right around a soldier.
+pub const CELL_SIZE: f64 = 4.0;
+
+type Cell = (i32, i32);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpatialGrid {
+    cell_size: f64,
+    cells: HashMap<Cell, Vec<IndivId>>,
+    positions: HashMap<IndivId, Position>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f64) -> SpatialGrid {
+        SpatialGrid {
+            cell_size,
+            cells: HashMap::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    fn cell_of(&self, pos: Position) -> Cell {
+        (
+            (pos.x / self.cell_size).floor() as i32,
+            (pos.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn insert(&mut self, id: IndivId, pos: Position) {
+        assert!(self.positions.insert(id, pos).is_none());
+        let cell = self.cell_of(pos);
+        self.cells.entry(cell).or_default().push(id);
+    }
+
+    pub fn remove(&mut self, id: IndivId) {
+        let pos = self.positions.remove(&id).expect("Indiv not in grid");
+        let cell = self.cell_of(pos);
+        self.remove_from_cell(cell, id);
+    }
+
+    fn remove_from_cell(&mut self, cell: Cell, id: IndivId) {
+        let ids = self.cells.get_mut(&cell).expect("Grid cell missing");
+        ids.retain(|&other| other != id);
+        if ids.is_empty() {
+            self.cells.remove(&cell);
+        }
+    }
+
+    /// Moves `id` to `pos`. Only touches the cells if it moved into another one.
+    pub fn update(&mut self, id: IndivId, pos: Position) {
+        let old = self.positions.insert(id, pos).expect("Indiv not in grid");
+        let old_cell = self.cell_of(old);
+        let new_cell = self.cell_of(pos);
+        if old_cell != new_cell {
+            self.remove_from_cell(old_cell, id);
+            self.cells.entry(new_cell).or_default().push(id);
+        }
+    }
+
+    /// Everyone within `radius` of `pos`, sorted by id.
+    pub fn query_radius(&self, pos: Position, radius: f64) -> Vec<IndivId> {
+        let min = Position::new(pos.x - radius, pos.y - radius);
+        let max = Position::new(pos.x + radius, pos.y + radius);
+        let mut ids = self.query_rect(min, max);
+        ids.retain(|id| self.positions[id].dist(&pos) <= radius);
+        ids
+    }
+
+    /// Everyone inside the rectangle from `min` to `max`, edges included, sorted by id.
+    pub fn query_rect(&self, min: Position, max: Position) -> Vec<IndivId> {
+        let (min_i, min_j) = self.cell_of(min);
+        let (max_i, max_j) = self.cell_of(max);
+        let mut ids = Vec::new();
+        for j in min_j..(max_j + 1) {
+            for i in min_i..(max_i + 1) {
+                if let Some(cell) = self.cells.get(&(i, j)) {
+                    for id in cell {
+                        let p = self.positions[id];
+                        if p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y {
+                            ids.push(*id);
+                        }
+                    }
+                }
+            }
+        }
+        ids.sort();
+        ids
+    }
+
+    /// The closest one within `range` of `pos` that passes `filter`, like `query_radius` those
+    /// at exactly `range` included. Ties go to the lowest id.
+    ///
+    /// Searches the cells in rings around `pos`, and stops as soon as the next ring can't hold
+    /// anything closer than what was found. When the range covers more cells than there are
+    /// occupied ones, it just looks at all occupied cells instead.
+    pub fn nearest<F: Fn(IndivId) -> bool>(
+        &self,
+        pos: Position,
+        range: f64,
+        filter: F,
+    ) -> Option<IndivId> {
+        let mut nearest = Nearest {
+            id: None,
+            dist: range,
+        };
+        let (ci, cj) = self.cell_of(pos);
+        let max_ring = (range / self.cell_size).ceil() as i32 + 1;
+        let cells_in_range = ((2 * max_ring + 1) * (2 * max_ring + 1)) as usize;
+        if cells_in_range > self.cells.len() {
+            for cell in self.cells.values() {
+                self.check_cell(cell, pos, &filter, &mut nearest);
+            }
+            return nearest.id;
+        }
+        for ring in 0..(max_ring + 1) {
+            // Everything in this ring is at least this far away
+            let ring_dist = (ring - 1).max(0) as f64 * self.cell_size;
+            if ring_dist > nearest.dist {
+                break;
+            }
+            for j in (cj - ring)..(cj + ring + 1) {
+                for i in (ci - ring)..(ci + ring + 1) {
+                    let on_ring = (i - ci).abs() == ring || (j - cj).abs() == ring;
+                    if !on_ring {
+                        continue;
+                    }
+                    if let Some(cell) = self.cells.get(&(i, j)) {
+                        self.check_cell(cell, pos, &filter, &mut nearest);
+                    }
+                }
+            }
+        }
+        nearest.id
+    }
+
+    fn check_cell<F: Fn(IndivId) -> bool>(
+        &self,
+        cell: &[IndivId],
+        pos: Position,
+        filter: &F,
+        nearest: &mut Nearest,
+    ) {
+        for &id in cell {
+            let dist = self.positions[&id].dist(&pos);
+            let closer =
+                dist < nearest.dist || (dist == nearest.dist && nearest.id.is_none_or(|n| id < n));
+            if closer && filter(id) {
+                nearest.id = Some(id);
+                nearest.dist = dist;
+            }
+        }
+    }
+}
+
+struct Nearest {
+    id: Option<IndivId>,
+    dist: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpatialGrid, CELL_SIZE};
+    use core::position::Position;
+    use core::unit::IndivId;
+    use rand::prng::XorShiftRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_grid(n: u32) -> (SpatialGrid, Vec<Position>) {
+        let mut rng = XorShiftRng::from_seed([5; 16]);
+        let mut grid = SpatialGrid::new(CELL_SIZE);
+        let mut positions = Vec::new();
+        for id in 0..n {
+            let pos = Position::new(rng.gen_range(0.0, 500.0), rng.gen_range(0.0, 500.0));
+            grid.insert(IndivId { id }, pos);
+            positions.push(pos);
+        }
+        (grid, positions)
+    }
+
+    #[test]
+    fn test_queries_match_brute_force() {
+        let (grid, positions) = random_grid(10_000);
+        let center = Position::new(250.0, 250.0);
+        let expected: Vec<IndivId> = (0..10_000)
+            .filter(|&id| positions[id as usize].dist(&center) <= 20.0)
+            .map(|id| IndivId { id })
+            .collect();
+        assert_eq!(grid.query_radius(center, 20.0), expected);
+        let nearest = (0..10_000u32)
+            .filter(|id| id % 2 == 0)
+            .min_by(|&a, &b| {
+                let da = positions[a as usize].dist(&center);
+                let db = positions[b as usize].dist(&center);
+                da.partial_cmp(&db).unwrap()
+            })
+            .map(|id| IndivId { id });
+        assert_eq!(grid.nearest(center, 50.0, |id| id.id % 2 == 0), nearest);
+        assert_eq!(grid.nearest(center, 0.0001, |_| true), None);
+    }
+
+    #[test]
+    fn test_queries_include_range() {
+        let mut grid = SpatialGrid::new(CELL_SIZE);
+        let id = IndivId { id: 0 };
+        grid.insert(id, Position::new(13.0, 14.0));
+        let center = Position::new(10.0, 10.0);
+        assert_eq!(grid.query_radius(center, 5.0), vec![id]);
+        assert_eq!(grid.nearest(center, 5.0, |_| true), Some(id));
+        assert_eq!(grid.nearest(center, 4.99, |_| true), None);
+    }
+
+    #[test]
+    fn test_update_and_remove() {
+        let mut grid = SpatialGrid::new(CELL_SIZE);
+        let id = IndivId { id: 0 };
+        grid.insert(id, Position::new(1.0, 1.0));
+        grid.update(id, Position::new(30.0, 30.0));
+        let min = Position::new(0.0, 0.0);
+        assert!(grid.query_rect(min, Position::new(10.0, 10.0)).is_empty());
+        assert_eq!(grid.query_rect(min, Position::new(40.0, 40.0)), vec![id]);
+        grid.remove(id);
+        assert!(grid.is_empty());
+        assert!(grid.query_rect(min, Position::new(40.0, 40.0)).is_empty());
+    }
+}
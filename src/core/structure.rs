@@ -0,0 +1,272 @@
+//! Walls, gates and towers that can be broken down. A structure blocks the ground it stands on,
+//! for walking and for sight, until it is breached.
+//!
+//! Companies ordered to attack a structure hit it in melee or shoot at it, and any projectile
+//! that comes down on one hits it. Like a blow against a soldier, a hit only does damage if it
+//! gets through the armor (see `combat::wound_chance`), but a structure has a different armor
+//! against each `DamageType`: axes and swords do little against stone, arrows even less. Every
+//! hit that gets through takes one hp.
+//!
+//! A structure is `Damaged` once it has lost half its hp and `Breached` once it has none left.
+//! A breach clears the way through it, and a breached gate opens its passage.
+
+use cgmath::Vector2;
+use core::combat::wound_chance;
+use core::level::{ConnectionId, Level};
+use core::movement::{heading_of, MoveOrder};
+use core::position::Position;
+use rand::Rng;
+
+/// Soldiers attacking a structure stand this far (in m) from it. Just over a nav cell, so they
+/// stay clear of the cells it blocks.
+pub const STRIKE_DIST: f64 = 1.2;
+
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StructureId {
+    pub id: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum StructureKind {
+    /// A stretch of wall, with a walkway on top if the scenario adds one
+    WallSegment,
+    /// A wooden gate with a passage through it, closed until it is breached
+    Gate,
+    Tower,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum StructureState {
+    Intact,
+    /// Lost at least half its hp
+    Damaged,
+    /// No hp left, there is a way through
+    Breached,
+}
+
+/// What a hit is done with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum DamageType {
+    /// Melee blows
+    Blow,
+    /// Arrows and javelins
+    Pierce,
+    /// Sling stones
+    Crush,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StructureStats {
+    pub hp: u32,
+    pub blow_armor: u8,
+    pub pierce_armor: u8,
+    pub crush_armor: u8,
+    /// Height of the top, in m
+    pub height: f64,
+}
+
+impl StructureStats {
+    pub fn armor(&self, damage_type: DamageType) -> u8 {
+        match damage_type {
+            DamageType::Blow => self.blow_armor,
+            DamageType::Pierce => self.pierce_armor,
+            DamageType::Crush => self.crush_armor,
+        }
+    }
+}
+
+impl StructureKind {
+    pub fn stats(&self) -> StructureStats {
+        match *self {
+            StructureKind::WallSegment => StructureStats {
+                hp: 300,
+                blow_armor: 40,
+                pierce_armor: 200,
+                crush_armor: 60,
+                height: Level::Walkway.height(),
+            },
+            StructureKind::Gate => StructureStats {
+                hp: 80,
+                blow_armor: 16,
+                pierce_armor: 60,
+                crush_armor: 40,
+                height: Level::Walkway.height(),
+            },
+            StructureKind::Tower => StructureStats {
+                hp: 400,
+                blow_armor: 60,
+                pierce_armor: 250,
+                crush_armor: 80,
+                height: Level::TowerTop.height(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Structure {
+    pub id: StructureId,
+    pub kind: StructureKind,
+    /// The corners of the ground it covers
+    pub min: Position,
+    pub max: Position,
+    pub hp: u32,
+    /// The passage through a gate
+    pub passage: Option<ConnectionId>,
+}
+
+impl Structure {
+    pub fn new(id: StructureId, kind: StructureKind, min: Position, max: Position) -> Structure {
+        Structure {
+            id,
+            kind,
+            min,
+            max,
+            hp: kind.stats().hp,
+            passage: None,
+        }
+    }
+
+    pub fn state(&self) -> StructureState {
+        if self.hp == 0 {
+            StructureState::Breached
+        } else if self.hp * 2 <= self.kind.stats().hp {
+            StructureState::Damaged
+        } else {
+            StructureState::Intact
+        }
+    }
+
+    pub fn is_breached(&self) -> bool {
+        self.state() == StructureState::Breached
+    }
+
+    pub fn centre(&self) -> Position {
+        Position::new(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+        )
+    }
+
+    pub fn contains(&self, pos: Position) -> bool {
+        pos.x >= self.min.x && pos.x <= self.max.x && pos.y >= self.min.y && pos.y <= self.max.y
+    }
+
+    /// The point of the structure closest to `pos`, `pos` itself if it is inside.
+    pub fn closest_point(&self, pos: Position) -> Position {
+        Position::on_level(
+            pos.x.max(self.min.x).min(self.max.x),
+            pos.y.max(self.min.y).min(self.max.y),
+            pos.level,
+        )
+    }
+
+    /// Distance from `pos` to the structure, 0 inside it.
+    pub fn dist(&self, pos: Position) -> f64 {
+        pos.dist(&self.closest_point(pos))
+    }
+
+    /// Where a company at `from` stands to attack the structure: facing the side closest to
+    /// it, `dist` m away, and no closer than `half_width` m to the corners of that side, so
+    /// as many soldiers as possible reach it.
+    pub fn approach(&self, from: Position, half_width: f64, dist: f64) -> MoveOrder {
+        let centre = self.centre();
+        let clamp_to_side = |p: f64, min: f64, max: f64| {
+            let margin = half_width.min((max - min) / 2.0);
+            p.max(min + margin).min(max - margin)
+        };
+        let out_x = (self.min.x - from.x).max(from.x - self.max.x);
+        let out_y = (self.min.y - from.y).max(from.y - self.max.y);
+        let (spot, normal) = if out_y >= out_x {
+            let x = clamp_to_side(from.x, self.min.x, self.max.x);
+            if from.y < centre.y {
+                (Position::new(x, self.min.y), Vector2::new(0.0, -1.0))
+            } else {
+                (Position::new(x, self.max.y), Vector2::new(0.0, 1.0))
+            }
+        } else {
+            let y = clamp_to_side(from.y, self.min.y, self.max.y);
+            if from.x < centre.x {
+                (Position::new(self.min.x, y), Vector2::new(-1.0, 0.0))
+            } else {
+                (Position::new(self.max.x, y), Vector2::new(1.0, 0.0))
+            }
+        };
+        MoveOrder {
+            dest: Position::new(spot.x + normal.x * dist, spot.y + normal.y * dist),
+            facing: heading_of(-normal),
+        }
+    }
+
+    /// Rolls a hit of `damage` against the armor for `damage_type`. Returns whether it took a
+    /// hp. A breached structure can't take any more.
+    pub fn hit<R: Rng>(&mut self, rng: &mut R, damage: u8, damage_type: DamageType) -> bool {
+        if self.hp == 0 {
+            return false;
+        }
+        let armor = self.kind.stats().armor(damage_type);
+        if !rng.gen_bool(wound_chance(damage, armor)) {
+            return false;
+        }
+        self.hp -= 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DamageType, Structure, StructureId, StructureKind, StructureState};
+    use core::misc::seeded_rng;
+    use core::position::Position;
+
+    fn new_gate() -> Structure {
+        Structure::new(
+            StructureId { id: 0 },
+            StructureKind::Gate,
+            Position::new(18.0, 29.0),
+            Position::new(22.0, 31.0),
+        )
+    }
+
+    #[test]
+    fn test_gate_breaks_under_blows() {
+        let mut gate = new_gate();
+        let mut rng = seeded_rng(1);
+        let mut blows = 0;
+        while gate.state() == StructureState::Intact {
+            gate.hit(&mut rng, 8, DamageType::Blow);
+            blows += 1;
+        }
+        assert_eq!(gate.hp, 40);
+        while !gate.is_breached() {
+            gate.hit(&mut rng, 8, DamageType::Blow);
+            blows += 1;
+        }
+        // A blow gets through a third of the time
+        assert!(blows > 160 && blows < 320, "{}", blows);
+        assert!(!gate.hit(&mut rng, 8, DamageType::Blow));
+        // Arrows do far less than blows of the same damage
+        let (mut blow_hits, mut arrow_hits) = (0, 0);
+        for _ in 0..1000 {
+            let mut gate = new_gate();
+            blow_hits += gate.hit(&mut rng, 8, DamageType::Blow) as u32;
+            arrow_hits += gate.hit(&mut rng, 8, DamageType::Pierce) as u32;
+        }
+        assert!(blow_hits > 2 * arrow_hits);
+    }
+
+    #[test]
+    fn test_approach_faces_the_nearest_side() {
+        let gate = new_gate();
+        // From the south, in line with the gate
+        let order = gate.approach(Position::new(12.0, 6.0), 2.0, 1.5);
+        assert!((order.dest.x - 20.0).abs() < 1e-9 && (order.dest.y - 27.5).abs() < 1e-9);
+        assert!(order.facing.0.abs() < 1e-6);
+        // From the east
+        let order = gate.approach(Position::new(40.0, 30.0), 2.0, 1.0);
+        assert!((order.dest.x - 23.0).abs() < 1e-9 && (order.dest.y - 30.0).abs() < 1e-9);
+        assert!(gate.dist(order.dest) < 1.0 + 1e-9);
+        assert!(gate.contains(gate.closest_point(Position::new(0.0, 0.0))));
+    }
+}
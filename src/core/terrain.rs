@@ -0,0 +1,203 @@
+//! The shape of the ground. Heights are sampled on a regular grid and interpolated in between.
+
+use cgmath::{Rad, Vector2};
+use core::level::Level;
+use core::misc::clamp;
+use core::movement::forward;
+use core::position::Position;
+use rand::Rng;
+use std::f64::consts::PI;
+use types::Size2;
+
+/// Speed lost per unit of uphill slope: a slope of 0.2 (1 m up for every 5 m) costs 30%.
+pub const UPHILL_SPEED_PENALTY: f64 = 1.5;
+/// Even the steepest slope can still be climbed at this fraction of the normal speed.
+pub const MIN_SLOPE_SPEED_FACTOR: f64 = 0.3;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Terrain {
+    /// Number of height samples along x and y
+    samples: Size2,
+    /// Distance between two neighbouring samples, in m
+    spacing: f64,
+    /// Row by row, starting at y = 0
+    heights: Vec<f64>,
+}
+
+impl Terrain {
+    /// Level ground covering `size` m.
+    pub fn flat(size: Size2) -> Terrain {
+        let samples = Size2 {
+            w: size.w + 1,
+            h: size.h + 1,
+        };
+        Terrain::from_heights(samples, 1.0, vec![0.0; (samples.w * samples.h) as usize])
+    }
+
+    pub fn from_heights(samples: Size2, spacing: f64, heights: Vec<f64>) -> Terrain {
+        assert!(samples.w >= 2 && samples.h >= 2);
+        assert_eq!(heights.len(), (samples.w * samples.h) as usize);
+        Terrain {
+            samples,
+            spacing,
+            heights,
+        }
+    }
+
+    /// Rolling hills covering `size` m, at most `max_height` m high.
+    pub fn generate<R: Rng>(rng: &mut R, size: Size2, max_height: f64) -> Terrain {
+        // A few long waves in random directions, each half as high as the one before
+        let waves: Vec<(f64, f64, f64, f64)> = (0..3)
+            .map(|i| {
+                let angle = rng.gen_range(0.0, 2.0 * PI);
+                let length = rng.gen_range(40.0, 120.0) / (i + 1) as f64;
+                let phase = rng.gen_range(0.0, 2.0 * PI);
+                let amplitude = 0.5f64.powi(i + 1);
+                (angle, length, phase, amplitude)
+            })
+            .collect();
+        let total: f64 = waves.iter().map(|w| w.3).sum();
+        let samples = Size2 {
+            w: size.w + 1,
+            h: size.h + 1,
+        };
+        let mut heights = Vec::with_capacity((samples.w * samples.h) as usize);
+        for j in 0..samples.h {
+            for i in 0..samples.w {
+                let mut h = 0.0;
+                for &(angle, length, phase, amplitude) in &waves {
+                    let d = i as f64 * angle.cos() + j as f64 * angle.sin();
+                    h += amplitude * (0.5 + 0.5 * (2.0 * PI * d / length + phase).sin());
+                }
+                heights.push(max_height * h / total);
+            }
+        }
+        Terrain::from_heights(samples, 1.0, heights)
+    }
+
+    /// Size of the area covered, in m.
+    pub fn size(&self) -> Size2 {
+        Size2 {
+            w: ((self.samples.w - 1) as f64 * self.spacing).round() as i32,
+            h: ((self.samples.h - 1) as f64 * self.spacing).round() as i32,
+        }
+    }
+
+    pub fn samples(&self) -> Size2 {
+        self.samples
+    }
+
+    pub fn spacing(&self) -> f64 {
+        self.spacing
+    }
+
+    /// The height at sample `(i, j)`, clamped to the edge of the grid.
+    pub fn sample(&self, i: i32, j: i32) -> f64 {
+        let i = clamp(i, 0, self.samples.w - 1);
+        let j = clamp(j, 0, self.samples.h - 1);
+        self.heights[(j * self.samples.w + i) as usize]
+    }
+
+    /// Height of the ground at `(x, y)`, in m. Outside the map the edge continues flat.
+    pub fn height(&self, x: f64, y: f64) -> f64 {
+        let gx = clamp(x / self.spacing, 0.0, (self.samples.w - 1) as f64);
+        let gy = clamp(y / self.spacing, 0.0, (self.samples.h - 1) as f64);
+        let (i, j) = (gx.floor() as i32, gy.floor() as i32);
+        let (fx, fy) = (gx - i as f64, gy - j as f64);
+        let bottom = self.sample(i, j) * (1.0 - fx) + self.sample(i + 1, j) * fx;
+        let top = self.sample(i, j + 1) * (1.0 - fx) + self.sample(i + 1, j + 1) * fx;
+        bottom * (1.0 - fy) + top * fy
+    }
+
+    /// The direction in which the ground rises fastest, scaled by how steep it is there.
+    pub fn gradient(&self, x: f64, y: f64) -> Vector2<f64> {
+        let d = self.spacing / 2.0;
+        Vector2::new(
+            (self.height(x + d, y) - self.height(x - d, y)) / (2.0 * d),
+            (self.height(x, y + d) - self.height(x, y - d)) / (2.0 * d),
+        )
+    }
+
+    /// Rise over run at `(x, y)`, in the steepest direction.
+    pub fn slope(&self, x: f64, y: f64) -> f64 {
+        let g = self.gradient(x, y);
+        g.x.hypot(g.y)
+    }
+
+    /// Rise over run at `(x, y)` when going along the unit vector `dir`. Negative downhill.
+    pub fn slope_along(&self, x: f64, y: f64, dir: Vector2<f64>) -> f64 {
+        let g = self.gradient(x, y);
+        g.x * dir.x + g.y * dir.y
+    }
+
+    /// Fraction of its normal speed something at `pos` keeps when heading along `rot`.
+    /// Walls and towers are level, only the ground slopes.
+    pub fn speed_factor(&self, pos: Position, rot: Rad<f32>) -> f64 {
+        if pos.level == Level::Ground {
+            slope_speed_factor(self.slope_along(pos.x, pos.y, forward(rot)))
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Fraction of its normal speed a soldier keeps going along a slope of `slope_along`.
+/// Only going uphill slows down.
+pub fn slope_speed_factor(slope_along: f64) -> f64 {
+    clamp(
+        1.0 - UPHILL_SPEED_PENALTY * slope_along,
+        MIN_SLOPE_SPEED_FACTOR,
+        1.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{slope_speed_factor, Terrain};
+    use cgmath::Vector2;
+    use rand::prng::XorShiftRng;
+    use rand::SeedableRng;
+    use types::Size2;
+
+    const EPS: f64 = 1e-9;
+
+    /// A ramp rising 1 m for every 2 m along x.
+    fn ramp() -> Terrain {
+        let samples = Size2 { w: 3, h: 2 };
+        Terrain::from_heights(samples, 2.0, vec![0.0, 1.0, 2.0, 0.0, 1.0, 2.0])
+    }
+
+    #[test]
+    fn test_height_is_interpolated() {
+        let terrain = ramp();
+        assert_eq!(terrain.size().w, 4);
+        assert!((terrain.height(1.0, 1.0) - 0.5).abs() < EPS);
+        assert!((terrain.height(3.0, 0.0) - 1.5).abs() < EPS);
+        // Beyond the edge the ground stays at the height of the edge
+        assert!((terrain.height(10.0, -5.0) - 2.0).abs() < EPS);
+    }
+
+    #[test]
+    fn test_slope() {
+        let terrain = ramp();
+        assert!((terrain.slope(2.0, 1.0) - 0.5).abs() < EPS);
+        assert!((terrain.slope_along(2.0, 1.0, Vector2::new(-1.0, 0.0)) + 0.5).abs() < EPS);
+        assert!(terrain.slope_along(2.0, 1.0, Vector2::new(0.0, 1.0)).abs() < EPS);
+        assert!((slope_speed_factor(0.2) - 0.7).abs() < EPS);
+        assert!((slope_speed_factor(-0.5) - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn test_generated_terrain_stays_in_bounds() {
+        let mut rng = XorShiftRng::from_seed([3; 16]);
+        let terrain = Terrain::generate(&mut rng, Size2 { w: 50, h: 30 }, 8.0);
+        assert_eq!(terrain.size().w, 50);
+        assert_eq!(terrain.size().h, 30);
+        for j in 0..=30 {
+            for i in 0..=50 {
+                let h = terrain.sample(i, j);
+                assert!((0.0..=8.0).contains(&h));
+            }
+        }
+    }
+}
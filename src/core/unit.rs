@@ -1,28 +1,51 @@
-use cgmath::Rad;
+use cgmath::{Rad, Vector2};
+use core::company::CompId;
+use core::movement::{steer, MoveOrder, Steering};
+use core::pathfinding::Path;
+use core::player::PlayerId;
 use core::position::Position;
+use core::visibility::DEFAULT_SIGHT_RANGE;
+use core::weapon::WeaponType;
+use std::f32::consts::PI;
 
-#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct IndivId {
     pub id: u32,
 }
 
-#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct UnitTypeId {
     pub id: u16,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Indiv {
     pub id: IndivId,
     pub pos: Position,
     pub rot: Rad<f32>,
-    pub player_id: u8, // Replace by PlayerID?
+    pub vel: Vector2<f64>,
+    pub comp_id: CompId,
+    /// An order given to this indiv alone. Overrides its slot in the company.
+    pub order: Option<MoveOrder>,
+    /// Where this indiv is heading: its own order or else its slot in the company formation
+    pub target: Option<MoveOrder>,
+    /// The way to `target`, when it can't be reached in a straight line
+    pub path: Path,
+    /// Where `path` leads to
+    pub path_goal: Option<Position>,
+    pub player_id: PlayerId,
     pub type_id: UnitTypeId,
     pub hp: i8,
     pub xp: i8,
+    /// Seconds until this indiv can attack or shoot again
+    pub attack_cooldown: f64,
+    /// Shots left for its ranged weapon
+    pub ammo: u8,
+    /// 1 fresh, 0 spent, see `fatigue`
+    pub stamina: f64,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct UnitType {
     pub name: String,
     pub count: u8,
@@ -32,8 +55,74 @@ pub struct UnitType {
     pub armor: u8,
     pub shield: u8,
     pub attack_skill: i32,
-    // pub weapon_type: WeaponType,     // make enum weaopn type
+    pub weapon_type: WeaponType,
+    /// Base morale of a company of this type, see `morale::MORALE_SCALE`
+    pub morale: u8,
     pub speed: u8,
     pub cost_recruit: f32,
     pub cost_upkeep: f32,
+    /// How far its soldiers see, in m
+    #[serde(default = "default_sight_range")]
+    pub sight_range: f64,
+}
+
+fn default_sight_range() -> f64 {
+    DEFAULT_SIGHT_RANGE
+}
+
+/// Converts `UnitType::speed` to m/s.
+const SPEED_SCALE: f64 = 0.25;
+
+impl UnitType {
+    /// Top speed in m/s.
+    pub fn max_speed(&self) -> f64 {
+        self.speed as f64 * SPEED_SCALE
+    }
+
+    pub fn steering(&self) -> Steering {
+        Steering {
+            max_speed: self.max_speed(),
+            accel: 2.0,
+            turn_rate: PI,
+            sidestep_dist: 1.5,
+            sidestep_factor: 0.5,
+        }
+    }
+}
+
+impl Indiv {
+    /// Advances this indiv by one simulation step of `dt` seconds.
+    pub fn update(&mut self, steering: &Steering, dt: f64) {
+        self.attack_cooldown = (self.attack_cooldown - dt).max(0.0);
+        if let Some(target) = self.target {
+            steer(
+                &mut self.pos,
+                &mut self.rot,
+                &mut self.vel,
+                &target,
+                steering,
+                dt,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+pub fn test_unit_type() -> UnitType {
+    UnitType {
+        name: "test".to_string(),
+        count: 80,
+        size: 2,
+        hp: 3,
+        defence_skill: 6,
+        armor: 8,
+        shield: 8,
+        attack_skill: 6,
+        weapon_type: WeaponType::Thrown,
+        morale: 8,
+        speed: 10,
+        cost_recruit: 300.0,
+        cost_upkeep: 50.0,
+        sight_range: DEFAULT_SIGHT_RANGE,
+    }
 }
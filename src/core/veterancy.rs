@@ -0,0 +1,101 @@
+//! Experience. Soldiers gain `XP_PER_KILL` xp for every enemy they kill and `XP_PER_BATTLE` for
+//! every battle they live through, up to `MAX_XP`.
+//!
+//! The xp of a soldier gives it a `Rank`, which adds to its attack and defence skill. The rank of
+//! a company follows from the average xp of its soldiers and adds to its base morale. The bonuses
+//! stop growing at `Rank::Elite`, so veterans are better but not invincible.
+
+/// Xp for killing an enemy
+pub const XP_PER_KILL: i8 = 3;
+/// Xp for surviving a battle
+pub const XP_PER_BATTLE: i8 = 5;
+pub const MAX_XP: i8 = 100;
+
+#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Rank {
+    Recruit,
+    Regular,
+    Veteran,
+    Elite,
+}
+
+impl Rank {
+    pub fn of(xp: i8) -> Rank {
+        match xp {
+            xp if xp >= 30 => Rank::Elite,
+            xp if xp >= 15 => Rank::Veteran,
+            xp if xp >= 5 => Rank::Regular,
+            _ => Rank::Recruit,
+        }
+    }
+
+    pub fn attack_bonus(self) -> i32 {
+        match self {
+            Rank::Recruit => 0,
+            Rank::Regular | Rank::Veteran => 1,
+            Rank::Elite => 2,
+        }
+    }
+
+    pub fn defence_bonus(self) -> i32 {
+        match self {
+            Rank::Recruit | Rank::Regular => 0,
+            Rank::Veteran | Rank::Elite => 1,
+        }
+    }
+
+    /// Added to the base morale of a company of this rank, see `morale::MORALE_SCALE`
+    pub fn morale_bonus(self) -> f64 {
+        match self {
+            Rank::Recruit => 0.0,
+            Rank::Regular => 5.0,
+            Rank::Veteran => 10.0,
+            Rank::Elite => 15.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rank::Recruit => "recruit",
+            Rank::Regular => "regular",
+            Rank::Veteran => "veteran",
+            Rank::Elite => "elite",
+        }
+    }
+}
+
+/// `xp` after gaining `gained`.
+pub fn add_xp(xp: i8, gained: i8) -> i8 {
+    xp.saturating_add(gained).min(MAX_XP)
+}
+
+/// A company that survived a battle, to take its experience into the next one. See
+/// `Battlefield::enlist_veterans`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VeteranCompany {
+    /// Name of the player it fought for
+    pub player: String,
+    pub unit_type: String,
+    /// Average xp of its soldiers
+    pub xp: i8,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_xp, Rank, MAX_XP};
+
+    #[test]
+    fn test_ranks_and_bonuses_are_bounded() {
+        assert_eq!(Rank::of(0), Rank::Recruit);
+        assert_eq!(Rank::of(5), Rank::Regular);
+        assert_eq!(Rank::of(MAX_XP), Rank::Elite);
+        assert_eq!(add_xp(MAX_XP - 1, 10), MAX_XP);
+        assert_eq!(add_xp(120, 120), MAX_XP);
+        let mut rank = Rank::Recruit;
+        for xp in 0..(MAX_XP + 1) {
+            assert!(Rank::of(xp) >= rank);
+            rank = Rank::of(xp);
+            assert!(rank.attack_bonus() <= 2 && rank.defence_bonus() <= 1);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! How a battle is won. Every battle has its own list of conditions; the first one that is met
+//! ends the battle.
+
+use core::level::ConnectionId;
+use core::player::TeamId;
+use core::veterancy::VeteranCompany;
+use std::collections::HashMap;
+
+/// Soldiers closer than this (in m) to either end of a gate are holding it.
+pub const HOLD_RADIUS: f64 = 5.0;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum VictoryCondition {
+    /// The last team with soldiers on the field wins
+    Annihilation,
+    /// The last team with a company that isn't running away wins
+    AllEnemiesRouted,
+    /// `team` wins once its soldiers held `gate` for `time` seconds in a row. The gate is held
+    /// while they stand at it and no enemy does.
+    HoldGate {
+        gate: ConnectionId,
+        team: TeamId,
+        time: f64,
+    },
+    /// The battle ends after `time` seconds. `winner` wins, usually the defender, or nobody if
+    /// it is `None`.
+    TimeLimit { time: f64, winner: Option<TeamId> },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BattleOutcome {
+    /// `None` for a draw
+    pub winner: Option<TeamId>,
+    /// The condition that ended the battle
+    pub condition: VictoryCondition,
+    /// Soldiers lost by every team that lost any
+    pub casualties: HashMap<TeamId, u32>,
+    /// In seconds
+    pub duration: f64,
+    /// The companies that made it through, with their experience
+    pub veterans: Vec<VeteranCompany>,
+}
+
+impl BattleOutcome {
+    pub fn casualties_of(&self, team: TeamId) -> u32 {
+        self.casualties.get(&team).cloned().unwrap_or(0)
+    }
+}
+
+/// The only team in `teams`, if there is at most one left: `Some(None)` if there are none.
+pub fn last_team_standing<I: Iterator<Item = TeamId>>(teams: I) -> Option<Option<TeamId>> {
+    let mut last = None;
+    for team in teams {
+        match last {
+            Some(other) if other != team => return None,
+            _ => last = Some(team),
+        }
+    }
+    Some(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::last_team_standing;
+    use core::player::TeamId;
+
+    #[test]
+    fn test_last_team_standing() {
+        let (a, b) = (TeamId { id: 0 }, TeamId { id: 1 });
+        assert_eq!(last_team_standing(vec![a, a].into_iter()), Some(Some(a)));
+        assert_eq!(last_team_standing(vec![a, b, a].into_iter()), None);
+        assert_eq!(last_team_standing(Vec::new().into_iter()), Some(None));
+    }
+}
@@ -0,0 +1,161 @@
+//! What each team can see. A soldier sees enemies within the sight range of its unit type,
+//! unless hills, walls or buildings are in the way. Allies share what they see.
+//!
+//! Every `SIGHT_INTERVAL` steps each team looks around. Enemies it can't see any more are
+//! remembered where they were last seen, until someone looks at that spot again.
+
+use cgmath::Rad;
+use core::battlefield::TICKS_PER_SECOND;
+use core::company::CompId;
+use core::pathfinding::NavGrid;
+use core::player::PlayerId;
+use core::position::Position;
+use core::terrain::Terrain;
+use core::unit::{Indiv, IndivId, UnitTypeId};
+use std::collections::{HashMap, HashSet};
+
+/// Steps between two looks around
+pub const SIGHT_INTERVAL: u64 = TICKS_PER_SECOND as u64 / 2;
+
+/// Sight range of unit types that don't say otherwise, in m
+pub const DEFAULT_SIGHT_RANGE: f64 = 60.0;
+
+/// Height of the eyes above the level a soldier stands on, in m
+const EYE_HEIGHT: f64 = 1.7;
+
+/// Walls and buildings block sight up to this height above the level they stand on, in m
+const OBSTACLE_HEIGHT: f64 = 6.0;
+
+/// Distance between the points checked along a line of sight, in m
+const SIGHT_STEP: f64 = 0.5;
+
+/// Where and how an enemy soldier was last seen.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Sighting {
+    pub indiv_id: IndivId,
+    pub comp_id: CompId,
+    pub player_id: PlayerId,
+    pub type_id: UnitTypeId,
+    pub pos: Position,
+    pub rot: Rad<f32>,
+    pub hp: i8,
+    pub ammo: u8,
+    /// Whether its company was running away
+    pub routing: bool,
+    /// The step it was seen at
+    pub tick: u64,
+}
+
+impl Sighting {
+    pub fn new(indiv: &Indiv, routing: bool, tick: u64) -> Sighting {
+        Sighting {
+            indiv_id: indiv.id,
+            comp_id: indiv.comp_id,
+            player_id: indiv.player_id,
+            type_id: indiv.type_id,
+            pos: indiv.pos,
+            rot: indiv.rot,
+            hp: indiv.hp,
+            ammo: indiv.ammo,
+            routing,
+            tick,
+        }
+    }
+}
+
+/// What a team knows of its enemies.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Sight {
+    /// The enemy soldiers in sight now
+    visible: HashSet<IndivId>,
+    /// The last sighting of every enemy soldier in sight now or seen before
+    sightings: HashMap<IndivId, Sighting>,
+}
+
+impl Sight {
+    pub fn can_see(&self, indiv_id: IndivId) -> bool {
+        self.visible.contains(&indiv_id)
+    }
+
+    /// The enemies in sight and the ghosts of those out of it, sorted by id.
+    pub fn sightings(&self) -> Vec<&Sighting> {
+        let mut sightings: Vec<&Sighting> = self.sightings.values().collect();
+        sightings.sort_by_key(|sighting| sighting.indiv_id);
+        sightings
+    }
+
+    /// The enemies out of sight, where they were last seen, sorted by id.
+    pub fn ghosts(&self) -> Vec<&Sighting> {
+        let mut ghosts = self.sightings();
+        ghosts.retain(|sighting| !self.can_see(sighting.indiv_id));
+        ghosts
+    }
+
+    /// Replaces what is in sight by `visible`. Ghosts are forgotten if `in_sight` says their
+    /// spot is in sight now.
+    pub fn update<F: Fn(Position) -> bool>(&mut self, visible: Vec<Sighting>, in_sight: F) {
+        self.visible = visible.iter().map(|sighting| sighting.indiv_id).collect();
+        let seen = &self.visible;
+        self.sightings
+            .retain(|indiv_id, sighting| seen.contains(indiv_id) || !in_sight(sighting.pos));
+        for sighting in visible {
+            self.sightings.insert(sighting.indiv_id, sighting);
+        }
+    }
+}
+
+/// Whether a soldier at `from` can see one at `to`, from eye to eye. The ground, walls and
+/// buildings in between may block the view, range isn't checked.
+pub fn line_of_sight(terrain: &Terrain, nav: &NavGrid, from: Position, to: Position) -> bool {
+    let eye = |pos: Position| terrain.height(pos.x, pos.y) + pos.level.height() + EYE_HEIGHT;
+    let (from_z, to_z) = (eye(from), eye(to));
+    let steps = (from.dist(&to) / SIGHT_STEP).ceil() as i32;
+    (1..steps).all(|step| {
+        let t = step as f64 / steps as f64;
+        let x = from.x + (to.x - from.x) * t;
+        let y = from.y + (to.y - from.y) * t;
+        let z = from_z + (to_z - from_z) * t;
+        let ground = terrain.height(x, y);
+        let blocked = nav
+            .obstacle_level(x, y)
+            .is_some_and(|level| z < ground + level.height() + OBSTACLE_HEIGHT);
+        z >= ground && !blocked
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::line_of_sight;
+    use core::level::Level;
+    use core::pathfinding::NavGrid;
+    use core::position::Position;
+    use core::terrain::Terrain;
+    use types::Size2;
+
+    #[test]
+    fn test_walls_and_hills_block_sight() {
+        let size = Size2 { w: 30, h: 30 };
+        let mut heights = vec![0.0; 31 * 31];
+        // A ridge 5 m high along x = 20
+        for j in 0..31 {
+            heights[j * 31 + 20] = 5.0;
+        }
+        let terrain = Terrain::from_heights(Size2 { w: 31, h: 31 }, 1.0, heights);
+        assert_eq!(terrain.size(), size);
+        let mut nav = NavGrid::new(&terrain);
+        nav.add_obstacle(
+            Level::Ground,
+            Position::new(0.0, 10.0),
+            Position::new(10.0, 11.0),
+        );
+        let see = |from: Position, to: Position| line_of_sight(&terrain, &nav, from, to);
+        assert!(see(Position::new(5.0, 5.0), Position::new(5.0, 8.0)));
+        // Through the wall, and from its top
+        assert!(!see(Position::new(5.0, 5.0), Position::new(5.0, 15.0)));
+        let walkway = Position::on_level(5.0, 10.5, Level::Walkway);
+        assert!(see(walkway, Position::new(5.0, 15.0)));
+        assert!(see(walkway, Position::new(5.0, 5.0)));
+        // Over the ridge
+        assert!(!see(Position::new(15.0, 25.0), Position::new(25.0, 25.0)));
+    }
+}
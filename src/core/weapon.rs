@@ -0,0 +1,98 @@
+use core::combat::{ATTACK_INTERVAL, MELEE_DAMAGE, MELEE_RANGE};
+use core::structure::DamageType;
+use std::str::FromStr;
+
+/// The main weapon of a unit. Everyone also carries a sidearm for melee, so this only
+/// decides whether and how a unit fights at range.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum WeaponType {
+    Melee,
+    /// Javelins like the pilum: short range, few of them, but they hit hard
+    Thrown,
+    Bow,
+    Sling,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WeaponStats {
+    /// Maximum distance to the target, in m
+    pub range: f64,
+    /// Seconds between two shots
+    pub reload_time: f64,
+    /// Between 0 and 1. A shot lands at a random spot at most `(1 - accuracy) * distance` from
+    /// where it was aimed.
+    pub accuracy: f64,
+    /// Compared against armor, see `combat::wound_chance`
+    pub damage: u8,
+    /// m/s
+    pub projectile_speed: f64,
+    /// Shots a soldier carries into battle
+    pub ammo: u8,
+}
+
+impl WeaponType {
+    pub fn stats(&self) -> WeaponStats {
+        match *self {
+            WeaponType::Melee => WeaponStats {
+                range: MELEE_RANGE,
+                reload_time: ATTACK_INTERVAL,
+                accuracy: 1.0,
+                damage: MELEE_DAMAGE,
+                projectile_speed: 0.0,
+                ammo: 0,
+            },
+            WeaponType::Thrown => WeaponStats {
+                range: 20.0,
+                reload_time: 8.0,
+                accuracy: 0.9,
+                damage: 12,
+                projectile_speed: 15.0,
+                ammo: 2,
+            },
+            WeaponType::Bow => WeaponStats {
+                range: 120.0,
+                reload_time: 6.0,
+                accuracy: 0.92,
+                damage: 6,
+                projectile_speed: 45.0,
+                ammo: 30,
+            },
+            WeaponType::Sling => WeaponStats {
+                range: 90.0,
+                reload_time: 5.0,
+                accuracy: 0.88,
+                damage: 7,
+                projectile_speed: 35.0,
+                ammo: 40,
+            },
+        }
+    }
+
+    pub fn is_ranged(&self) -> bool {
+        *self != WeaponType::Melee
+    }
+
+    /// What its shots do to structures. Melee blows are always `DamageType::Blow`.
+    pub fn damage_type(&self) -> DamageType {
+        match *self {
+            WeaponType::Melee => DamageType::Blow,
+            WeaponType::Thrown | WeaponType::Bow => DamageType::Pierce,
+            WeaponType::Sling => DamageType::Crush,
+        }
+    }
+}
+
+impl FromStr for WeaponType {
+    type Err = String;
+
+    /// Reads the name of the variant, as written in unit files.
+    fn from_str(name: &str) -> Result<WeaponType, String> {
+        match name {
+            "Melee" => Ok(WeaponType::Melee),
+            "Thrown" => Ok(WeaponType::Thrown),
+            "Bow" => Ok(WeaponType::Bow),
+            "Sling" => Ok(WeaponType::Sling),
+            _ => Err(format!("Unknown weapon type '{}'", name)),
+        }
+    }
+}
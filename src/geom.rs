@@ -2,7 +2,9 @@
 use cgmath::{Angle, Rad, Vector3};
 use std::f32::consts::PI;
 // use core::position::{ExactPos, MapPos, SlotId, get_slots_count};
+use core::level::Level;
 use core::position::Position as MapPos;
+use core::terrain::Terrain;
 use types::{VertexCoord, WorldDistance, WorldPos};
 
 pub const MIN_LIFT_HEIGHT: f32 = 0.01;
@@ -15,15 +17,13 @@ pub fn vec3_z(z: f32) -> Vector3<f32> {
     }
 }
 
-/// Still need to do checks to see if this is the correct level
+/// The level is taken from the height, so a point on top of a wall ends up on its walkway.
 pub fn world_pos_to_map_pos(pos: WorldPos) -> MapPos {
-    MapPos::new(pos.v.x, pos.v.y)
+    MapPos::on_level(pos.v.x, pos.v.y, Level::from_height(pos.v.z))
 }
 
-/// Still need to do checks to get the height from the level.
-pub fn map_pos_to_world_pos(p: MapPos) -> WorldPos {
-    let v: Vector3<f64> = Vector3::new(p.x, p.y, 0.0);
-    WorldPos { v: v }
+pub fn map_pos_to_world_pos(p: MapPos, terrain: &Terrain) -> WorldPos {
+    p.to_world_pos(terrain)
 }
 
 pub fn lift(v: Vector3<f32>) -> Vector3<f32> {
@@ -0,0 +1,15 @@
+//! The game without its window: the battle simulation in `core` and the loading of its data in
+//! `assets`. Shared by the game and by tools such as the headless simulator in `src/bin/`.
+
+extern crate bincode;
+extern crate cgmath;
+extern crate image;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+pub mod assets;
+pub mod core;
+pub mod types;
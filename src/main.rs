@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate gfx;
 
+extern crate at_the_gates;
 extern crate cgmath;
 extern crate collision;
 extern crate gfx_device_gl as gfx_gl;
@@ -12,7 +13,6 @@ extern crate rusttype;
 
 mod camera;
 mod context;
-mod core;
 mod fs;
 mod gen;
 mod geom;
@@ -22,44 +22,123 @@ mod obj;
 mod pipeline;
 mod scene;
 mod texture;
-mod types;
 mod ui;
 mod visualizer;
 
+use at_the_gates::{assets, core, types};
+use core::battlefield::TICKS_PER_SECOND;
+use core::deployment::Deployment;
+use core::event::BattleEvent;
+use core::replay::{Playback, Replay};
+use core::veterancy::VeteranCompany;
+use std::mem;
 use std::sync::mpsc::{channel, Receiver};
+use ui::screen::PlaybackCommand;
 use visualizer::Visualizer;
 
+/// Where the battle is saved to and loaded from
+const QUICKSAVE: &str = "saves/quicksave.sav";
+
+/// Where the replay of the last battle played is kept
+const LAST_REPLAY: &str = "replays/last.rep";
+
+/// Seconds skipped by the skip buttons of a replay
+const REPLAY_SKIP: u64 = 10;
+
 pub fn main() {
     std::env::set_var("RUST_BACKTRACE", "1");
     let mut visualizer = Visualizer::new();
     let mut game_state = GameState::Menu;
+    let mut recording = None;
+    let mut veterans = Vec::new();
     let (tx, rx) = channel();
     while visualizer.is_running() {
-        visualizer.tick(&game_state, &tx);
+        let dtime = visualizer.tick(&game_state, &tx);
 
-        /* match game_state {
-            GameState::Battle(battlefield) => battlefield.tick(),
-            _ => (),
-        }*/
+        let mut company_destroyed = false;
+        match game_state {
+            GameState::Battle(ref mut battlefield) => {
+                let events = battlefield.tick(dtime);
+                visualizer.handle_battle_events(&events);
+                let mut changed = Vec::new();
+                for event in &events {
+                    match *event {
+                        BattleEvent::IndivDied { comp_id, .. }
+                            if battlefield.get_company(&comp_id).is_none() =>
+                        {
+                            company_destroyed = true
+                        }
+                        BattleEvent::IndivDied { comp_id, .. }
+                        | BattleEvent::CompanyPromoted { comp_id, .. } => changed.push(comp_id),
+                        _ => {}
+                    }
+                }
+                changed.sort();
+                changed.dedup();
+                for comp_id in changed {
+                    visualizer.update_company_card(battlefield, comp_id);
+                }
+                if let Some(outcome) = battlefield.outcome() {
+                    let aftermath = GameState::Aftermath(outcome.clone());
+                    assert!(tx.send(GameCommand::ChangeState(aftermath)).is_ok());
+                }
+            }
+            GameState::Replay(ref mut playback) => {
+                let events = playback.tick(dtime);
+                visualizer.handle_battle_events(&events);
+            }
+            _ => {}
+        }
 
-        process_commands(&mut game_state, &rx, &mut visualizer);
+        if company_destroyed {
+            // Its card has to go, and the ones below it move up
+            visualizer.new_gui(&game_state);
+        }
+
+        process_commands(
+            &mut game_state,
+            &rx,
+            &mut visualizer,
+            &mut recording,
+            &mut veterans,
+        );
     }
+    stop_recording(&mut recording, &game_state);
 }
 
 #[derive(Debug)]
 pub enum GameState {
     Menu,
+    /// The players place their companies before the battle
+    Deployment(Deployment),
     Battle(core::battlefield::Battlefield),
+    /// The battle is over
+    Aftermath(core::victory::BattleOutcome),
+    Replay(Playback),
 }
 
 pub enum GameCommand {
     ChangeState(GameState),
+    /// Saves the battle going on to `QUICKSAVE`
+    SaveBattle,
+    /// Replaces the battle going on, if any, by the one in `QUICKSAVE`
+    LoadBattle,
+    /// Plays back the replay in `LAST_REPLAY`
+    WatchReplay,
+    ControlPlayback(PlaybackCommand),
+    /// The ground was clicked, see `Deployment::click`
+    MapClick(core::position::Position),
+    AutoDeploy,
+    /// The player deploying is done. Once everyone is, the battle starts.
+    DeploymentReady,
 }
 
 fn process_commands(
     game_state: &mut GameState,
     rx: &Receiver<GameCommand>,
     visualizer: &mut Visualizer,
+    recording: &mut Option<Replay>,
+    veterans: &mut Vec<VeteranCompany>,
 ) {
     while let Ok(command) = rx.try_recv() {
         match command {
@@ -67,19 +146,154 @@ fn process_commands(
                 GameState::Menu => {
                     if let GameState::Menu = game_state {
                     } else {
+                        stop_recording(recording, game_state);
                         *game_state = state;
                         visualizer.new_gui(game_state);
                     }
                 }
-                GameState::Battle(battlefield) => {
+                GameState::Aftermath(ref outcome) => {
+                    *veterans = outcome.veterans.clone();
+                    stop_recording(recording, game_state);
+                    *game_state = state;
+                    visualizer.new_gui(game_state);
+                }
+                GameState::Deployment(deployment) => {
+                    stop_recording(recording, game_state);
+                    visualizer.new_scene(deployment.battlefield());
+                    *game_state = GameState::Deployment(deployment);
+                    visualizer.new_gui(game_state);
+                }
+                GameState::Battle(mut battlefield) => {
                     if let GameState::Battle(_) = game_state {
                     } else {
+                        battlefield.enlist_veterans(veterans);
                         visualizer.new_scene(&battlefield);
+                        *recording = Some(Replay::new(&battlefield));
                         *game_state = GameState::Battle(battlefield);
                         visualizer.new_gui(game_state);
                     }
                 }
+                GameState::Replay(playback) => {
+                    stop_recording(recording, game_state);
+                    visualizer.new_scene(playback.battlefield());
+                    *game_state = GameState::Replay(playback);
+                    visualizer.new_gui(game_state);
+                }
+            },
+            GameCommand::SaveBattle => {
+                if let GameState::Battle(ref battlefield) = *game_state {
+                    if let Err(err) = fs::save_battle(QUICKSAVE, battlefield) {
+                        println!("Can`t save battle: {}", err);
+                    }
+                }
+            }
+            GameCommand::LoadBattle => match fs::load_battle(QUICKSAVE) {
+                Ok(battlefield) => {
+                    stop_recording(recording, game_state);
+                    visualizer.new_scene(&battlefield);
+                    *recording = Some(Replay::new(&battlefield));
+                    *game_state = GameState::Battle(battlefield);
+                    visualizer.new_gui(game_state);
+                }
+                Err(err) => println!("Can`t load battle: {}", err),
+            },
+            GameCommand::WatchReplay => match fs::load_replay(LAST_REPLAY) {
+                Ok(replay) => {
+                    let playback = Playback::new(replay);
+                    stop_recording(recording, game_state);
+                    visualizer.new_scene(playback.battlefield());
+                    *game_state = GameState::Replay(playback);
+                    visualizer.new_gui(game_state);
+                }
+                Err(err) => println!("Can`t load replay: {}", err),
             },
+            GameCommand::ControlPlayback(command) => {
+                if let GameState::Replay(ref mut playback) = *game_state {
+                    control_playback(playback, command, visualizer);
+                }
+            }
+            GameCommand::MapClick(pos) => {
+                if let GameState::Deployment(ref mut deployment) = *game_state {
+                    if let Err(err) = deployment.click(pos) {
+                        println!("Can`t deploy company: {}", err);
+                    }
+                }
+            }
+            GameCommand::AutoDeploy => {
+                if let GameState::Deployment(ref mut deployment) = *game_state {
+                    if let Err(err) = deployment.auto_deploy() {
+                        println!("Can`t deploy all companies: {}", err);
+                    }
+                }
+            }
+            GameCommand::DeploymentReady => {
+                let everyone_ready = match *game_state {
+                    GameState::Deployment(ref mut deployment) => match deployment.ready() {
+                        Ok(everyone_ready) => everyone_ready,
+                        Err(err) => {
+                            println!("Can`t start the battle: {}", err);
+                            continue;
+                        }
+                    },
+                    _ => continue,
+                };
+                if everyone_ready {
+                    if let GameState::Deployment(deployment) =
+                        mem::replace(game_state, GameState::Menu)
+                    {
+                        // The scene stays, the soldiers are where they were deployed
+                        let mut battlefield = deployment.into_battlefield();
+                        battlefield.enlist_veterans(veterans);
+                        *recording = Some(Replay::new(&battlefield));
+                        *game_state = GameState::Battle(battlefield);
+                    }
+                }
+                // The next player deploys, or the battle starts
+                visualizer.new_gui(game_state);
+            }
+        }
+    }
+}
+
+/// Saves the replay of the battle being recorded, if any, to `LAST_REPLAY`. Call it before the
+/// battle is left.
+fn stop_recording(recording: &mut Option<Replay>, game_state: &GameState) {
+    if let (Some(mut replay), &GameState::Battle(ref battlefield)) = (recording.take(), game_state)
+    {
+        replay.record(battlefield);
+        if let Err(err) = fs::save_replay(LAST_REPLAY, &replay) {
+            println!("Can`t save replay: {}", err);
+        }
+    }
+}
+
+fn control_playback(
+    playback: &mut Playback,
+    command: PlaybackCommand,
+    visualizer: &mut Visualizer,
+) {
+    let skip = REPLAY_SKIP * TICKS_PER_SECOND as u64;
+    match command {
+        PlaybackCommand::TogglePause => {
+            let paused = playback.is_paused();
+            playback.set_paused(!paused);
+        }
+        PlaybackCommand::SlowDown => {
+            let speed = playback.speed() / 2.0;
+            playback.set_speed(speed);
+        }
+        PlaybackCommand::SpeedUp => {
+            let speed = playback.speed() * 2.0;
+            playback.set_speed(speed);
+        }
+        PlaybackCommand::SkipBack => {
+            let position = playback.position().saturating_sub(skip);
+            playback.seek(position);
+        }
+        PlaybackCommand::SkipForward => {
+            let position = playback.position() + skip;
+            playback.seek(position);
         }
     }
+    visualizer.sync_scene(playback.battlefield());
 }
@@ -1,10 +1,12 @@
 use context::Context;
+use core::structure::{Structure, StructureKind, StructureState};
+use core::terrain::Terrain;
 use gfx;
 use gfx::traits::FactoryExt;
 use gfx_gl;
 use pipeline::Vertex;
 use texture::{create_flat_texture, Texture};
-use types::{Size2, WorldPos};
+use types::Size2;
 
 #[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub struct MeshId {
@@ -30,30 +32,120 @@ impl Mesh {
         }
     }
 
-    pub fn plane(context: &mut Context, bottom_left: WorldPos, top_right: WorldPos) -> Mesh {
-        let bottom_left = bottom_left.v32();
-        let top_right = top_right.v32();
-        // Move through vertices counterclockwise
-        let v1 = Vertex {
-            pos: [bottom_left.x, bottom_left.y, bottom_left.z],
-            uv: [0.0, 0.0],
-        };
-        let v2 = Vertex {
-            pos: [top_right.x, bottom_left.y, bottom_left.z],
-            uv: [0.0, 0.0],
-        };
-        let v3 = Vertex {
-            pos: [top_right.x, top_right.y, top_right.z],
-            uv: [0.0, 0.0],
-        };
-        let v4 = Vertex {
-            pos: [bottom_left.x, top_right.y, top_right.z],
-            uv: [0.0, 0.0],
-        };
-        let vertices = [v1, v2, v3, v4];
+    /// The ground of `terrain`, one vertex per height sample, with `tex` stretched over it.
+    /// Heightmaps can have more samples than a u16 can count, so it has u32 indices.
+    pub fn terrain(context: &mut Context, terrain: &Terrain, tex: Texture) -> Mesh {
+        let samples = terrain.samples();
+        let mut vertices = Vec::new();
+        for j in 0..samples.h {
+            for i in 0..samples.w {
+                vertices.push(Vertex {
+                    pos: [
+                        (i as f64 * terrain.spacing()) as f32,
+                        (j as f64 * terrain.spacing()) as f32,
+                        terrain.sample(i, j) as f32,
+                    ],
+                    uv: [
+                        i as f32 / (samples.w - 1) as f32,
+                        j as f32 / (samples.h - 1) as f32,
+                    ],
+                });
+            }
+        }
+        // Two triangles per cell, counterclockwise
+        let mut indices = Vec::new();
+        for j in 0..(samples.h - 1) {
+            for i in 0..(samples.w - 1) {
+                let v1 = (j * samples.w + i) as u32;
+                let v2 = v1 + 1;
+                let v3 = v2 + samples.w as u32;
+                let v4 = v1 + samples.w as u32;
+                indices.extend_from_slice(&[v1, v2, v3, v3, v1, v4]);
+            }
+        }
+        let (v, s) = context
+            .factory_mut()
+            .create_vertex_buffer_with_slice(&vertices, &indices[..]);
+        Mesh {
+            slice: s,
+            vertex_buffer: v,
+            texture: tex,
+        }
+    }
+
+    /// A thin stick pointing along +y, for javelins, arrows and stones in flight.
+    pub fn projectile(context: &mut Context) -> Mesh {
+        let w = 0.05;
+        let l = 0.5;
+        let vertices = [
+            Vertex {
+                pos: [-w, -l, 0.0],
+                uv: [0.0, 0.0],
+            },
+            Vertex {
+                pos: [w, -l, 0.0],
+                uv: [0.0, 0.0],
+            },
+            Vertex {
+                pos: [w, l, 0.0],
+                uv: [0.0, 0.0],
+            },
+            Vertex {
+                pos: [-w, l, 0.0],
+                uv: [0.0, 0.0],
+            },
+        ];
         let indices: [u16; 6] = [0, 1, 2, 2, 0, 3];
-        let texture = create_flat_texture(context, Size2 { w: 4, h: 4 }, [255, 120, 20, 180]);
+        let texture = create_flat_texture(context, Size2 { w: 4, h: 4 }, [40, 30, 20, 255]);
+
+        Mesh::new(context, &vertices, &indices, texture)
+    }
 
+    /// `structure` in `state`, in world coordinates. Intact it is a block as high as the
+    /// structure, damaged its top is broken off unevenly, and breached only a heap of rubble is
+    /// left. Gates are wood, the rest is stone.
+    pub fn structure(
+        context: &mut Context,
+        structure: &Structure,
+        state: StructureState,
+        terrain: &Terrain,
+    ) -> Mesh {
+        let (min, max) = (structure.min, structure.max);
+        let centre = structure.centre();
+        // Sunk into the ground a bit, so it doesn't float on slopes
+        let base = terrain.height(centre.x, centre.y) - 0.5;
+        let top = structure.kind.stats().height + 0.5;
+        // Fractions of the full height, of blocks side by side along the long side
+        let heights: &[f64] = match state {
+            StructureState::Intact => &[1.0],
+            StructureState::Damaged => &[1.0, 0.6, 0.85, 0.45],
+            StructureState::Breached => &[0.25, 0.1, 0.2],
+        };
+        let along_x = max.x - min.x >= max.y - min.y;
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for (i, &height) in heights.iter().enumerate() {
+            let t0 = i as f64 / heights.len() as f64;
+            let t1 = (i + 1) as f64 / heights.len() as f64;
+            let (x0, x1, y0, y1) = if along_x {
+                let w = max.x - min.x;
+                (min.x + w * t0, min.x + w * t1, min.y, max.y)
+            } else {
+                let h = max.y - min.y;
+                (min.x, max.x, min.y + h * t0, min.y + h * t1)
+            };
+            add_block(
+                &mut vertices,
+                &mut indices,
+                [x0, y0, base],
+                [x1, y1, base + top * height],
+            );
+        }
+        let colour = match structure.kind {
+            StructureKind::Gate => [110, 80, 50, 255],
+            StructureKind::WallSegment | StructureKind::Tower => [150, 145, 135, 255],
+        };
+        let texture = create_flat_texture(context, Size2 { w: 4, h: 4 }, colour);
         Mesh::new(context, &vertices, &indices, texture)
     }
 
@@ -69,3 +161,27 @@ impl Mesh {
         &self.texture
     }
 }
+
+/// Adds the top and the four sides of the box from `min` to `max`, counterclockwise seen from
+/// outside.
+fn add_block(vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>, min: [f64; 3], max: [f64; 3]) {
+    let ([x0, y0, z0], [x1, y1, z1]) = (min, max);
+    let faces = [
+        [[x0, y0, z1], [x1, y0, z1], [x1, y1, z1], [x0, y1, z1]],
+        [[x0, y0, z0], [x1, y0, z0], [x1, y0, z1], [x0, y0, z1]],
+        [[x1, y0, z0], [x1, y1, z0], [x1, y1, z1], [x1, y0, z1]],
+        [[x1, y1, z0], [x0, y1, z0], [x0, y1, z1], [x1, y1, z1]],
+        [[x0, y1, z0], [x0, y0, z0], [x0, y0, z1], [x0, y1, z1]],
+    ];
+    let uvs = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+    for face in &faces {
+        let first = vertices.len() as u16;
+        for (corner, &uv) in face.iter().zip(&uvs) {
+            vertices.push(Vertex {
+                pos: [corner[0] as f32, corner[1] as f32, corner[2] as f32],
+                uv,
+            });
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first, first + 3]);
+    }
+}
@@ -2,8 +2,13 @@ use camera::Camera;
 use cgmath::{self, Rad, Vector2, Vector3};
 use context::Context;
 use core::battlefield::Battlefield;
+use core::event::BattleEvent;
+use core::player::PlayerId;
 use core::position::Position as MapPos;
+use core::structure::{StructureId, StructureState};
+use core::terrain::Terrain;
 use core::unit::IndivId;
+use core::visibility::Sighting;
 use fs;
 use geom;
 use glutin::{
@@ -14,11 +19,19 @@ use mesh_manager::MeshManager;
 use std::cmp::{Ord, Ordering};
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::f32::consts::PI;
+use texture::load_texture;
 use types::{ScreenPos, Size2, WorldPos};
 use ui::screen::{EventStatus, ScreenCommand, ScreenType};
 
 const ZOOM_LEVEL: f32 = 0.3;
 
+/// Opacity of the enemies drawn where they were last seen
+const GHOST_ALPHA: f32 = 0.4;
+
+/// How far, in pixels, the mouse may move between press and release for it to be a click
+/// rather than a drag of the camera
+const CLICK_TOLERANCE: i32 = 4;
+
 #[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub struct NodeId {
     pub id: i32,
@@ -48,12 +61,18 @@ impl Ord for Z {
 #[derive(Clone, Debug)]
 pub struct Scene {
     indiv_id_to_node_id_map: HashMap<IndivId, NodeId>,
+    /// The enemies out of sight, by the soldier they stand for
+    ghost_id_to_node_id_map: HashMap<IndivId, NodeId>,
     //sector_id_to_node_id_map: HashMap<SectorId, NodeId>,    // probably not neccesary
     //object_id_to_node_id_map: HashMap<ObjectId, HashSet<NodeId>>,   // possibly not neccesary
     nodes: HashMap<NodeId, SceneNode>,
     transparent_node_ids: BTreeMap<Z, HashSet<NodeId>>,
     next_id: NodeId,
     unit_meshes: MeshManager,
+    projectile_mesh: Mesh,
+    terrain_mesh: Mesh,
+    /// A mesh for every structure in every state it can get in
+    structure_meshes: HashMap<(StructureId, StructureState), Mesh>,
     camera: Camera,
 }
 
@@ -61,68 +80,189 @@ impl Scene {
     pub fn new(context: &mut Context, battlefield: &Battlefield) -> Scene {
         let (unit_meshes, _) = fs::load_all_units(context);
         let mut camera = Camera::new(context.win_size());
-        camera.set_max_pos(get_max_camera_pos(battlefield.map_size));
-        camera.set_pos(get_initial_camera_pos(battlefield.map_size));
+        let terrain = battlefield.terrain();
+        camera.set_max_pos(get_max_camera_pos(battlefield.map_size, terrain));
+        camera.set_pos(get_initial_camera_pos(battlefield.map_size, terrain));
+        let floor = load_texture(context, &fs::load("floor.png").into_inner());
+        let mut structure_meshes = HashMap::new();
+        for (&structure_id, structure) in battlefield.get_structure_iter() {
+            let states = [
+                StructureState::Intact,
+                StructureState::Damaged,
+                StructureState::Breached,
+            ];
+            for &state in &states {
+                let mesh = Mesh::structure(context, structure, state, terrain);
+                structure_meshes.insert((structure_id, state), mesh);
+            }
+        }
         Scene {
             indiv_id_to_node_id_map: HashMap::new(), /* 
             sector_id_to_node_id_map: HashMap::new(),
             object_id_to_node_id_map: HashMap::new(), */
+            ghost_id_to_node_id_map: HashMap::new(),
             nodes: HashMap::new(),
             transparent_node_ids: BTreeMap::new(),
             next_id: NodeId { id: 0 },
             unit_meshes,
+            projectile_mesh: Mesh::projectile(context),
+            terrain_mesh: Mesh::terrain(context, terrain, floor),
+            structure_meshes,
             camera,
         }
     }
 
-    pub fn draw(&mut self, context: &mut Context, battlefield: &Battlefield) {
+    /// Draws the battle as `viewer` sees it: enemies out of its sight are left out, or shown
+    /// where it saw them last. Without a viewer everyone is drawn.
+    pub fn draw(
+        &mut self,
+        context: &mut Context,
+        battlefield: &Battlefield,
+        viewer: Option<PlayerId>,
+    ) {
         // Update all nodes of indivs
         for (indiv_id, indiv) in battlefield.get_indiv_iter() {
+            let seen = viewer.map_or(true, |viewer| battlefield.can_see(viewer, *indiv_id));
+            if !seen {
+                if self.indiv_id_to_node_id_map.contains_key(indiv_id) {
+                    self.remove_indiv(*indiv_id);
+                }
+                continue;
+            }
             if self.indiv_id_to_node_id_map.contains_key(indiv_id) {
                 let node_id = self.indiv_id_to_node_id(*indiv_id);
                 let node = self.node_mut(node_id);
-                node.pos = indiv.pos.to_world_pos();
+                node.pos = indiv.pos.to_world_pos(battlefield.terrain());
                 node.rot = indiv.rot;
             } else {
                 let node = SceneNode {
-                    pos: indiv.pos.to_world_pos(),
+                    pos: indiv.pos.to_world_pos(battlefield.terrain()),
                     rot: indiv.rot,
                     mesh_id: Some(MeshId {
                         id: indiv.type_id.id,
                     }),
-                    color: [1.0, 1.0, 1.0, 1.0],
+                    color: battlefield
+                        .get_player(&indiv.player_id)
+                        .expect("Bad player id")
+                        .colour,
                     children: vec![],
                 };
                 self.add_indiv(*indiv_id, node);
             }
         }
-        self.draw_statics(context, battlefield);
+        self.update_ghosts(battlefield, viewer);
+        self.draw_statics(context);
+        self.draw_structures(context, battlefield);
         self.draw_scene_nodes(context);
+        self.draw_projectiles(context, battlefield, viewer);
+    }
+
+    /// Adds a faded soldier for every enemy `viewer` knows of but can't see, and removes
+    /// those it doesn't have to remember any more.
+    fn update_ghosts(&mut self, battlefield: &Battlefield, viewer: Option<PlayerId>) {
+        let ghosts: Vec<Sighting> = viewer
+            .and_then(|viewer| battlefield.get_player(&viewer))
+            .and_then(|player| battlefield.sight(player.team))
+            .map_or(Vec::new(), |sight| {
+                sight.ghosts().into_iter().cloned().collect()
+            });
+        let ghost_ids: HashSet<IndivId> = ghosts.iter().map(|ghost| ghost.indiv_id).collect();
+        let gone: Vec<IndivId> = self
+            .ghost_id_to_node_id_map
+            .keys()
+            .filter(|indiv_id| !ghost_ids.contains(indiv_id))
+            .cloned()
+            .collect();
+        for indiv_id in gone {
+            let node_id = self.ghost_id_to_node_id_map.remove(&indiv_id).unwrap();
+            self.remove_node(node_id);
+        }
+        for ghost in ghosts {
+            if self.ghost_id_to_node_id_map.contains_key(&ghost.indiv_id) {
+                continue;
+            }
+            let mut color = battlefield
+                .get_player(&ghost.player_id)
+                .expect("Bad player id")
+                .colour;
+            color[3] = GHOST_ALPHA;
+            let node_id = self.add_node(SceneNode {
+                pos: ghost.pos.to_world_pos(battlefield.terrain()),
+                rot: ghost.rot,
+                mesh_id: Some(MeshId {
+                    id: ghost.type_id.id,
+                }),
+                color,
+                children: vec![],
+            });
+            self.ghost_id_to_node_id_map.insert(ghost.indiv_id, node_id);
+        }
+    }
+
+    pub fn handle_battle_events(&mut self, events: &[BattleEvent]) {
+        for event in events {
+            if let BattleEvent::IndivDied { indiv_id, .. } = *event {
+                // Soldiers can die before they were ever drawn
+                if self.indiv_id_to_node_id_map.contains_key(&indiv_id) {
+                    self.remove_indiv(indiv_id);
+                }
+            }
+        }
+    }
+
+    /// Removes the soldiers that aren't on `battlefield` (anymore). Those that are missing
+    /// are added by `draw`.
+    pub fn remove_missing_indivs(&mut self, battlefield: &Battlefield) {
+        let missing: Vec<IndivId> = self
+            .indiv_id_to_node_id_map
+            .keys()
+            .filter(|indiv_id| battlefield.get_indiv(indiv_id).is_none())
+            .cloned()
+            .collect();
+        for indiv_id in missing {
+            self.remove_indiv(indiv_id);
+        }
     }
 
-    fn draw_statics(&self, context: &mut Context, battlefield: &Battlefield) {
+    /// Draws the projectiles `viewer` can see, see `Battlefield::can_see_projectile`.
+    fn draw_projectiles(
+        &self,
+        context: &mut Context,
+        battlefield: &Battlefield,
+        viewer: Option<PlayerId>,
+    ) {
         let m = self.camera.mat();
+        context.set_basic_color([1.0, 1.0, 1.0, 1.0]);
+        for (_, projectile) in battlefield.get_projectile_iter() {
+            let seen = viewer.map_or(true, |viewer| {
+                battlefield.can_see_projectile(viewer, projectile)
+            });
+            if !seen {
+                continue;
+            }
+            let world_pos = projectile.to_world_pos(battlefield.terrain());
+            let tr_mat = cgmath::Matrix4::from_translation(world_pos.v32());
+            let rot_mat = cgmath::Matrix4::from(cgmath::Matrix3::from_angle_z(projectile.rot()));
+            context.set_mvp(m * tr_mat * rot_mat);
+            context.draw_mesh(&self.projectile_mesh);
+        }
+    }
 
-        let mesh = Mesh::plane(
-            context,
-            WorldPos {
-                v: Vector3 {
-                    x: 0.0,
-                    y: 0.0,
-                    z: 0.0,
-                },
-            },
-            WorldPos {
-                v: Vector3 {
-                    x: battlefield.map_size.w as f64,
-                    y: battlefield.map_size.h as f64,
-                    z: 0.0,
-                },
-            },
-        );
+    fn draw_statics(&self, context: &mut Context) {
+        let m = self.camera.mat();
         context.set_mvp(m);
         context.set_basic_color([1.0, 1.0, 1.0, 1.0]);
-        context.draw_mesh(&mesh);
+        context.draw_mesh(&self.terrain_mesh);
+    }
+
+    /// Draws every structure with the mesh of the state it is in now.
+    fn draw_structures(&self, context: &mut Context, battlefield: &Battlefield) {
+        context.set_mvp(self.camera.mat());
+        context.set_basic_color([1.0, 1.0, 1.0, 1.0]);
+        for (&structure_id, structure) in battlefield.get_structure_iter() {
+            let mesh = &self.structure_meshes[&(structure_id, structure.state())];
+            context.draw_mesh(mesh);
+        }
     }
 
     fn draw_scene_nodes(&self, context: &mut Context) {
@@ -195,7 +335,6 @@ impl Scene {
         node_id
     }
 
-    #[allow(dead_code)]
     pub fn remove_indiv(&mut self, indiv_id: IndivId) {
         assert!(self.indiv_id_to_node_id_map.contains_key(&indiv_id));
         let node_id = self.indiv_id_to_node_id(indiv_id);
@@ -402,19 +541,22 @@ impl Scene {
         }
     }
 
-    fn handle_event_lmb_release(&mut self, _context: &mut Context) {
-        /*
-        let pick_result = self.pick_tile(context);
-        if let Some(button_id) = self.gui.button_manager.get_clicked_button_id(context) {
-            self.handle_event_button_press(context, button_id);
-        } else if let Some(pick_result) = pick_result {
-            self.try_create_context_menu_popup(context, pick_result);
-        }*/
+    fn handle_event_lmb_release(&mut self, context: &mut Context) {
+        let drag = context.mouse().pos.v - context.mouse().last_press_pos.v;
+        if drag.x.abs() > CLICK_TOLERANCE || drag.y.abs() > CLICK_TOLERANCE {
+            return;
+        }
+        if let Some(pos) = self
+            .camera
+            .ground_pos(context.mouse().pos, context.win_size())
+        {
+            context.add_command(ScreenCommand::MapClick(geom::world_pos_to_map_pos(pos)));
+        }
     }
 }
 
-fn get_initial_camera_pos(map_size: Size2) -> WorldPos {
-    let pos = get_max_camera_pos(map_size);
+fn get_initial_camera_pos(map_size: Size2, terrain: &Terrain) -> WorldPos {
+    let pos = get_max_camera_pos(map_size, terrain);
     WorldPos {
         v: Vector3 {
             x: pos.v.x / 2.0,
@@ -424,9 +566,9 @@ fn get_initial_camera_pos(map_size: Size2) -> WorldPos {
     }
 }
 
-fn get_max_camera_pos(map_size: Size2) -> WorldPos {
+fn get_max_camera_pos(map_size: Size2, terrain: &Terrain) -> WorldPos {
     let map_pos = MapPos::new(map_size.w as f64, (map_size.h - 1) as f64);
-    let pos = geom::map_pos_to_world_pos(map_pos);
+    let pos = geom::map_pos_to_world_pos(map_pos, terrain);
     WorldPos {
         v: Vector3 {
             x: -pos.v.x,
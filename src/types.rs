@@ -40,7 +40,7 @@ pub struct WorldDistance {
     pub n: f32,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Size2 {
     pub w: i32,
     pub h: i32,
@@ -1,6 +1,12 @@
 use context::Context;
 use core::battlefield::Battlefield;
+use core::company::CompId;
+use core::deployment::Deployment;
+use core::event::BattleEvent;
+use core::player::PlayerId;
+use fs;
 use glutin::Event;
+use rand;
 use scene::Scene;
 use std::fs::metadata;
 use std::sync::mpsc::{channel, Receiver, Sender};
@@ -8,9 +14,16 @@ use std::{process, thread, time};
 use types::Time;
 use ui::gui::Gui;
 use ui::screen::{EventStatus, Screen, ScreenCommand, ScreenType};
+use ui::tactical_screen;
 use GameCommand;
 use GameState;
 
+/// The battle started from the main menu.
+const SCENARIO: &str = "scenarios/skirmish.json";
+
+/// The battle against the computer started from the main menu.
+const AI_SCENARIO: &str = "scenarios/gate_assault.json";
+
 fn check_assets_dir() {
     if let Err(e) = metadata("assets") {
         println!("Can`t find 'assets' dir: {}", e);
@@ -51,15 +64,36 @@ impl Visualizer {
         self.gui = Gui::new(&mut self.context, gamestate);
     }
 
+    /// Brings the card of `comp_id` up to date, without rebuilding the rest of the GUI.
+    pub fn update_company_card(&mut self, battlefield: &Battlefield, comp_id: CompId) {
+        let label = tactical_screen::company_card(battlefield, comp_id);
+        self.gui.update_card(&mut self.context, comp_id, &label);
+    }
+
     pub fn new_scene(&mut self, battlefield: &Battlefield) {
         self.scene = Option::Some(Scene::new(&mut self.context, battlefield));
     }
 
-    pub fn tick(&mut self, gamestate: &GameState, tx: &Sender<GameCommand>) {
+    /// Lets the scene know what happened on the battlefield since the last frame.
+    pub fn handle_battle_events(&mut self, events: &[BattleEvent]) {
+        if let Some(ref mut scene) = self.scene {
+            scene.handle_battle_events(events);
+        }
+    }
+
+    /// Brings the scene in line with a battlefield that jumped in time.
+    pub fn sync_scene(&mut self, battlefield: &Battlefield) {
+        if let Some(ref mut scene) = self.scene {
+            scene.remove_missing_indivs(battlefield);
+        }
+    }
+
+    /// Draws a frame and handles input. Returns the time passed since the previous frame.
+    pub fn tick(&mut self, gamestate: &GameState, tx: &Sender<GameCommand>) -> Time {
         let max_fps = 60;
         let max_frame_time = time::Duration::from_millis(1000 / max_fps);
         let start_frame_time = time::Instant::now();
-        let _dtime = self.update_time();
+        let dtime = self.update_time();
         self.draw(gamestate);
         self.handle_events();
         self.handle_commands(tx);
@@ -67,13 +101,27 @@ impl Visualizer {
         if max_frame_time > delta_time {
             thread::sleep(max_frame_time - delta_time);
         }
+        dtime
     }
 
     fn draw(&mut self, gamestate: &GameState) {
         self.context.clear();
         match *gamestate {
             GameState::Battle(ref battlefield) => match self.scene {
-                Some(ref mut s) => s.draw(&mut self.context, &battlefield),
+                Some(ref mut s) => s.draw(&mut self.context, &battlefield, viewer(battlefield)),
+                None => panic!("No Scene!"),
+            },
+            GameState::Deployment(ref deployment) => match self.scene {
+                Some(ref mut s) => {
+                    let battlefield = deployment.battlefield();
+                    let viewer = deployment.player().or_else(|| viewer(battlefield));
+                    s.draw(&mut self.context, battlefield, viewer)
+                }
+                None => panic!("No Scene!"),
+            },
+            // Replays are watched from above the fog
+            GameState::Replay(ref playback) => match self.scene {
+                Some(ref mut s) => s.draw(&mut self.context, playback.battlefield(), None),
                 None => panic!("No Scene!"),
             },
             _ => {}
@@ -126,13 +174,8 @@ impl Visualizer {
                     ScreenType::Menu => {
                         assert!(tx.send(GameCommand::ChangeState(GameState::Menu)).is_ok());
                     }
-                    ScreenType::Battle => {
-                        assert!(
-                            tx.send(GameCommand::ChangeState(GameState::Battle(
-                                Battlefield::new(),
-                            ))).is_ok()
-                        );
-                    }
+                    ScreenType::Battle => start_scenario(SCENARIO, tx),
+                    ScreenType::BattleVsAi => start_scenario(AI_SCENARIO, tx),
                 },
                 ScreenCommand::PushPopup(popup) => {
                     self.popups.push(popup);
@@ -141,6 +184,27 @@ impl Visualizer {
                     assert!(self.popups.len() > 0);
                     let _ = self.popups.pop();
                 }
+                ScreenCommand::SaveBattle => {
+                    assert!(tx.send(GameCommand::SaveBattle).is_ok());
+                }
+                ScreenCommand::LoadBattle => {
+                    assert!(tx.send(GameCommand::LoadBattle).is_ok());
+                }
+                ScreenCommand::WatchReplay => {
+                    assert!(tx.send(GameCommand::WatchReplay).is_ok());
+                }
+                ScreenCommand::ControlPlayback(command) => {
+                    assert!(tx.send(GameCommand::ControlPlayback(command)).is_ok());
+                }
+                ScreenCommand::MapClick(pos) => {
+                    assert!(tx.send(GameCommand::MapClick(pos)).is_ok());
+                }
+                ScreenCommand::AutoDeploy => {
+                    assert!(tx.send(GameCommand::AutoDeploy).is_ok());
+                }
+                ScreenCommand::DeploymentReady => {
+                    assert!(tx.send(GameCommand::DeploymentReady).is_ok());
+                }
             }
         }
     }
@@ -158,3 +222,34 @@ impl Visualizer {
         dtime
     }
 }
+
+/// Loads the scenario at `path` and lets the players deploy.
+fn start_scenario(path: &str, tx: &Sender<GameCommand>) {
+    let battlefield = fs::load_all_unit_types()
+        .and_then(|unit_types| fs::load_scenario(path, unit_types, rand::random()));
+    match battlefield {
+        Ok(battlefield) => {
+            let deployment = Deployment::new(battlefield);
+            for &(player_id, err) in deployment.ai_errors() {
+                let player = deployment
+                    .battlefield()
+                    .get_player(&player_id)
+                    .expect("Bad player id");
+                println!("Can`t deploy all companies of {}: {}", player.name, err);
+            }
+            let deployment = GameState::Deployment(deployment);
+            assert!(tx.send(GameCommand::ChangeState(deployment)).is_ok());
+        }
+        Err(err) => println!("Can`t start battle: {}", err),
+    }
+}
+
+/// The player whose eyes the battle is seen through: the first human one. `None` if the
+/// computer plays all sides, then everyone is drawn.
+fn viewer(battlefield: &Battlefield) -> Option<PlayerId> {
+    battlefield
+        .get_player_iter()
+        .filter(|&(_, player)| player.is_human())
+        .map(|(&id, _)| id)
+        .min()
+}